@@ -1,6 +1,10 @@
 
 //! A more advanced pen suitable for diagrams. 
 
+use graphics::dash::Dash;
+use graphics::simple_pen::Pen;
+use graphics::scalar::Scalar;
+
 /// Describes how an end of a line should look like.
 pub enum LineCap<Fl=f64> {
     /// Round edge.
@@ -39,5 +43,38 @@ pub struct AdvancedPen<Fl=f64> {
     pub line_cap_end: LineCap<Fl>,
     /// Line join.
     pub line_join: LineJoin<Fl>,
+    /// Dash pattern, or `None` for a solid line.
+    pub dash: Option<Dash<Fl>>,
+}
+
+impl<Fl: Scalar> AdvancedPen<Fl> {
+    /// Creates an advanced pen drawing like a simple pen.
+    pub fn from_pen(pen: &Pen<Fl>) -> AdvancedPen<Fl> {
+        AdvancedPen {
+            width: pen.width,
+            color: pen.color,
+            line_cap_start: RoundLineCap,
+            line_cap_end: RoundLineCap,
+            line_join: RoundLineJoin,
+            dash: pen.dash.clone(),
+        }
+    }
+
+    /// Returns whether both ends have the same round or square cap
+    /// and the joins are round or square.
+    ///
+    /// Vector formats have line caps and joins for such strokes.
+    /// Other strokes are written as filled outlines from `stroke::stroke_shape`.
+    pub fn has_standard_ends(&self) -> bool {
+        let caps = match (&self.line_cap_start, &self.line_cap_end) {
+            (&RoundLineCap, &RoundLineCap) | (&SquareLineCap, &SquareLineCap) => true,
+            _ => false,
+        };
+        let join = match self.line_join {
+            RoundLineJoin | SquareLineJoin => true,
+            _ => false,
+        };
+        caps && join
+    }
 }
 
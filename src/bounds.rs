@@ -0,0 +1,263 @@
+
+//! Bounding boxes and hit testing of shapes.
+//!
+//! Bounding boxes are stored as [x1, y1, x2, y2],
+//! the minimum and maximum corner.
+//!
+//! Hit testing happens in shape coordinates.
+//! A point under the mouse must be mapped back through
+//! the inverse of the view before testing.
+
+use std::num::{Float, zero, one};
+
+use graphics::{Shape, Matrix};
+use graphics::{Pixel, PixelHorizontalLine, PixelVerticalLine, PixelLine};
+use graphics::{Line, Rect, RoundRect, Ellipse, Circle, Triangle, Polygon};
+use graphics::{Catmull, QuadraticBezierBound, QuadraticBezierFree};
+use graphics::simple_pen::Pen;
+use graphics::advanced_pen::{AdvancedPen, LineCap};
+use graphics::advanced_pen::{RoundLineCap, ArrowLineCap, SquareLineCap};
+use graphics::advanced_pen::{DiamonLineCap, CircleLineCap};
+use graphics::advanced_pen::{RoundLineJoin, SquareLineJoin, DiamonLineJoin, CircleLineJoin};
+use graphics::curve::{Curve, outline, transform};
+use graphics::stroke::{stroke_shape, ARROW_LENGTH, MITER_LIMIT};
+use graphics::scalar::Scalar;
+use graphics_new::identity;
+
+/// The maximum error in shape coordinates when approximating round parts.
+pub static HIT_TOLERANCE: f64 = 0.001;
+
+/// The maximum error when stroking for a hit test,
+/// relative to how far the stroke reaches from the outline.
+pub static STROKE_HIT_TOLERANCE: f64 = 0.01;
+
+/// Implemented by pens that can tell whether a point is on their stroke.
+pub trait StrokeHit<Fl=f64> {
+    /// Returns true if the point is covered by the stroke of a shape.
+    fn hit_stroke(&self, shape: &Shape<Fl>, p: [Fl, ..2]) -> bool;
+}
+
+impl<Fl: Scalar> StrokeHit<Fl> for Pen<Fl> {
+    fn hit_stroke(&self, shape: &Shape<Fl>, p: [Fl, ..2]) -> bool {
+        let half: Fl = Scalar::from_f64(0.5);
+        match self.dash {
+            Some(ref dash) if !dash.is_solid() =>
+                AdvancedPen::from_pen(self).hit_stroke(shape, p),
+            _ => distance(shape, p) <= half * self.width,
+        }
+    }
+}
+
+impl<Fl: Scalar> StrokeHit<Fl> for AdvancedPen<Fl> {
+    /// Points farther from the outline than the stroke reaches
+    /// are rejected without stroking.
+    fn hit_stroke(&self, shape: &Shape<Fl>, p: [Fl, ..2]) -> bool {
+        let reach = stroke_reach(self);
+        if !(distance(shape, p) <= reach) { return false; }
+        let tolerance: Fl = Scalar::from_f64(STROKE_HIT_TOLERANCE);
+        let polygons = stroke_shape(shape, self, tolerance * reach);
+        polygons.iter().any(|polygon| winding(polygon.as_slice(), p) != 0)
+    }
+}
+
+/// Returns how far the stroke of a pen reaches from the outline,
+/// including caps and joins.
+pub fn stroke_reach<Fl: Scalar>(pen: &AdvancedPen<Fl>) -> Fl {
+    let (half, two, five): (Fl, Fl, Fl) =
+        (Scalar::from_f64(0.5), Scalar::from_f64(2.0), Scalar::from_f64(5.0));
+    let (arrow_length, miter_limit): (Fl, Fl) =
+        (Scalar::from_f64(ARROW_LENGTH), Scalar::from_f64(MITER_LIMIT));
+    let hw = half * pen.width;
+    let cap = |line_cap: &LineCap<Fl>| match *line_cap {
+        RoundLineCap => hw,
+        SquareLineCap => hw * two.sqrt(),
+        // The head may stick out behind the start of a short line.
+        ArrowLineCap => arrow_length * hw * five.sqrt(),
+        DiamonLineCap(r) | CircleLineCap(r) => r,
+    };
+    let join = match pen.line_join {
+        RoundLineJoin => hw,
+        SquareLineJoin => miter_limit * hw,
+        DiamonLineJoin(r) | CircleLineJoin(r) => r,
+    };
+    hw.max(cap(&pen.line_cap_start)).max(cap(&pen.line_cap_end)).max(join)
+}
+
+/// Returns the bounding box of a shape.
+///
+/// Returns `None` if the shape has no points.
+#[inline(always)]
+pub fn bounding_box<Fl: Scalar>(shape: &Shape<Fl>) -> Option<[Fl, ..4]> {
+    transformed_bounding_box(shape, &identity())
+}
+
+/// Returns the bounding box of a shape after transforming with a matrix.
+///
+/// Returns `None` if the shape has no points.
+pub fn transformed_bounding_box<Fl: Scalar>(
+    shape: &Shape<Fl>,
+    m: &Matrix<Fl>
+) -> Option<[Fl, ..4]> {
+    let (o, half): (Fl, Fl) = (zero(), Scalar::from_f64(0.5));
+    match *shape {
+        Pixel(_) | PixelHorizontalLine(_) | PixelVerticalLine(_) | PixelLine(_)
+        | Line(_) | Rect(_) | Triangle(_) | Polygon(_) => {
+            let tolerance: Fl = Scalar::from_f64(HIT_TOLERANCE);
+            let mut res = None;
+            for &(ref polyline, _) in outline(shape, m, tolerance).iter() {
+                for &p in polyline.iter() {
+                    res = Some(include(res, transform(m, p)));
+                }
+            }
+            res
+        }
+        RoundRect(r) => {
+            let radius = r[4].min(half * r[2].abs()).min(half * r[3].abs()).max(o);
+            let (x1, y1) = (r[0] + radius, r[1] + radius);
+            let (x2, y2) = (r[0] + r[2] - radius, r[1] + r[3] - radius);
+            let inner = [[x1, y1], [x2, y1], [x2, y2], [x1, y2]];
+            let mut res = None;
+            for &p in inner.iter() {
+                res = Some(union(res, ellipse_box(m, p, [radius, radius])));
+            }
+            res
+        }
+        Ellipse(e) => {
+            let radius = [half * e[2], half * e[3]];
+            Some(ellipse_box(m, [e[0] + radius[0], e[1] + radius[1]], radius))
+        }
+        Circle(c) => Some(ellipse_box(m, [c[0], c[1]], [c[2], c[2]])),
+        Catmull(_) | QuadraticBezierBound(_) | QuadraticBezierFree(_) => {
+            let curve = Curve::from_shape(shape).unwrap();
+            let mut res = None;
+            for s in curve.segments.iter() {
+                res = Some(union(res, s.transform(m).bounding_box()));
+            }
+            res
+        }
+    }
+}
+
+/// Returns true if a point is inside the area of a shape.
+///
+/// Uses the nonzero winding rule.
+/// Lines have no area, so they never contain a point.
+pub fn contains<Fl: Scalar>(shape: &Shape<Fl>, p: [Fl, ..2]) -> bool {
+    match *shape {
+        Line(_) | PixelLine(_) => return false,
+        _ => {}
+    }
+    let tolerance: Fl = Scalar::from_f64(HIT_TOLERANCE);
+    let mut sum = 0;
+    for &(ref polyline, _) in outline(shape, &identity(), tolerance).iter() {
+        sum += winding(polyline.as_slice(), p);
+    }
+    sum != 0
+}
+
+/// Returns the distance from a point to the outline of a shape.
+///
+/// Returns infinity if the shape has no points.
+pub fn distance<Fl: Scalar>(shape: &Shape<Fl>, p: [Fl, ..2]) -> Fl {
+    let tolerance: Fl = Scalar::from_f64(HIT_TOLERANCE);
+    let mut res: Fl = Float::infinity();
+    for &(ref polyline, closed) in outline(shape, &identity(), tolerance).iter() {
+        let q = polyline.as_slice();
+        let n = q.len();
+        if n == 0 { continue; }
+        if n == 1 { res = res.min(segment_distance(q[0], q[0], p)); }
+        let segments = if closed { n } else { n - 1 };
+        for i in range(0u, segments) {
+            res = res.min(segment_distance(q[i], q[(i + 1) % n], p));
+        }
+    }
+    res
+}
+
+/// Returns the union of two bounding boxes.
+#[inline(always)]
+pub fn union<Fl: Scalar>(a: Option<[Fl, ..4]>, b: [Fl, ..4]) -> [Fl, ..4] {
+    match a {
+        None => b,
+        Some(a) => [a[0].min(b[0]), a[1].min(b[1]), a[2].max(b[2]), a[3].max(b[3])],
+    }
+}
+
+/// Returns a bounding box extended to include a point.
+#[inline(always)]
+fn include<Fl: Scalar>(a: Option<[Fl, ..4]>, p: [Fl, ..2]) -> [Fl, ..4] {
+    union(a, [p[0], p[1], p[0], p[1]])
+}
+
+/// Returns the bounding box of a transformed axis aligned ellipse.
+fn ellipse_box<Fl: Scalar>(m: &Matrix<Fl>, center: [Fl, ..2], radius: [Fl, ..2]) -> [Fl, ..4] {
+    let c = transform(m, center);
+    let ex = ((m[0] * radius[0]) * (m[0] * radius[0])
+            + (m[1] * radius[1]) * (m[1] * radius[1])).sqrt();
+    let ey = ((m[3] * radius[0]) * (m[3] * radius[0])
+            + (m[4] * radius[1]) * (m[4] * radius[1])).sqrt();
+    [c[0] - ex, c[1] - ey, c[0] + ex, c[1] + ey]
+}
+
+/// Returns the winding number of a closed polygon around a point.
+pub fn winding<Fl: Scalar>(polygon: &[[Fl, ..2]], p: [Fl, ..2]) -> int {
+    let o: Fl = zero();
+    let n = polygon.len();
+    let mut res = 0;
+    for i in range(0u, n) {
+        let (a, b) = (polygon[i], polygon[(i + 1) % n]);
+        let side = (b[0] - a[0]) * (p[1] - a[1]) - (p[0] - a[0]) * (b[1] - a[1]);
+        if a[1] <= p[1] {
+            if b[1] > p[1] && side > o { res += 1; }
+        } else if b[1] <= p[1] && side < o {
+            res -= 1;
+        }
+    }
+    res
+}
+
+/// Returns the distance from a point to a line segment.
+pub fn segment_distance<Fl: Scalar>(a: [Fl, ..2], b: [Fl, ..2], p: [Fl, ..2]) -> Fl {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 == o { o } else {
+        (((p[0] - a[0]) * dx + (p[1] - a[1]) * dy) / len2).max(o).min(l)
+    };
+    let (x, y) = (a[0] + t * dx - p[0], a[1] + t * dy - p[1]);
+    (x * x + y * y).sqrt()
+}
+
+#[test]
+fn test_bounds() {
+    let circle: Shape = Circle(&[10.0, 10.0, 5.0]);
+    assert_eq!(bounding_box(&circle), Some([5.0, 5.0, 15.0, 15.0]));
+    let rot = [0.0, -1.0, 0.0,
+               1.0, 0.0, 0.0];
+    let rect: Shape = Rect(&[0.0, 0.0, 4.0, 2.0]);
+    assert_eq!(transformed_bounding_box(&rect, &rot), Some([-2.0, 0.0, 0.0, 4.0]));
+
+    // The curve bulges beyond the end points.
+    let curve: Shape = QuadraticBezierFree(&[0.0, 0.0, 0.0, 4.0, 4.0, 4.0, 4.0, 0.0]);
+    let b = bounding_box(&curve).unwrap();
+    assert!((b[3] - 3.0).abs() < 1e-9);
+
+    assert!(contains(&circle, [12.0, 12.0]));
+    assert!(!contains(&circle, [14.0, 14.0]));
+    assert!((distance(&rect, [2.0, 5.0]) - 3.0).abs() < 1e-9);
+    let pen: Pen = Pen { width: 2.0, color: [0.0, 0.0, 0.0, 1.0], dash: None };
+    let line: Shape = Line(&[0.0, 0.0, 10.0, 0.0]);
+    assert!(pen.hit_stroke(&line, [5.0, 0.9]));
+    assert!(!pen.hit_stroke(&line, [5.0, 1.1]));
+
+    // A free curve needs four points, so this one is empty.
+    let empty: Shape = QuadraticBezierFree(&[0.0, 0.0, 1.0, 1.0]);
+    assert!(distance(&empty, [0.0, 0.0]).is_infinite());
+    let pen = AdvancedPen::from_pen(&pen);
+    assert!(pen.hit_stroke(&circle, [15.5, 10.0]));
+    assert!(!pen.hit_stroke(&circle, [12.0, 10.0]));
+    assert_eq!(stroke_reach(&pen), 1.0);
+    let arrow = AdvancedPen { line_cap_end: ArrowLineCap, dash: None, ..pen };
+    assert!(arrow.hit_stroke(&line, [5.0, 2.0]));
+    assert!(!arrow.hit_stroke(&line, [5.0, 2.75]));
+}
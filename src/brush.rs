@@ -0,0 +1,358 @@
+
+//! Brushes deciding the color inside filled shapes.
+//!
+//! A brush returns a color for each point in shape coordinates.
+//! Brushes with a pattern have their own transform
+//! from brush coordinates to shape coordinates,
+//! which is inverted when set instead of for every sample.
+//! The fill rule of the brush decides which points are inside
+//! shapes with overlapping parts.
+
+use std::num::zero;
+
+use graphics::Matrix;
+use graphics::gradient::ColorGradient;
+use graphics::scalar::Scalar;
+use graphics_new::{identity, invert, multiply, transform_point};
+
+/// Decides which points are inside shapes with overlapping parts.
+#[deriving(Clone, Eq, Show)]
+pub enum FillRule {
+    /// Inside when the outline winds around the point at least once.
+    NonZero,
+    /// Inside when a ray from the point crosses the outline an odd number of times.
+    EvenOdd,
+}
+
+/// Implemented by brushes that fill shapes.
+pub trait Brush<Fl=f64> {
+    /// Returns the color at a point in shape coordinates.
+    fn sample(&self, p: [Fl, ..2]) -> [Fl, ..4];
+
+    /// Returns the fill rule.
+    fn fill_rule(&self) -> FillRule;
+}
+
+/// Fills with a single color.
+#[deriving(Clone, Eq, Show)]
+pub struct SolidBrush<Fl=f64> {
+    /// The color.
+    pub color: [Fl, ..4],
+    /// The fill rule.
+    pub fill_rule: FillRule,
+}
+
+impl<Fl: Scalar> SolidBrush<Fl> {
+    /// Creates a solid brush using the nonzero rule.
+    pub fn new(color: [Fl, ..4]) -> SolidBrush<Fl> {
+        SolidBrush { color: color, fill_rule: NonZero }
+    }
+}
+
+impl<Fl: Scalar> Brush<Fl> for SolidBrush<Fl> {
+    #[inline(always)]
+    fn sample(&self, _p: [Fl, ..2]) -> [Fl, ..4] {
+        self.color
+    }
+
+    #[inline(always)]
+    fn fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+/// Fills with parallel lines.
+///
+/// The lines are horizontal in brush coordinates,
+/// starting at y = 0 and repeating every `spacing` units.
+/// Rotate the transform to get diagonal hatching.
+#[deriving(Clone, Eq, Show)]
+pub struct HatchBrush<Fl=f64> {
+    /// The color of the lines.
+    pub color: [Fl, ..4],
+    /// The color between the lines.
+    pub background: [Fl, ..4],
+    /// The distance between the start of two lines.
+    pub spacing: Fl,
+    /// The width of the lines.
+    pub line_width: Fl,
+    /// The fill rule.
+    pub fill_rule: FillRule,
+    transform: Matrix<Fl>,
+    inverse: Option<Matrix<Fl>>,
+}
+
+impl<Fl: Scalar> HatchBrush<Fl> {
+    /// Creates a hatch brush with transparent background.
+    pub fn new(color: [Fl, ..4], spacing: Fl, line_width: Fl) -> HatchBrush<Fl> {
+        HatchBrush {
+            color: color,
+            background: [zero(), zero(), zero(), zero()],
+            spacing: spacing,
+            line_width: line_width,
+            fill_rule: NonZero,
+            transform: identity(),
+            inverse: Some(identity()),
+        }
+    }
+
+    /// Returns the transform from brush coordinates to shape coordinates.
+    #[inline(always)]
+    pub fn get_transform<'a>(&'a self) -> &'a Matrix<Fl> {
+        &self.transform
+    }
+
+    /// Sets the transform from brush coordinates to shape coordinates.
+    pub fn set_transform(&mut self, m: &Matrix<Fl>) {
+        self.transform = *m;
+        self.inverse = invert(m);
+    }
+
+    /// Applies a matrix on top of the brush transform.
+    #[inline(always)]
+    pub fn transform(&mut self, m: &Matrix<Fl>) {
+        let m = multiply(m, &self.transform);
+        self.set_transform(&m);
+    }
+}
+
+impl<Fl: Scalar> Brush<Fl> for HatchBrush<Fl> {
+    fn sample(&self, p: [Fl, ..2]) -> [Fl, ..4] {
+        let q = match brush_point(&self.inverse, p) {
+            None => return self.background,
+            Some(q) => q,
+        };
+        if self.spacing <= zero() { return self.color; }
+        let y = q[1] - (q[1] / self.spacing).floor() * self.spacing;
+        if y < self.line_width { self.color } else { self.background }
+    }
+
+    #[inline(always)]
+    fn fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+/// Fills with squares of two alternating colors.
+#[deriving(Clone, Eq, Show)]
+pub struct CheckerBrush<Fl=f64> {
+    /// The two colors, the first is used at the origin.
+    pub colors: [[Fl, ..4], ..2],
+    /// The size of each square.
+    pub size: Fl,
+    /// The fill rule.
+    pub fill_rule: FillRule,
+    transform: Matrix<Fl>,
+    inverse: Option<Matrix<Fl>>,
+}
+
+impl<Fl: Scalar> CheckerBrush<Fl> {
+    /// Creates a checkerboard brush.
+    pub fn new(first: [Fl, ..4], second: [Fl, ..4], size: Fl) -> CheckerBrush<Fl> {
+        CheckerBrush {
+            colors: [first, second],
+            size: size,
+            fill_rule: NonZero,
+            transform: identity(),
+            inverse: Some(identity()),
+        }
+    }
+
+    /// Returns the transform from brush coordinates to shape coordinates.
+    #[inline(always)]
+    pub fn get_transform<'a>(&'a self) -> &'a Matrix<Fl> {
+        &self.transform
+    }
+
+    /// Sets the transform from brush coordinates to shape coordinates.
+    pub fn set_transform(&mut self, m: &Matrix<Fl>) {
+        self.transform = *m;
+        self.inverse = invert(m);
+    }
+
+    /// Applies a matrix on top of the brush transform.
+    #[inline(always)]
+    pub fn transform(&mut self, m: &Matrix<Fl>) {
+        let m = multiply(m, &self.transform);
+        self.set_transform(&m);
+    }
+}
+
+impl<Fl: Scalar> Brush<Fl> for CheckerBrush<Fl> {
+    fn sample(&self, p: [Fl, ..2]) -> [Fl, ..4] {
+        let q = match brush_point(&self.inverse, p) {
+            None => return self.colors[0],
+            Some(q) => q,
+        };
+        if self.size <= zero() { return self.colors[0]; }
+        let i = (q[0] / self.size).floor().as_f64() as i64
+              + (q[1] / self.size).floor().as_f64() as i64;
+        self.colors[(i & 1) as uint]
+    }
+
+    #[inline(always)]
+    fn fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+/// Fills with an image.
+///
+/// Each pixel of the image is one unit in brush coordinates.
+/// The image is sampled with the nearest pixel.
+#[deriving(Clone, Eq, Show)]
+pub struct ImageBrush<Fl=f64> {
+    /// Whether the image repeats outside its bounds.
+    pub repeat: bool,
+    /// The fill rule.
+    pub fill_rule: FillRule,
+    width: uint,
+    height: uint,
+    pixels: Vec<u8>,
+    transform: Matrix<Fl>,
+    inverse: Option<Matrix<Fl>>,
+}
+
+impl<Fl: Scalar> ImageBrush<Fl> {
+    /// Creates a repeating image brush.
+    ///
+    /// Returns an error if the number of pixels does not match the size.
+    pub fn new(width: uint, height: uint, pixels: Vec<u8>) -> Result<ImageBrush<Fl>, ~str> {
+        if pixels.len() != width * height * 4 {
+            return Err(format!("Expected {} bytes for a {}x{} image, found {}",
+                width * height * 4, width, height, pixels.len()));
+        }
+        Ok(ImageBrush {
+            width: width,
+            height: height,
+            pixels: pixels,
+            repeat: true,
+            fill_rule: NonZero,
+            transform: identity(),
+            inverse: Some(identity()),
+        })
+    }
+
+    /// Returns the width in pixels.
+    #[inline(always)]
+    pub fn width(&self) -> uint {
+        self.width
+    }
+
+    /// Returns the height in pixels.
+    #[inline(always)]
+    pub fn height(&self) -> uint {
+        self.height
+    }
+
+    /// Returns the pixels stored row by row as [r, g, b, a] with straight alpha.
+    #[inline(always)]
+    pub fn pixels<'a>(&'a self) -> &'a [u8] {
+        self.pixels.as_slice()
+    }
+
+    /// Returns the transform from brush coordinates to shape coordinates.
+    #[inline(always)]
+    pub fn get_transform<'a>(&'a self) -> &'a Matrix<Fl> {
+        &self.transform
+    }
+
+    /// Sets the transform from brush coordinates to shape coordinates.
+    pub fn set_transform(&mut self, m: &Matrix<Fl>) {
+        self.transform = *m;
+        self.inverse = invert(m);
+    }
+
+    /// Applies a matrix on top of the brush transform.
+    #[inline(always)]
+    pub fn transform(&mut self, m: &Matrix<Fl>) {
+        let m = multiply(m, &self.transform);
+        self.set_transform(&m);
+    }
+
+    /// Returns the color of a pixel.
+    ///
+    /// Fails if the pixel is outside the image.
+    #[inline(always)]
+    pub fn get_pixel(&self, x: uint, y: uint) -> [Fl, ..4] {
+        let i = (y * self.width + x) * 4;
+        let p = self.pixels.as_slice();
+        [Scalar::from_f64(p[i] as f64 / 255.0), Scalar::from_f64(p[i + 1] as f64 / 255.0),
+         Scalar::from_f64(p[i + 2] as f64 / 255.0), Scalar::from_f64(p[i + 3] as f64 / 255.0)]
+    }
+}
+
+impl<Fl: Scalar> Brush<Fl> for ImageBrush<Fl> {
+    fn sample(&self, p: [Fl, ..2]) -> [Fl, ..4] {
+        let transparent = [zero(), zero(), zero(), zero()];
+        if self.width == 0 || self.height == 0 { return transparent; }
+        let q = match brush_point(&self.inverse, p) {
+            None => return transparent,
+            Some(q) => q,
+        };
+        let (x, y) = (q[0].floor().as_f64() as i64, q[1].floor().as_f64() as i64);
+        let (w, h) = (self.width as i64, self.height as i64);
+        if self.repeat {
+            self.get_pixel((((x % w) + w) % w) as uint, (((y % h) + h) % h) as uint)
+        } else if x < 0 || y < 0 || x >= w || y >= h {
+            transparent
+        } else {
+            self.get_pixel(x as uint, y as uint)
+        }
+    }
+
+    #[inline(always)]
+    fn fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+impl<Fl: Scalar> Brush<Fl> for ColorGradient<Fl> {
+    #[inline(always)]
+    fn sample(&self, p: [Fl, ..2]) -> [Fl, ..4] {
+        // Inherent methods are found first, so this is `ColorGradient::sample`.
+        self.sample(p)
+    }
+
+    #[inline(always)]
+    fn fill_rule(&self) -> FillRule {
+        NonZero
+    }
+}
+
+/// Maps a point from shape coordinates to brush coordinates
+/// with the inverse brush transform.
+///
+/// Returns `None` if the transform is singular.
+#[inline(always)]
+fn brush_point<Fl: Scalar>(inverse: &Option<Matrix<Fl>>, p: [Fl, ..2]) -> Option<[Fl, ..2]> {
+    inverse.as_ref().map(|inv| transform_point(inv, p))
+}
+
+#[test]
+fn test_brush() {
+    let checker: CheckerBrush = CheckerBrush::new([1.0, 1.0, 1.0, 1.0], [0.0, 0.0, 0.0, 1.0], 2.0);
+    assert_eq!(checker.sample([1.0, 1.0]), [1.0, 1.0, 1.0, 1.0]);
+    assert_eq!(checker.sample([3.0, 1.0]), [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(checker.sample([-1.0, 1.0]), [0.0, 0.0, 0.0, 1.0]);
+
+    let hatch: HatchBrush = HatchBrush::new([1.0, 0.0, 0.0, 1.0], 4.0, 1.0);
+    assert_eq!(hatch.sample([7.0, 4.5]), [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(hatch.sample([7.0, 6.0]), [0.0, 0.0, 0.0, 0.0]);
+    let mut hatch = hatch;
+    hatch.transform(&[1.0, 0.0, 0.0,
+                      0.0, 2.0, 0.0]);
+    assert_eq!(hatch.get_transform()[4], 2.0);
+    assert_eq!(hatch.sample([7.0, 6.0]), [0.0, 0.0, 0.0, 0.0]);
+    assert_eq!(hatch.sample([7.0, 9.0]), [1.0, 0.0, 0.0, 1.0]);
+    hatch.set_transform(&[0.0, 0.0, 0.0,
+                          0.0, 0.0, 0.0]);
+    assert_eq!(hatch.sample([7.0, 9.0]), [0.0, 0.0, 0.0, 0.0]);
+
+    let image: ImageBrush = ImageBrush::new(2, 1, vec!(255, 0, 0, 255, 0, 0, 255, 255)).unwrap();
+    assert_eq!(image.sample([2.5, 0.5]), [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(image.sample([-0.5, 3.5]), [0.0, 0.0, 1.0, 1.0]);
+    assert_eq!((image.width(), image.height(), image.pixels().len()), (2, 1, 8));
+    let wrong_size: Result<ImageBrush, ~str> = ImageBrush::new(2, 2, vec!(0, 0, 0, 0));
+    assert!(wrong_size.is_err());
+}
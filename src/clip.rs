@@ -0,0 +1,130 @@
+//! Clipping regions restricting where shapes are drawn.
+//!
+//! A clip is the intersection of regions.
+//! Each region is a set of closed contours in device coordinates,
+//! using the nonzero winding rule.
+//! A clip without regions does not restrict drawing.
+
+use std::num::{zero, one};
+
+use graphics::{Shape, Matrix};
+use graphics::curve::{outline, transform};
+use graphics::bounds::{winding, union};
+use graphics::scalar::Scalar;
+
+/// The maximum distance in device coordinates between
+/// round parts of a clip shape and its contours.
+pub static CLIP_TOLERANCE: f64 = 0.25;
+
+/// The intersection of regions in device coordinates.
+#[deriving(Clone, Eq, Show)]
+pub struct Clip<Fl=f64> {
+    /// The regions, each made of closed contours.
+    pub regions: Vec<Vec<Vec<[Fl, ..2]>>>,
+}
+
+impl<Fl: Scalar> Clip<Fl> {
+    /// Creates a clip that does not restrict drawing.
+    pub fn new() -> Clip<Fl> {
+        Clip { regions: Vec::new() }
+    }
+
+    /// Returns true if the clip does not restrict drawing.
+    #[inline(always)]
+    pub fn is_unbounded(&self) -> bool {
+        self.regions.len() == 0
+    }
+
+    /// Returns the clip intersected with a region.
+    pub fn intersect(&self, region: Vec<Vec<[Fl, ..2]>>) -> Clip<Fl> {
+        let mut regions = self.regions.clone();
+        regions.push(region);
+        Clip { regions: regions }
+    }
+
+    /// Returns the clip intersected with a rectangle [x, y, w, h]
+    /// transformed by a matrix.
+    pub fn intersect_rect(&self, m: &Matrix<Fl>, rect: [Fl, ..4]) -> Clip<Fl> {
+        let r = rect;
+        let corners = [[r[0], r[1]], [r[0] + r[2], r[1]],
+                       [r[0] + r[2], r[1] + r[3]], [r[0], r[1] + r[3]]];
+        self.intersect(vec!(corners.iter().map(|&p| transform(m, p)).collect()))
+    }
+
+    /// Returns the clip intersected with the area of a shape
+    /// transformed by a matrix.
+    ///
+    /// Lines have no area, so clipping to a line hides everything.
+    pub fn intersect_shape(&self, m: &Matrix<Fl>, shape: &Shape<Fl>) -> Clip<Fl> {
+        let tolerance: Fl = Scalar::from_f64(CLIP_TOLERANCE);
+        let region = outline(shape, m, tolerance).move_iter().map(|(contour, _)|
+            contour.iter().map(|&p| transform(m, p)).collect()
+        ).collect();
+        self.intersect(region)
+    }
+
+    /// Returns true if a point in device coordinates is inside the clip.
+    pub fn contains(&self, p: [Fl, ..2]) -> bool {
+        self.regions.iter().all(|region| {
+            let mut sum = 0;
+            for contour in region.iter() {
+                sum += winding(contour.as_slice(), p);
+            }
+            sum != 0
+        })
+    }
+
+    /// Returns a box [x1, y1, x2, y2] containing the clip.
+    ///
+    /// Returns `None` if the clip does not restrict drawing.
+    /// The box may be empty, with x1 > x2 or y1 > y2.
+    pub fn bounding_box(&self) -> Option<[Fl, ..4]> {
+        let (o, l): (Fl, Fl) = (zero(), one());
+        let mut res: Option<[Fl, ..4]> = None;
+        for region in self.regions.iter() {
+            let mut b = None;
+            for contour in region.iter() {
+                for p in contour.iter() {
+                    b = Some(union(b, [p[0], p[1], p[0], p[1]]));
+                }
+            }
+            let b = match b {
+                None => [o, o, -l, -l],
+                Some(b) => b,
+            };
+            res = Some(match res {
+                None => b,
+                Some(a) => [a[0].max(b[0]), a[1].max(b[1]), a[2].min(b[2]), a[3].min(b[3])],
+            });
+        }
+        res
+    }
+}
+
+#[test]
+fn test_clip() {
+    use graphics::Circle;
+
+    let identity = [1.0, 0.0, 0.0,
+                    0.0, 1.0, 0.0];
+    let clip: Clip = Clip::new();
+    assert!(clip.contains([1000.0, 1000.0]));
+    let clip = clip.intersect_rect(&identity, [0.0, 0.0, 10.0, 10.0]);
+    let moved = [1.0, 0.0, 5.0,
+                 0.0, 1.0, 0.0];
+    let clip = clip.intersect_rect(&moved, [0.0, 0.0, 10.0, 10.0]);
+    assert!(clip.contains([7.0, 5.0]));
+    assert!(!clip.contains([3.0, 5.0]));
+    assert!(!clip.contains([12.0, 5.0]));
+    assert_eq!(clip.bounding_box(), Some([5.0, 0.0, 10.0, 10.0]));
+
+    let clip = clip.intersect_shape(&identity, &Circle(&[10.0, 5.0, 2.0]));
+    assert!(clip.contains([9.0, 5.0]));
+    assert!(!clip.contains([11.0, 5.0]));
+
+    // Clips work the same with `f32`.
+    let clip: Clip<f32> = Clip::new();
+    let clip = clip.intersect_shape(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0], &Circle(&[10.0, 5.0, 2.0]));
+    assert!(clip.contains([9.0, 5.0]));
+    assert!(!clip.contains([11.0, 5.0]));
+}
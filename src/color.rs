@@ -0,0 +1,503 @@
+
+//! Color spaces and conversions between them.
+//!
+//! A bare color [r, g, b, a] is sRGB with straight alpha,
+//! which is what shaders, brushes and `graphics_new::Color` expect.
+//! The types in this module say which space a color is in.
+//! Linear RGB uses the sRGB primaries without the transfer curve,
+//! and is the space where light adds up, for example when blending.
+//! Lab is CIE L*a*b* relative to the D65 white point,
+//! and OKLab is the perceptual space of Björn Ottosson.
+//! Hue is measured in degrees, other components go from 0 to 1,
+//! except for L in Lab which goes from 0 to 100.
+//!
+//! Strings are parsed as CSS colors:
+//! hex notation, `rgb()`, `rgba()`, `hsl()`, `hsla()`, `transparent`
+//! and the named colors of CSS 2.1 plus `orange`.
+
+use std::ascii::StrAsciiExt;
+use std::num::Float;
+
+/// A color in sRGB with straight alpha.
+#[deriving(Clone, Eq, Show)]
+pub struct Srgb {
+    /// Red.
+    pub r: f64,
+    /// Green.
+    pub g: f64,
+    /// Blue.
+    pub b: f64,
+    /// Alpha.
+    pub a: f64,
+}
+
+/// A color in linear RGB with straight alpha.
+#[deriving(Clone, Eq, Show)]
+pub struct LinearRgb {
+    /// Red.
+    pub r: f64,
+    /// Green.
+    pub g: f64,
+    /// Blue.
+    pub b: f64,
+    /// Alpha.
+    pub a: f64,
+}
+
+/// A color as hue, saturation and value.
+#[deriving(Clone, Eq, Show)]
+pub struct Hsv {
+    /// Hue in degrees.
+    pub h: f64,
+    /// Saturation.
+    pub s: f64,
+    /// Value.
+    pub v: f64,
+    /// Alpha.
+    pub a: f64,
+}
+
+/// A color as hue, saturation and lightness.
+#[deriving(Clone, Eq, Show)]
+pub struct Hsl {
+    /// Hue in degrees.
+    pub h: f64,
+    /// Saturation.
+    pub s: f64,
+    /// Lightness.
+    pub l: f64,
+    /// Alpha.
+    pub a: f64,
+}
+
+/// A color in CIE L*a*b* relative to D65.
+#[deriving(Clone, Eq, Show)]
+pub struct Lab {
+    /// Lightness from 0 to 100.
+    pub l: f64,
+    /// Green to red.
+    pub a: f64,
+    /// Blue to yellow.
+    pub b: f64,
+    /// Alpha.
+    pub alpha: f64,
+}
+
+/// A color in OKLab.
+#[deriving(Clone, Eq, Show)]
+pub struct Oklab {
+    /// Lightness from 0 to 1.
+    pub l: f64,
+    /// Green to red.
+    pub a: f64,
+    /// Blue to yellow.
+    pub b: f64,
+    /// Alpha.
+    pub alpha: f64,
+}
+
+/// The white point D65 in XYZ.
+static WHITE: [f64, ..3] = [0.95047, 1.0, 1.08883];
+
+/// The named colors.
+static NAMES: &'static [(&'static str, [u8, ..3])] = &[
+    ("black", [0, 0, 0]),
+    ("silver", [192, 192, 192]),
+    ("gray", [128, 128, 128]),
+    ("grey", [128, 128, 128]),
+    ("white", [255, 255, 255]),
+    ("maroon", [128, 0, 0]),
+    ("red", [255, 0, 0]),
+    ("purple", [128, 0, 128]),
+    ("fuchsia", [255, 0, 255]),
+    ("magenta", [255, 0, 255]),
+    ("green", [0, 128, 0]),
+    ("lime", [0, 255, 0]),
+    ("olive", [128, 128, 0]),
+    ("yellow", [255, 255, 0]),
+    ("navy", [0, 0, 128]),
+    ("blue", [0, 0, 255]),
+    ("teal", [0, 128, 128]),
+    ("aqua", [0, 255, 255]),
+    ("cyan", [0, 255, 255]),
+    ("orange", [255, 165, 0]),
+];
+
+impl Srgb {
+    /// Creates a color.
+    #[inline(always)]
+    pub fn new(r: f64, g: f64, b: f64, a: f64) -> Srgb {
+        Srgb { r: r, g: g, b: b, a: a }
+    }
+
+    /// Creates a color from bytes.
+    #[inline(always)]
+    pub fn from_bytes(r: u8, g: u8, b: u8, a: u8) -> Srgb {
+        Srgb::new(r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0, a as f64 / 255.0)
+    }
+
+    /// Creates a color from a bare color.
+    #[inline(always)]
+    pub fn from_color(c: [f64, ..4]) -> Srgb {
+        Srgb::new(c[0], c[1], c[2], c[3])
+    }
+
+    /// Returns a bare color.
+    #[inline(always)]
+    pub fn to_color(&self) -> [f64, ..4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// Converts to linear RGB.
+    pub fn to_linear(&self) -> LinearRgb {
+        LinearRgb {
+            r: srgb_to_linear(self.r),
+            g: srgb_to_linear(self.g),
+            b: srgb_to_linear(self.b),
+            a: self.a,
+        }
+    }
+
+    /// Converts to hue, saturation and value.
+    pub fn to_hsv(&self) -> Hsv {
+        let (h, max, min) = hue(self.r, self.g, self.b);
+        Hsv {
+            h: h,
+            s: if max > 0.0 { (max - min) / max } else { 0.0 },
+            v: max,
+            a: self.a,
+        }
+    }
+
+    /// Converts to hue, saturation and lightness.
+    pub fn to_hsl(&self) -> Hsl {
+        let (h, max, min) = hue(self.r, self.g, self.b);
+        let l = 0.5 * (max + min);
+        let d = max - min;
+        Hsl {
+            h: h,
+            s: if d > 0.0 { d / (1.0 - (2.0 * l - 1.0).abs()) } else { 0.0 },
+            l: l,
+            a: self.a,
+        }
+    }
+
+    /// Converts to CIE L*a*b*.
+    #[inline(always)]
+    pub fn to_lab(&self) -> Lab {
+        self.to_linear().to_lab()
+    }
+
+    /// Converts to OKLab.
+    #[inline(always)]
+    pub fn to_oklab(&self) -> Oklab {
+        self.to_linear().to_oklab()
+    }
+
+    /// Returns a hex string `#rrggbb`, or `#rrggbbaa` if not opaque.
+    pub fn to_hex(&self) -> ~str {
+        let rgb = format!("\\#{:02x}{:02x}{:02x}",
+            to_byte(self.r), to_byte(self.g), to_byte(self.b));
+        if to_byte(self.a) == 255 { rgb } else { rgb + format!("{:02x}", to_byte(self.a)) }
+    }
+
+    /// Parses a CSS color.
+    pub fn parse(text: &str) -> Result<Srgb, ~str> {
+        let text = text.trim().to_ascii_lower();
+        let text = text.as_slice();
+        if text.starts_with("#") {
+            return match parse_hex(text.slice_from(1)) {
+                None => Err(format!("Invalid hex color `{}`", text)),
+                Some(c) => Ok(c),
+            };
+        }
+        if text == "transparent" { return Ok(Srgb::new(0.0, 0.0, 0.0, 0.0)); }
+        for &(name, c) in NAMES.iter() {
+            if text == name { return Ok(Srgb::from_bytes(c[0], c[1], c[2], 255)); }
+        }
+        let (name, args) = match (text.find('('), text.ends_with(")")) {
+            (Some(i), true) => (text.slice_to(i).trim(), text.slice(i + 1, text.len() - 1)),
+            _ => return Err(format!("Unknown color `{}`", text)),
+        };
+        let args: Vec<&str> = args.split(',').map(|arg| arg.trim()).collect();
+        let args = args.as_slice();
+        let expected = match name {
+            "rgb" | "hsl" => 3,
+            "rgba" | "hsla" => 4,
+            _ => return Err(format!("Unknown color function `{}`", name)),
+        };
+        if args.len() != expected {
+            return Err(format!("Expected {} arguments for `{}`, found {}",
+                expected, name, args.len()));
+        }
+        let alpha = if expected == 4 { try!(parse_number(args[3], 1.0)) } else { 1.0 };
+        if name.starts_with("rgb") {
+            Ok(Srgb::new(try!(parse_number(args[0], 255.0)) / 255.0,
+                         try!(parse_number(args[1], 255.0)) / 255.0,
+                         try!(parse_number(args[2], 255.0)) / 255.0,
+                         alpha))
+        } else {
+            let h = try!(parse_number(args[0], 360.0));
+            Ok(Hsl { h: h,
+                     s: try!(parse_number(args[1], 1.0)),
+                     l: try!(parse_number(args[2], 1.0)),
+                     a: alpha }.to_srgb())
+        }
+    }
+}
+
+impl LinearRgb {
+    /// Creates a color.
+    #[inline(always)]
+    pub fn new(r: f64, g: f64, b: f64, a: f64) -> LinearRgb {
+        LinearRgb { r: r, g: g, b: b, a: a }
+    }
+
+    /// Converts to sRGB.
+    pub fn to_srgb(&self) -> Srgb {
+        Srgb {
+            r: linear_to_srgb(self.r),
+            g: linear_to_srgb(self.g),
+            b: linear_to_srgb(self.b),
+            a: self.a,
+        }
+    }
+
+    /// Converts to CIE L*a*b*.
+    pub fn to_lab(&self) -> Lab {
+        let (r, g, b) = (self.r, self.g, self.b);
+        let x = 0.4124564 * r + 0.3575761 * g + 0.1804375 * b;
+        let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+        let z = 0.0193339 * r + 0.1191920 * g + 0.9503041 * b;
+        let (fx, fy, fz) = (lab_f(x / WHITE[0]), lab_f(y / WHITE[1]), lab_f(z / WHITE[2]));
+        Lab {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+            alpha: self.a,
+        }
+    }
+
+    /// Converts to OKLab.
+    pub fn to_oklab(&self) -> Oklab {
+        let (r, g, b) = (self.r, self.g, self.b);
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+        Oklab {
+            l: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            b: 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+            alpha: self.a,
+        }
+    }
+}
+
+impl Hsv {
+    /// Converts to sRGB.
+    pub fn to_srgb(&self) -> Srgb {
+        let c = self.v * self.s;
+        from_hue(self.h, c, self.v - c, self.a)
+    }
+}
+
+impl Hsl {
+    /// Converts to sRGB.
+    pub fn to_srgb(&self) -> Srgb {
+        let c = (1.0 - (2.0 * self.l - 1.0).abs()) * self.s;
+        from_hue(self.h, c, self.l - 0.5 * c, self.a)
+    }
+}
+
+impl Lab {
+    /// Converts to linear RGB.
+    pub fn to_linear(&self) -> LinearRgb {
+        let fy = (self.l + 16.0) / 116.0;
+        let fx = fy + self.a / 500.0;
+        let fz = fy - self.b / 200.0;
+        let (x, y, z) = (WHITE[0] * lab_f_inv(fx), WHITE[1] * lab_f_inv(fy),
+                         WHITE[2] * lab_f_inv(fz));
+        LinearRgb {
+            r: 3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+            g: -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+            b: 0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+            a: self.alpha,
+        }
+    }
+
+    /// Converts to sRGB.
+    #[inline(always)]
+    pub fn to_srgb(&self) -> Srgb {
+        self.to_linear().to_srgb()
+    }
+}
+
+impl Oklab {
+    /// Converts to linear RGB.
+    pub fn to_linear(&self) -> LinearRgb {
+        let l = self.l + 0.3963377774 * self.a + 0.2158037573 * self.b;
+        let m = self.l - 0.1055613458 * self.a - 0.0638541728 * self.b;
+        let s = self.l - 0.0894841775 * self.a - 1.2914855480 * self.b;
+        let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+        LinearRgb {
+            r: 4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+            g: -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+            b: -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+            a: self.alpha,
+        }
+    }
+
+    /// Converts to sRGB.
+    #[inline(always)]
+    pub fn to_srgb(&self) -> Srgb {
+        self.to_linear().to_srgb()
+    }
+}
+
+/// Converts a channel from sRGB to linear RGB.
+#[inline(always)]
+pub fn srgb_to_linear(v: f64) -> f64 {
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Converts a channel from linear RGB to sRGB.
+#[inline(always)]
+pub fn linear_to_srgb(v: f64) -> f64 {
+    if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Converts a bare color from straight to premultiplied alpha.
+#[inline(always)]
+pub fn premultiply(c: [f64, ..4]) -> [f64, ..4] {
+    [c[0] * c[3], c[1] * c[3], c[2] * c[3], c[3]]
+}
+
+/// Converts a bare color from premultiplied to straight alpha.
+///
+/// Fully transparent colors become transparent black.
+#[inline(always)]
+pub fn unpremultiply(c: [f64, ..4]) -> [f64, ..4] {
+    if c[3] <= 0.0 { return [0.0, 0.0, 0.0, 0.0]; }
+    [c[0] / c[3], c[1] / c[3], c[2] / c[3], c[3]]
+}
+
+/// Returns the hue in degrees with the largest and smallest channel.
+fn hue(r: f64, g: f64, b: f64) -> (f64, f64, f64) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let d = max - min;
+    let h = if d <= 0.0 { 0.0 }
+        else if max == r { 60.0 * ((g - b) / d) }
+        else if max == g { 60.0 * ((b - r) / d + 2.0) }
+        else { 60.0 * ((r - g) / d + 4.0) };
+    (if h < 0.0 { h + 360.0 } else { h }, max, min)
+}
+
+/// Creates an sRGB color from hue, chroma and the smallest channel.
+fn from_hue(h: f64, c: f64, min: f64, a: f64) -> Srgb {
+    let h = (h % 360.0 + 360.0) % 360.0 / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as uint {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    Srgb::new(r + min, g + min, b + min, a)
+}
+
+/// The nonlinear part of CIE L*a*b*.
+#[inline(always)]
+fn lab_f(t: f64) -> f64 {
+    let d = 6.0 / 29.0;
+    if t > d * d * d { t.cbrt() } else { t / (3.0 * d * d) + 4.0 / 29.0 }
+}
+
+/// The inverse of `lab_f`.
+#[inline(always)]
+fn lab_f_inv(t: f64) -> f64 {
+    let d = 6.0 / 29.0;
+    if t > d { t * t * t } else { 3.0 * d * d * (t - 4.0 / 29.0) }
+}
+
+/// Converts a channel to a byte.
+#[inline(always)]
+fn to_byte(v: f64) -> u8 {
+    (v.max(0.0).min(1.0) * 255.0 + 0.5).floor() as u8
+}
+
+/// Parses hex digits `rgb`, `rgba`, `rrggbb` or `rrggbbaa`.
+fn parse_hex(text: &str) -> Option<Srgb> {
+    let mut digits = Vec::new();
+    for c in text.chars() {
+        match c.to_digit(16) {
+            None => return None,
+            Some(d) => digits.push(d as u8),
+        }
+    }
+    let d = digits.as_slice();
+    match d.len() {
+        3 | 4 => {
+            let a = if d.len() == 4 { d[3] * 17 } else { 255 };
+            Some(Srgb::from_bytes(d[0] * 17, d[1] * 17, d[2] * 17, a))
+        }
+        6 | 8 => {
+            let a = if d.len() == 8 { d[6] * 16 + d[7] } else { 255 };
+            Some(Srgb::from_bytes(d[0] * 16 + d[1], d[2] * 16 + d[3], d[4] * 16 + d[5], a))
+        }
+        _ => None,
+    }
+}
+
+/// Parses a number or a percentage of a full value.
+fn parse_number(text: &str, full: f64) -> Result<f64, ~str> {
+    let (number, scale) = if text.ends_with("%") {
+        (text.slice_to(text.len() - 1), 0.01 * full)
+    } else {
+        (text, 1.0)
+    };
+    match from_str::<f64>(number.trim()) {
+        None => Err(format!("Expected number, found `{}`", text)),
+        Some(v) => Ok(v * scale),
+    }
+}
+
+#[test]
+fn test_color() {
+    let close = |a: [f64, ..4], b: [f64, ..4]|
+        range(0u, 4).all(|i| (a[i] - b[i]).abs() < 1e-6);
+
+    let orange = Srgb::parse("#FF8000").unwrap();
+    assert_eq!(orange.to_hex(), ~"#ff8000");
+    assert_eq!(Srgb::parse(" #f008 ").unwrap().to_hex(), ~"#ff000088");
+    assert_eq!(Srgb::parse("rgba(255, 0, 0, 50%)"), Ok(Srgb::new(1.0, 0.0, 0.0, 0.5)));
+    assert_eq!(Srgb::parse("hsl(120, 100%, 25%)").unwrap().to_hex(), ~"#008000");
+    assert_eq!(Srgb::parse("Green"), Srgb::parse("#008000"));
+    assert!(Srgb::parse("#12345").is_err());
+    assert!(Srgb::parse("rgb(1, 2)").is_err());
+    assert!(Srgb::parse("chartreuse").is_err());
+
+    assert!(close(orange.to_linear().to_srgb().to_color(), orange.to_color()));
+    assert!(close(orange.to_hsv().to_srgb().to_color(), orange.to_color()));
+    assert!(close(orange.to_hsl().to_srgb().to_color(), orange.to_color()));
+    assert!(close(orange.to_lab().to_srgb().to_color(), orange.to_color()));
+    assert!(close(orange.to_oklab().to_srgb().to_color(), orange.to_color()));
+    let hsv = orange.to_hsv();
+    assert!((hsv.h - 30.0).abs() < 0.5 && hsv.s == 1.0 && hsv.v == 1.0);
+
+    // White has no chroma.
+    let white = Srgb::new(1.0, 1.0, 1.0, 1.0);
+    let lab = white.to_lab();
+    assert!((lab.l - 100.0).abs() < 1e-3 && lab.a.abs() < 1e-3 && lab.b.abs() < 1e-3);
+    let oklab = white.to_oklab();
+    assert!((oklab.l - 1.0).abs() < 1e-3 && oklab.a.abs() < 1e-3 && oklab.b.abs() < 1e-3);
+
+    assert!((srgb_to_linear(0.5) - 0.214041).abs() < 1e-6);
+    assert!((linear_to_srgb(srgb_to_linear(0.5)) - 0.5).abs() < 1e-9);
+    let c = [0.5, 0.25, 1.0, 0.5];
+    assert_eq!(premultiply(c), [0.25, 0.125, 0.5, 0.5]);
+    assert_eq!(unpremultiply(premultiply(c)), c);
+    assert_eq!(unpremultiply([0.5, 0.5, 0.5, 0.0]), [0.0, 0.0, 0.0, 0.0]);
+}
@@ -0,0 +1,147 @@
+
+//! Porter-Duff compositing and blend modes.
+//!
+//! An operator decides how a source color is combined
+//! with the destination color already in the target.
+//! Colors are given in straight alpha and converted to
+//! premultiplied alpha while compositing.
+//!
+//! The blend modes use the separable blend functions of the
+//! W3C compositing specification, combined with source-over.
+
+use std::num::{zero, one};
+
+use graphics::scalar::Scalar;
+
+/// Decides how a source color combines with the destination.
+#[deriving(Clone, Eq, Show)]
+pub enum CompositeOp {
+    /// Draws the source on top of the destination.
+    SourceOver,
+    /// Draws the source where the destination is.
+    SourceIn,
+    /// Draws the source where the destination is not.
+    SourceOut,
+    /// Draws the source on top of the destination, only where the destination is.
+    SourceAtop,
+    /// Draws the destination on top of the source.
+    DestinationOver,
+    /// Keeps the destination where the source is.
+    DestinationIn,
+    /// Keeps the destination where the source is not.
+    DestinationOut,
+    /// Draws the destination on top of the source, only where the source is.
+    DestinationAtop,
+    /// Keeps the source and destination where they do not overlap.
+    Xor,
+    /// Clears the destination.
+    Clear,
+    /// Replaces the destination with the source.
+    Source,
+    /// Adds the source and destination.
+    Plus,
+    /// Multiplies the colors, which darkens.
+    Multiply,
+    /// Inverts, multiplies and inverts the colors, which lightens.
+    Screen,
+    /// Multiplies or screens depending on the destination.
+    Overlay,
+    /// Keeps the darker color.
+    Darken,
+    /// Keeps the lighter color.
+    Lighten,
+    /// Subtracts the darker color from the lighter one.
+    Difference,
+}
+
+impl CompositeOp {
+    /// Returns true if the operator is a blend mode
+    /// instead of a Porter-Duff operator.
+    pub fn is_blend_mode(&self) -> bool {
+        match *self {
+            Multiply | Screen | Overlay | Darken | Lighten | Difference => true,
+            _ => false,
+        }
+    }
+}
+
+/// Combines a source color with a destination color.
+///
+/// Both colors and the result are [r, g, b, a] in straight alpha.
+pub fn composite<Fl: Scalar>(op: CompositeOp, src: [Fl, ..4], dst: [Fl, ..4]) -> [Fl, ..4] {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    let (sa, da) = (clamp(src[3]), clamp(dst[3]));
+    let mut res = [o, ..4];
+    if op.is_blend_mode() {
+        let a = sa + da * (l - sa);
+        if a <= o { return res; }
+        for c in range(0u, 3) {
+            let (s, d) = (clamp(src[c]), clamp(dst[c]));
+            let blended = blend(op, s, d);
+            let premultiplied = s * sa * (l - da) + d * da * (l - sa) + sa * da * blended;
+            res[c] = clamp(premultiplied / a);
+        }
+        res[3] = a;
+        return res;
+    }
+
+    let (fa, fb) = match op {
+        Clear => (o, o),
+        Source => (l, o),
+        SourceIn => (da, o),
+        SourceOut => (l - da, o),
+        SourceAtop => (da, l - sa),
+        DestinationOver => (l - da, l),
+        DestinationIn => (o, sa),
+        DestinationOut => (o, l - sa),
+        DestinationAtop => (l - da, sa),
+        Xor => (l - da, l - sa),
+        Plus => (l, l),
+        _ => (l, l - sa),
+    };
+    let a = (sa * fa + da * fb).min(l);
+    if a <= o { return res; }
+    for c in range(0u, 3) {
+        let premultiplied = clamp(src[c]) * sa * fa + clamp(dst[c]) * da * fb;
+        res[c] = clamp(premultiplied / a);
+    }
+    res[3] = a;
+    res
+}
+
+/// Returns the blended channel of a blend mode.
+fn blend<Fl: Scalar>(op: CompositeOp, s: Fl, d: Fl) -> Fl {
+    let (l, half, two): (Fl, Fl, Fl) =
+        (one(), Scalar::from_f64(0.5), Scalar::from_f64(2.0));
+    match op {
+        Multiply => s * d,
+        Screen => s + d - s * d,
+        Overlay => if d <= half { two * s * d } else { l - two * (l - s) * (l - d) },
+        Darken => s.min(d),
+        Lighten => s.max(d),
+        Difference => (s - d).abs(),
+        _ => s,
+    }
+}
+
+/// Clamps a value to the range 0 to 1.
+#[inline(always)]
+fn clamp<Fl: Scalar>(v: Fl) -> Fl {
+    v.max(zero()).min(one())
+}
+
+#[test]
+fn test_composite() {
+    let red: [f64, ..4] = [1.0, 0.0, 0.0, 1.0];
+    let half_blue: [f64, ..4] = [0.0, 0.0, 1.0, 0.5];
+    assert_eq!(composite(SourceOver, half_blue, red), [0.5, 0.0, 0.5, 1.0]);
+    assert_eq!(composite(DestinationOver, half_blue, red), red);
+    assert_eq!(composite(Clear, half_blue, red), [0.0, 0.0, 0.0, 0.0]);
+    assert_eq!(composite(Source, half_blue, red), half_blue);
+    assert_eq!(composite(Xor, half_blue, red), [1.0, 0.0, 0.0, 0.5]);
+    assert_eq!(composite(DestinationOut, half_blue, red), [1.0, 0.0, 0.0, 0.5]);
+    assert_eq!(composite::<f64>(Multiply, [0.5, 1.0, 1.0, 1.0], [0.5, 0.5, 0.0, 1.0]),
+        [0.25, 0.5, 0.0, 1.0]);
+    assert_eq!(composite::<f64>(Screen, [0.5, 0.0, 1.0, 1.0], [0.5, 0.0, 0.0, 1.0]),
+        [0.75, 0.0, 1.0, 1.0]);
+}
@@ -0,0 +1,521 @@
+
+//! Evaluation and flattening of curves.
+//!
+//! The curve shapes are converted into a list of segments:
+//!
+//! * `Catmull` becomes one cubic segment between each pair of points,
+//! using the neighbour points as tangents, wrapping around.
+//! * `QuadraticBezierBound` becomes one quadratic segment per point,
+//! from the middle of one line to the middle of the next.
+//! * `QuadraticBezierFree` starts at the first point,
+//! followed by groups of two control points and an end point.
+//! This curve is not closed.
+//!
+//! A curve is parameterized from 0 to the number of segments,
+//! where the integer part selects the segment.
+
+use std::cmp::{min, max};
+use std::num::{Float, zero, one};
+
+use graphics::{Shape, Matrix};
+use graphics::{Pixel, PixelHorizontalLine, PixelVerticalLine, PixelLine};
+use graphics::{Line, Rect, RoundRect, Ellipse, Circle, Triangle, Polygon};
+use graphics::{Catmull, QuadraticBezierBound, QuadraticBezierFree};
+use graphics::scalar::Scalar;
+
+/// A part of a curve.
+#[deriving(Clone, Show)]
+pub enum Segment<Fl=f64> {
+    /// A straight line [start, end].
+    LineSegment([[Fl, ..2], ..2]),
+    /// A quadratic Bezier curve [start, control, end].
+    QuadraticSegment([[Fl, ..2], ..3]),
+    /// A cubic Bezier curve [start, control 1, control 2, end].
+    CubicSegment([[Fl, ..2], ..4]),
+}
+
+/// Gauss-Legendre abscissas on the interval 0 to 1.
+static GAUSS_X: [f64, ..5] = [0.04691007703066800, 0.23076534494715845, 0.5,
+                              0.76923465505284155, 0.95308992296933200];
+
+/// Gauss-Legendre weights on the interval 0 to 1.
+static GAUSS_W: [f64, ..5] = [0.11846344252809454, 0.23931433524968324,
+                              0.28444444444444444,
+                              0.23931433524968324, 0.11846344252809454];
+
+/// The number of intervals used when integrating arc length.
+static LENGTH_INTERVALS: uint = 8;
+
+impl<Fl: Scalar> Segment<Fl> {
+    /// Returns the start point.
+    #[inline(always)]
+    pub fn start(&self) -> [Fl, ..2] {
+        match *self {
+            LineSegment(p) => p[0],
+            QuadraticSegment(p) => p[0],
+            CubicSegment(p) => p[0],
+        }
+    }
+
+    /// Returns the end point.
+    #[inline(always)]
+    pub fn end(&self) -> [Fl, ..2] {
+        match *self {
+            LineSegment(p) => p[1],
+            QuadraticSegment(p) => p[2],
+            CubicSegment(p) => p[3],
+        }
+    }
+
+    /// Returns the point at a parameter from 0 to 1.
+    pub fn point(&self, t: Fl) -> [Fl, ..2] {
+        let l: Fl = one();
+        let (two, three): (Fl, Fl) = (Scalar::from_f64(2.0), Scalar::from_f64(3.0));
+        let s = l - t;
+        match *self {
+            LineSegment(p) => combine(&p, &[s, t]),
+            QuadraticSegment(p) => combine(&p, &[s * s, two * s * t, t * t]),
+            CubicSegment(p) => combine(&p,
+                &[s * s * s, three * s * s * t, three * s * t * t, t * t * t]),
+        }
+    }
+
+    /// Returns the derivative at a parameter from 0 to 1.
+    ///
+    /// The length of the tangent is the speed along the segment.
+    pub fn tangent(&self, t: Fl) -> [Fl, ..2] {
+        let l: Fl = one();
+        let (two, three): (Fl, Fl) = (Scalar::from_f64(2.0), Scalar::from_f64(3.0));
+        let s = l - t;
+        match *self {
+            LineSegment(p) => combine(&p, &[-l, l]),
+            QuadraticSegment(p) => combine(&p, &[-two * s, two * (s - t), two * t]),
+            CubicSegment(p) => combine(&p, &[-three * s * s, three * s * (s - two * t),
+                                             three * t * (two * s - t), three * t * t]),
+        }
+    }
+
+    /// Returns the arc length between two parameters.
+    pub fn length_between(&self, t0: Fl, t1: Fl) -> Fl {
+        let mut sum: Fl = zero();
+        let intervals: Fl = Scalar::from_f64(LENGTH_INTERVALS as f64);
+        let h = (t1 - t0) / intervals;
+        for i in range(0u, LENGTH_INTERVALS) {
+            let i: Fl = Scalar::from_f64(i as f64);
+            let a = t0 + h * i;
+            for j in range(0u, 5) {
+                let (x, w): (Fl, Fl) = (Scalar::from_f64(GAUSS_X[j]), Scalar::from_f64(GAUSS_W[j]));
+                let d = self.tangent(a + h * x);
+                sum = sum + w * (d[0] * d[0] + d[1] * d[1]).sqrt();
+            }
+        }
+        sum * h
+    }
+
+    /// Returns the arc length.
+    #[inline(always)]
+    pub fn length(&self) -> Fl {
+        self.length_between(zero(), one())
+    }
+
+    /// Returns the parameter where the arc length from the start is reached.
+    pub fn param_at_length(&self, len: Fl) -> Fl {
+        let (o, l): (Fl, Fl) = (zero(), one());
+        let half: Fl = Scalar::from_f64(0.5);
+        let total = self.length();
+        if len <= o || total <= o { return o; }
+        if len >= total { return l; }
+        let (mut lo, mut hi) = (o, l);
+        for _ in range(0u, 40) {
+            let t = half * (lo + hi);
+            if self.length_between(o, t) < len { lo = t; } else { hi = t; }
+        }
+        half * (lo + hi)
+    }
+
+    /// Returns the number of lines needed to stay within a tolerance,
+    /// after transforming with a matrix.
+    pub fn flatten_count(&self, m: &Matrix<Fl>, tolerance: Fl) -> uint {
+        let (three, four): (Fl, Fl) = (Scalar::from_f64(3.0), Scalar::from_f64(4.0));
+        let min_tolerance: Fl = Scalar::from_f64(1e-6);
+        let tolerance = tolerance.max(min_tolerance);
+        let n = match *self {
+            LineSegment(_) => return 1,
+            QuadraticSegment(p) => {
+                let dd = second_difference(m, p[0], p[1], p[2]);
+                (dd / (four * tolerance)).sqrt()
+            }
+            CubicSegment(p) => {
+                let dd = second_difference(m, p[0], p[1], p[2])
+                    .max(second_difference(m, p[1], p[2], p[3]));
+                (three * dd / (four * tolerance)).sqrt()
+            }
+        };
+        min(max(n.ceil().as_f64() as uint, 1), 1024)
+    }
+
+    /// Returns the segment transformed with a matrix.
+    pub fn transform(&self, m: &Matrix<Fl>) -> Segment<Fl> {
+        let t = |p: [Fl, ..2]| transform(m, p);
+        match *self {
+            LineSegment(p) => LineSegment([t(p[0]), t(p[1])]),
+            QuadraticSegment(p) => QuadraticSegment([t(p[0]), t(p[1]), t(p[2])]),
+            CubicSegment(p) => CubicSegment([t(p[0]), t(p[1]), t(p[2]), t(p[3])]),
+        }
+    }
+
+    /// Returns the tight bounding box [x1, y1, x2, y2].
+    ///
+    /// Checks the end points and the points where the tangent
+    /// is horizontal or vertical.
+    pub fn bounding_box(&self) -> [Fl, ..4] {
+        let (o, l): (Fl, Fl) = (zero(), one());
+        let (two, three, four): (Fl, Fl, Fl) =
+            (Scalar::from_f64(2.0), Scalar::from_f64(3.0), Scalar::from_f64(4.0));
+        let epsilon: Fl = Scalar::from_f64(1e-12);
+        let (a, b) = (self.start(), self.end());
+        let mut res = [a[0].min(b[0]), a[1].min(b[1]), a[0].max(b[0]), a[1].max(b[1])];
+        let mut roots: Vec<Fl> = Vec::new();
+        for k in range(0u, 2) {
+            match *self {
+                LineSegment(_) => {}
+                QuadraticSegment(p) => {
+                    let d = p[0][k] - two * p[1][k] + p[2][k];
+                    if d != o { roots.push((p[0][k] - p[1][k]) / d); }
+                }
+                CubicSegment(p) => {
+                    let a = p[3][k] - three * p[2][k] + three * p[1][k] - p[0][k];
+                    let b = two * (p[0][k] - two * p[1][k] + p[2][k]);
+                    let c = p[1][k] - p[0][k];
+                    if a.abs() < epsilon {
+                        if b != o { roots.push(-c / b); }
+                    } else {
+                        let disc = b * b - four * a * c;
+                        if disc >= o {
+                            let sq = disc.sqrt();
+                            roots.push((-b + sq) / (two * a));
+                            roots.push((-b - sq) / (two * a));
+                        }
+                    }
+                }
+            }
+        }
+        for &t in roots.iter() {
+            if t <= o || t >= l { continue; }
+            let q = self.point(t);
+            res = [res[0].min(q[0]), res[1].min(q[1]), res[2].max(q[0]), res[3].max(q[1])];
+        }
+        res
+    }
+}
+
+/// A sequence of connected segments.
+#[deriving(Clone, Show)]
+pub struct Curve<Fl=f64> {
+    /// The segments in order.
+    pub segments: Vec<Segment<Fl>>,
+    /// Whether the end connects to the start.
+    pub closed: bool,
+}
+
+impl<Fl: Scalar> Curve<Fl> {
+    /// Creates a curve from a shape.
+    ///
+    /// Supports `Line`, `Polygon` and the curve shapes.
+    pub fn from_shape(shape: &Shape<Fl>) -> Option<Curve<Fl>> {
+        let six: Fl = Scalar::from_f64(6.0);
+        match *shape {
+            Line(l) => Some(Curve {
+                segments: vec!(LineSegment([[l[0], l[1]], [l[2], l[3]]])),
+                closed: false,
+            }),
+            Polygon(p) => {
+                let p = points(p);
+                let n = p.len();
+                Some(Curve {
+                    segments: range(0u, n).map(|i|
+                        LineSegment([p.as_slice()[i], p.as_slice()[(i + 1) % n]])
+                    ).collect(),
+                    closed: true,
+                })
+            }
+            Catmull(p) => {
+                let p = points(p);
+                let p = p.as_slice();
+                let n = p.len();
+                Some(Curve {
+                    segments: range(0u, n).map(|i| {
+                        let (p0, p1) = (p[(i + n - 1) % n], p[i]);
+                        let (p2, p3) = (p[(i + 1) % n], p[(i + 2) % n]);
+                        CubicSegment([p1,
+                            [p1[0] + (p2[0] - p0[0]) / six, p1[1] + (p2[1] - p0[1]) / six],
+                            [p2[0] - (p3[0] - p1[0]) / six, p2[1] - (p3[1] - p1[1]) / six],
+                            p2])
+                    }).collect(),
+                    closed: true,
+                })
+            }
+            QuadraticBezierBound(p) => {
+                let p = points(p);
+                let p = p.as_slice();
+                let n = p.len();
+                Some(Curve {
+                    segments: range(0u, n).map(|i| {
+                        let c = p[(i + 1) % n];
+                        QuadraticSegment([mid(p[i], c), c, mid(c, p[(i + 2) % n])])
+                    }).collect(),
+                    closed: true,
+                })
+            }
+            QuadraticBezierFree(p) => {
+                let p = points(p);
+                let p = p.as_slice();
+                let mut segments = Vec::new();
+                let mut i = 0u;
+                while i + 3 < p.len() {
+                    segments.push(CubicSegment([p[i], p[i + 1], p[i + 2], p[i + 3]]));
+                    i += 3;
+                }
+                Some(Curve { segments: segments, closed: false })
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the point at a parameter from 0 to the number of segments.
+    ///
+    /// Returns `None` if the curve has no segments.
+    pub fn point(&self, t: Fl) -> Option<[Fl, ..2]> {
+        self.split(t).map(|(i, u)| self.segments.get(i).point(u))
+    }
+
+    /// Returns the derivative at a parameter from 0 to the number of segments.
+    ///
+    /// Returns `None` if the curve has no segments.
+    pub fn tangent(&self, t: Fl) -> Option<[Fl, ..2]> {
+        self.split(t).map(|(i, u)| self.segments.get(i).tangent(u))
+    }
+
+    /// Returns the arc length of the whole curve.
+    pub fn length(&self) -> Fl {
+        let o: Fl = zero();
+        self.segments.iter().fold(o, |sum, s| sum + s.length())
+    }
+
+    /// Returns the parameter where the arc length from the start is reached.
+    pub fn param_at_length(&self, len: Fl) -> Fl {
+        let mut left = len;
+        for (i, s) in self.segments.iter().enumerate() {
+            let l = s.length();
+            if left <= l {
+                let i: Fl = Scalar::from_f64(i as f64);
+                return i + s.param_at_length(left);
+            }
+            left = left - l;
+        }
+        Scalar::from_f64(self.segments.len() as f64)
+    }
+
+    /// Returns the point at an arc length from the start.
+    ///
+    /// Returns `None` if the curve has no segments.
+    #[inline(always)]
+    pub fn point_at_length(&self, len: Fl) -> Option<[Fl, ..2]> {
+        self.point(self.param_at_length(len))
+    }
+
+    /// Converts the curve into a polyline.
+    ///
+    /// The points are in the curve coordinates, but the tolerance is
+    /// the maximum distance in pixels after transforming with the matrix.
+    /// A closed curve does not repeat the first point at the end.
+    pub fn flatten(&self, m: &Matrix<Fl>, tolerance: Fl) -> Vec<[Fl, ..2]> {
+        let mut res = Vec::new();
+        if self.segments.len() == 0 { return res; }
+        res.push(self.segments.get(0).start());
+        for s in self.segments.iter() {
+            let n = s.flatten_count(m, tolerance);
+            for j in range(1u, n + 1) {
+                res.push(s.point(Scalar::from_f64(j as f64 / n as f64)));
+            }
+        }
+        if self.closed && res.len() > 1 { res.pop(); }
+        res
+    }
+
+    /// Splits a parameter into segment index and local parameter.
+    fn split(&self, t: Fl) -> Option<(uint, Fl)> {
+        let n = self.segments.len();
+        if n == 0 { return None; }
+        let end: Fl = Scalar::from_f64(n as f64);
+        let t = t.max(zero()).min(end);
+        let i = min(t.floor().as_f64() as uint, n - 1);
+        let start: Fl = Scalar::from_f64(i as f64);
+        Some((i, t - start))
+    }
+}
+
+/// Converts a shape into polylines in shape coordinates.
+///
+/// Returns each polyline together with whether it is closed.
+/// The tolerance is the maximum distance in pixels between
+/// round parts and the polyline, after transforming with the matrix.
+/// The one pixel shapes become the rectangles of the pixels they cover,
+/// except `PixelLine` which becomes an open line.
+pub fn outline<Fl: Scalar>(
+    shape: &Shape<Fl>,
+    m: &Matrix<Fl>,
+    tolerance: Fl
+) -> Vec<(Vec<[Fl, ..2]>, bool)> {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    let (half, two): (Fl, Fl) = (Scalar::from_f64(0.5), Scalar::from_f64(2.0));
+    let scale = (m[0] * m[4] - m[1] * m[3]).abs().sqrt();
+    match *shape {
+        Pixel(p) => vec!((vec!([p[0], p[1]], [p[0] + l, p[1]],
+                               [p[0] + l, p[1] + l], [p[0], p[1] + l]), true)),
+        PixelHorizontalLine(r) => vec!((vec!([r[0], r[1]], [r[0] + r[2], r[1]],
+            [r[0] + r[2], r[1] + l], [r[0], r[1] + l]), true)),
+        PixelVerticalLine(r) => vec!((vec!([r[0], r[1]], [r[0] + l, r[1]],
+            [r[0] + l, r[1] + r[2]], [r[0], r[1] + r[2]]), true)),
+        PixelLine(r) => vec!((vec!([r[0], r[1]], [r[2], r[3]]), false)),
+        Line(r) => vec!((vec!([r[0], r[1]], [r[2], r[3]]), false)),
+        Rect(r) => vec!((vec!([r[0], r[1]], [r[0] + r[2], r[1]],
+                              [r[0] + r[2], r[1] + r[3]], [r[0], r[1] + r[3]]), true)),
+        RoundRect(r) => {
+            let radius = r[4].min(half * r[2].abs()).min(half * r[3].abs()).max(o);
+            let n = arc_segments(radius * scale, half, tolerance);
+            let corners = [([r[0] + r[2] - radius, r[1] + radius], -half),
+                           ([r[0] + r[2] - radius, r[1] + r[3] - radius], o),
+                           ([r[0] + radius, r[1] + r[3] - radius], half),
+                           ([r[0] + radius, r[1] + radius], l)];
+            let mut contour = Vec::new();
+            for &(center, start) in corners.iter() {
+                arc(&mut contour, center, [radius, radius], start, half, n);
+            }
+            vec!((contour, true))
+        }
+        Ellipse(e) => {
+            let radius = [half * e[2], half * e[3]];
+            let n = arc_segments(radius[0].abs().max(radius[1].abs()) * scale, two, tolerance);
+            let mut contour = Vec::new();
+            arc(&mut contour, [e[0] + radius[0], e[1] + radius[1]], radius, o, two, n);
+            contour.pop();
+            vec!((contour, true))
+        }
+        Circle(c) => {
+            let n = arc_segments(c[2].abs() * scale, two, tolerance);
+            let mut contour = Vec::new();
+            arc(&mut contour, [c[0], c[1]], [c[2], c[2]], o, two, n);
+            contour.pop();
+            vec!((contour, true))
+        }
+        Triangle(t) => vec!((vec!([t[0], t[1]], [t[2], t[3]], [t[4], t[5]]), true)),
+        Polygon(p) => vec!((points(p), true)),
+        Catmull(_) | QuadraticBezierBound(_) | QuadraticBezierFree(_) => {
+            let curve = Curve::from_shape(shape).unwrap();
+            vec!((curve.flatten(m, tolerance), curve.closed))
+        }
+    }
+}
+
+/// Pushes points along an elliptic arc, including both ends.
+///
+/// Angles are measured in half turns, so 2.0 is a full circle.
+pub fn arc<Fl: Scalar>(
+    contour: &mut Vec<[Fl, ..2]>,
+    center: [Fl, ..2],
+    radius: [Fl, ..2],
+    start: Fl,
+    sweep: Fl,
+    n: uint
+) {
+    let pi: Fl = Float::pi();
+    let count: Fl = Scalar::from_f64(n as f64);
+    for i in range(0u, n + 1) {
+        let i: Fl = Scalar::from_f64(i as f64);
+        let angle = pi * (start + sweep * i / count);
+        contour.push([center[0] + radius[0] * angle.cos(),
+                      center[1] + radius[1] * angle.sin()]);
+    }
+}
+
+/// Returns the number of segments needed for an arc
+/// of a radius in pixels, sweeping half turns.
+pub fn arc_segments<Fl: Scalar>(radius: Fl, sweep: Fl, tolerance: Fl) -> uint {
+    if radius <= tolerance { return 4; }
+    let pi: Fl = Float::pi();
+    let (l, two): (Fl, Fl) = (one(), Scalar::from_f64(2.0));
+    let step = two * (l - tolerance / radius).acos();
+    min(max((sweep * pi / step).ceil().as_f64() as uint, 4), 1024)
+}
+
+/// Transforms a point with a matrix.
+#[inline(always)]
+pub fn transform<Fl: Scalar>(m: &Matrix<Fl>, p: [Fl, ..2]) -> [Fl, ..2] {
+    [m[0] * p[0] + m[1] * p[1] + m[2],
+     m[3] * p[0] + m[4] * p[1] + m[5]]
+}
+
+/// Converts a flat list of coordinates into points.
+pub fn points<Fl: Scalar>(p: &[Fl]) -> Vec<[Fl, ..2]> {
+    range(0u, p.len() / 2).map(|i| [p[i * 2], p[i * 2 + 1]]).collect()
+}
+
+/// Returns the point between two points.
+#[inline(always)]
+fn mid<Fl: Scalar>(a: [Fl, ..2], b: [Fl, ..2]) -> [Fl, ..2] {
+    let half: Fl = Scalar::from_f64(0.5);
+    [half * (a[0] + b[0]), half * (a[1] + b[1])]
+}
+
+/// Returns the weighted sum of points.
+#[inline(always)]
+fn combine<Fl: Scalar>(p: &[[Fl, ..2]], w: &[Fl]) -> [Fl, ..2] {
+    let mut res: [Fl, ..2] = [zero(), zero()];
+    for (q, &w) in p.iter().zip(w.iter()) {
+        res[0] = res[0] + q[0] * w;
+        res[1] = res[1] + q[1] * w;
+    }
+    res
+}
+
+/// Returns the length of the second difference after transforming.
+///
+/// The translation cancels out, so only the linear part is used.
+#[inline(always)]
+fn second_difference<Fl: Scalar>(
+    m: &Matrix<Fl>,
+    a: [Fl, ..2],
+    b: [Fl, ..2],
+    c: [Fl, ..2]
+) -> Fl {
+    let two: Fl = Scalar::from_f64(2.0);
+    let d = [a[0] - two * b[0] + c[0], a[1] - two * b[1] + c[1]];
+    let (x, y) = (m[0] * d[0] + m[1] * d[1], m[3] * d[0] + m[4] * d[1]);
+    (x * x + y * y).sqrt()
+}
+
+#[test]
+fn test_curve() {
+    let identity = [1.0, 0.0, 0.0,
+                    0.0, 1.0, 0.0];
+    let curve: Curve = Curve::from_shape(&QuadraticBezierFree(
+        &[0.0, 0.0, 1.0, 0.0, 2.0, 0.0, 3.0, 0.0])).unwrap();
+    assert_eq!(curve.segments.len(), 1);
+    assert!((curve.length() - 3.0).abs() < 1e-9);
+    assert!((curve.point_at_length(1.5).unwrap()[0] - 1.5).abs() < 1e-6);
+    let empty: Curve = Curve::from_shape(&QuadraticBezierFree(&[0.0, 0.0])).unwrap();
+    assert_eq!(empty.point(0.5), None);
+    assert_eq!(empty.tangent(0.5), None);
+
+    // A square of control points gives a closed curve through the middles.
+    let curve: Curve = Curve::from_shape(&QuadraticBezierBound(
+        &[0.0, 0.0, 10.0, 0.0, 10.0, 10.0, 0.0, 10.0])).unwrap();
+    assert_eq!(curve.point(0.0), Some([5.0, 0.0]));
+    assert_eq!(curve.point(1.0), Some([10.0, 5.0]));
+    let poly = curve.flatten(&identity, 0.1);
+    assert!(poly.len() > 8);
+    for p in poly.iter() {
+        assert!(p[0] >= 0.0 && p[0] <= 10.0 && p[1] >= 0.0 && p[1] <= 10.0);
+    }
+}
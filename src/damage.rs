@@ -0,0 +1,272 @@
+
+//! Tracking of damaged regions for incremental redraw.
+//!
+//! Changes are recorded as boxes [x1, y1, x2, y2] in device coordinates,
+//! rounded outwards to whole units so they cover partly changed pixels.
+//! Before redrawing, boxes are merged when one box costs less than two.
+//! The cost of a box is its area plus a fixed overhead,
+//! which stands for the work of starting a redraw of a region.
+//! A high overhead gives fewer and larger regions.
+
+use std::num::zero;
+
+use graphics::{Shader, Shape, Matrix, Rect};
+use graphics::bounds::{transformed_bounding_box, union};
+use graphics::clip::Clip;
+use graphics::composite::Source;
+use graphics::scalar::Scalar;
+use graphics::scene::{Scene, NodeId, ShapeNode};
+use graphics_new::{Backend, identity, multiply};
+
+/// Implemented by shaders that can restrict drawing to a clip.
+pub trait ClipShader<Fl=f64>: Shader<Fl> {
+    /// Gets a mutable clip in device coordinates.
+    fn get_mut_clip<'a>(&'a mut self) -> &'a mut Clip<Fl>;
+}
+
+/// Records regions that changed between frames.
+#[deriving(Clone, Show)]
+pub struct DamageTracker<Fl=f64> {
+    /// The cost of redrawing a region, in units of area.
+    pub overhead: Fl,
+    /// The maximum number of regions after merging.
+    pub max_regions: uint,
+    regions: Vec<[Fl, ..4]>,
+}
+
+impl<Fl: Scalar> DamageTracker<Fl> {
+    /// Creates a tracker without damage.
+    pub fn new(overhead: Fl, max_regions: uint) -> DamageTracker<Fl> {
+        DamageTracker {
+            overhead: overhead,
+            max_regions: max_regions,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Returns the damaged regions.
+    #[inline(always)]
+    pub fn regions<'a>(&'a self) -> &'a [[Fl, ..4]] {
+        self.regions.as_slice()
+    }
+
+    /// Returns true if nothing changed.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.regions.len() == 0
+    }
+
+    /// Forgets all damage.
+    pub fn clear(&mut self) {
+        self.regions.clear();
+    }
+
+    /// Adds a damaged box [x1, y1, x2, y2].
+    ///
+    /// Empty boxes are ignored.
+    pub fn add_box(&mut self, b: [Fl, ..4]) {
+        if !(b[0] < b[2] && b[1] < b[3]) { return; }
+        self.regions.push([b[0].floor(), b[1].floor(), b[2].ceil(), b[3].ceil()]);
+    }
+
+    /// Adds the bounding box of a shape transformed by a matrix.
+    ///
+    /// The padding is added on every side,
+    /// for example to cover strokes and anti-aliasing.
+    pub fn add_shape(&mut self, shape: &Shape<Fl>, m: &Matrix<Fl>, padding: Fl) {
+        match transformed_bounding_box(shape, m) {
+            None => {}
+            Some(b) => self.add_box([b[0] - padding, b[1] - padding,
+                                     b[2] + padding, b[3] + padding]),
+        }
+    }
+
+    /// Adds the shapes inside a scene node, including hidden ones.
+    ///
+    /// The matrix maps scene coordinates to device coordinates.
+    /// Call this before and after changing a node,
+    /// such that both the old and new area are redrawn.
+    pub fn add_node<P, B, G>(
+        &mut self,
+        scene: &Scene<P, B, G, Fl>,
+        id: NodeId,
+        m: &Matrix<Fl>,
+        padding: Fl
+    ) {
+        let world = match scene.world_transform(id) {
+            None => return,
+            Some(world) => world,
+        };
+        let mut stack = vec!((id, multiply(m, &world)));
+        while stack.len() > 0 {
+            let (id, m) = stack.pop().unwrap();
+            let node = scene.get(id).unwrap();
+            match node.kind {
+                ShapeNode(ref shape, _) => self.add_shape(&shape.as_shape(), &m, padding),
+                _ => {}
+            }
+            for &child in node.children().iter() {
+                let transform = scene.get(child).unwrap().transform;
+                stack.push((child, multiply(&m, &transform)));
+            }
+        }
+    }
+
+    /// Merges regions while it lowers the cost,
+    /// and until there are not more than the maximum number of regions.
+    ///
+    /// The cheapest pair is merged first.
+    /// Every merge compares all pairs of regions,
+    /// so merging n regions takes O(n³) time.
+    pub fn merge(&mut self) {
+        loop {
+            let n = self.regions.len();
+            let mut best: Option<(uint, uint, Fl)> = None;
+            for i in range(0u, n) {
+                for j in range(i + 1, n) {
+                    let cost = merge_cost(self.regions.as_slice()[i],
+                                          self.regions.as_slice()[j], self.overhead);
+                    best = match best {
+                        Some((_, _, best_cost)) if best_cost <= cost => best,
+                        _ => Some((i, j, cost)),
+                    };
+                }
+            }
+            match best {
+                Some((i, j, cost)) if cost <= zero() || n > self.max_regions => {
+                    let b = self.regions.remove(j).unwrap();
+                    let a = *self.regions.get(i);
+                    *self.regions.get_mut(i) = union(Some(a), b);
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Redraws the damaged regions and forgets the damage.
+    ///
+    /// The regions are merged first.
+    /// Each region is replaced with the background color,
+    /// such that hidden shapes disappear and translucent shapes
+    /// are not blended over their old pixels.
+    /// Then the draw function is called with the shader
+    /// clipped to the region, and the region to skip shapes outside it,
+    /// for example with `Scene::shade_region`.
+    /// The clip of the shader is restored afterwards.
+    pub fn redraw<S: ClipShader<Fl> + Backend<Fl>>(
+        &mut self,
+        shader: &mut S,
+        background: [Fl, ..4],
+        draw: |&mut S, [Fl, ..4]|
+    ) {
+        self.merge();
+        let id = identity();
+        for &r in self.regions.iter() {
+            let clip = shader.get_mut_clip().clone();
+            let rect = [r[0], r[1], r[2] - r[0], r[3] - r[1]];
+            let region_clip = clip.intersect_rect(&id, rect);
+            shader.draw(&id, &background, &Source, &region_clip, &Rect(&rect));
+            *shader.get_mut_clip() = region_clip;
+            draw(shader, r);
+            *shader.get_mut_clip() = clip;
+        }
+        self.regions.clear();
+    }
+}
+
+/// Returns true if two boxes [x1, y1, x2, y2] overlap.
+#[inline(always)]
+pub fn intersects<Fl: Scalar>(a: [Fl, ..4], b: [Fl, ..4]) -> bool {
+    a[0] < b[2] && b[0] < a[2] && a[1] < b[3] && b[1] < a[3]
+}
+
+/// Returns the change in cost when merging two regions.
+///
+/// Negative values mean merging is cheaper.
+fn merge_cost<Fl: Scalar>(a: [Fl, ..4], b: [Fl, ..4], overhead: Fl) -> Fl {
+    let overlap = [a[0].max(b[0]), a[1].max(b[1]), a[2].min(b[2]), a[3].min(b[3])];
+    let overlap_area = if intersects(a, b) { area(overlap) } else { zero() };
+    area(union(Some(a), b)) - (area(a) + area(b) - overlap_area) - overhead
+}
+
+/// Returns the area of a box.
+#[inline(always)]
+fn area<Fl: Scalar>(b: [Fl, ..4]) -> Fl {
+    (b[2] - b[0]) * (b[3] - b[1])
+}
+
+#[test]
+fn test_damage() {
+    use graphics::brush::SolidBrush;
+    use graphics::scene::FillEffect;
+    use graphics::raster::RasterBuffer;
+
+    let mut damage: DamageTracker = DamageTracker::new(4.0, 8);
+    damage.add_box([0.0, 0.0, 2.0, 2.0]);
+    damage.add_box([1.5, 0.5, 3.0, 2.0]);
+    damage.add_box([10.0, 10.0, 12.0, 12.0]);
+    damage.add_box([5.0, 5.0, 5.0, 6.0]);
+    damage.merge();
+    assert_eq!(Vec::from_slice(damage.regions()),
+        vec!([0.0, 0.0, 3.0, 2.0], [10.0, 10.0, 12.0, 12.0]));
+    damage.max_regions = 1;
+    damage.merge();
+    assert_eq!(Vec::from_slice(damage.regions()), vec!([0.0, 0.0, 12.0, 12.0]));
+    damage.clear();
+
+    let mut scene: Scene = Scene::new();
+    let root = scene.root();
+    let red = FillEffect(SolidBrush::new([1.0, 0.0, 0.0, 1.0]));
+    let blue = FillEffect(SolidBrush::new([0.0, 0.0, 1.0, 1.0]));
+    scene.add_shape(root, &Rect(&[0.0, 0.0, 8.0, 8.0]), red).unwrap();
+    let b = scene.add_shape(root, &Rect(&[5.0, 5.0, 2.0, 2.0]), blue).unwrap();
+    let mut raster: RasterBuffer = RasterBuffer::new(8, 8);
+    scene.shade(&mut raster);
+    assert_eq!(raster.get_pixel(6, 6), [0, 0, 255, 255]);
+
+    // Only the area of the hidden node is drawn again.
+    damage.add_node(&scene, b, &identity(), 0.0);
+    scene.get_mut(b).unwrap().visible = false;
+    raster.blend_pixel(0, 0, [0.0, 1.0, 0.0, 1.0]);
+    damage.redraw(&mut raster, [0.0, 0.0, 0.0, 1.0], |raster, r| {
+        assert_eq!(r, [5.0, 5.0, 7.0, 7.0]);
+        scene.shade(raster);
+    });
+    assert!(damage.is_empty());
+    assert!(raster.clip.is_unbounded());
+    assert_eq!(raster.get_pixel(6, 6), [255, 0, 0, 255]);
+    assert_eq!(raster.get_pixel(0, 0), [0, 255, 0, 255]);
+
+    // Pixels around the region are not touched.
+    let green = [0.0, 1.0, 0.0, 1.0];
+    for &(x, y) in [(1, 1), (2, 2), (4, 2), (2, 5), (5, 3), (1, 5)].iter() {
+        raster.blend_pixel(x, y, green);
+    }
+    damage.add_box([2.0, 3.0, 4.0, 5.0]);
+    damage.redraw(&mut raster, [0.0, 0.0, 0.0, 1.0],
+        |raster, r| scene.shade_region(raster, r, 0.0));
+    assert_eq!(raster.get_pixel(3, 3), [255, 0, 0, 255]);
+    for &(x, y) in [(1u, 1u), (2, 2), (4, 2), (2, 5), (5, 3), (1, 5)].iter() {
+        assert_eq!(raster.get_pixel(x, y), [0, 255, 0, 255]);
+    }
+
+    // Hidden shapes over a transparent background are cleared,
+    // and translucent shapes are not blended twice.
+    let mut scene: Scene = Scene::new();
+    let root = scene.root();
+    let red = FillEffect(SolidBrush::new([1.0, 0.0, 0.0, 1.0]));
+    let half_blue = FillEffect(SolidBrush::new([0.0, 0.0, 1.0, 0.5]));
+    let a = scene.add_shape(root, &Rect(&[1.0, 1.0, 2.0, 2.0]), red).unwrap();
+    scene.add_shape(root, &Rect(&[5.0, 1.0, 2.0, 2.0]), half_blue).unwrap();
+    let mut raster: RasterBuffer = RasterBuffer::new(8, 8);
+    scene.shade(&mut raster);
+    let translucent = raster.get_pixel(6, 2);
+    damage.add_node(&scene, a, &identity(), 0.0);
+    scene.get_mut(a).unwrap().visible = false;
+    damage.add_box([5.0, 1.0, 7.0, 3.0]);
+    damage.redraw(&mut raster, [0.0, 0.0, 0.0, 0.0],
+        |raster, r| scene.shade_region(raster, r, 0.0));
+    assert_eq!(raster.get_pixel(2, 2), [0, 0, 0, 0]);
+    assert_eq!(raster.get_pixel(6, 2), translucent);
+    assert!(raster.clip.is_unbounded());
+}
@@ -0,0 +1,159 @@
+
+//! Dash patterns splitting strokes into pieces.
+//!
+//! A dash pattern alternates between lengths that are drawn
+//! and lengths that are skipped, measured along the outline.
+//! Curves are flattened before splitting,
+//! so dashes follow curves by arc length.
+//! Each piece is stroked as an open polyline,
+//! which gives every dash the start and end caps of the pen.
+
+use std::num::zero;
+
+use graphics::Shape;
+use graphics::curve::outline;
+use graphics::scalar::Scalar;
+use graphics_new::identity;
+
+/// A dash pattern.
+#[deriving(Clone, Eq, Show)]
+pub struct Dash<Fl=f64> {
+    /// Lengths alternating between drawn and skipped, starting with drawn.
+    ///
+    /// An odd number of lengths is repeated to get an even number.
+    /// A drawn length of zero gives a dot when the pen has round caps.
+    pub array: Vec<Fl>,
+    /// The distance into the pattern at the start of the outline.
+    pub offset: Fl,
+}
+
+impl<Fl: Scalar> Dash<Fl> {
+    /// Creates a dash pattern starting at the beginning.
+    pub fn new(array: Vec<Fl>) -> Dash<Fl> {
+        Dash { array: array, offset: zero() }
+    }
+
+    /// Creates a dotted pattern with dots at a distance from each other.
+    #[inline(always)]
+    pub fn dotted(spacing: Fl) -> Dash<Fl> {
+        Dash::new(vec!(zero(), spacing))
+    }
+
+    /// Returns true if the pattern draws a solid line.
+    ///
+    /// This happens when there are no lengths,
+    /// when a length is negative or when nothing is skipped.
+    pub fn is_solid(&self) -> bool {
+        let o: Fl = zero();
+        let array = self.array.as_slice();
+        let n = array.len();
+        if n == 0 || array.iter().any(|&x| x < o) { return true; }
+        range(0u, 2 * n).filter(|&i| i % 2 == 1).all(|i| array[i % n] == o)
+    }
+}
+
+/// Splits the outline of a shape into dashes.
+///
+/// The tolerance is the maximum error when flattening curves.
+/// Each subpath of the outline starts at the beginning of the pattern.
+pub fn dash_shape<Fl: Scalar>(
+    shape: &Shape<Fl>,
+    dash: &Dash<Fl>,
+    tolerance: Fl
+) -> Vec<Vec<[Fl, ..2]>> {
+    let mut res = Vec::new();
+    for &(ref polyline, closed) in outline(shape, &identity(), tolerance).iter() {
+        res.push_all_move(dash_polyline(polyline.as_slice(), closed, dash));
+    }
+    res
+}
+
+/// Splits a polyline into dashes.
+///
+/// A closed polyline continues the pattern from the last point to the first.
+/// Returns the whole polyline as a single piece if the pattern is solid.
+pub fn dash_polyline<Fl: Scalar>(
+    points: &[[Fl, ..2]],
+    closed: bool,
+    dash: &Dash<Fl>
+) -> Vec<Vec<[Fl, ..2]>> {
+    let n = points.len();
+    if n == 0 { return vec!(); }
+    if dash.is_solid() {
+        let mut whole = Vec::from_slice(points);
+        if closed { whole.push(points[0]); }
+        return vec!(whole);
+    }
+
+    let mut pattern = dash.array.clone();
+    if pattern.len() % 2 == 1 { pattern.push_all(dash.array.as_slice()); }
+    let pattern = pattern.as_slice();
+    let m = pattern.len();
+    let o: Fl = zero();
+    let period = pattern.iter().fold(o, |sum, &x| sum + x);
+
+    // Find where in the pattern the outline starts.
+    // Stopping at a drawn length of zero keeps a dot at the start.
+    let mut i = 0u;
+    let mut left = dash.offset - (dash.offset / period).floor() * period;
+    while left > pattern[i] || (left == pattern[i] && pattern[i] > o) {
+        left = left - pattern[i];
+        i = (i + 1) % m;
+    }
+    left = pattern[i] - left;
+
+    let mut res = Vec::new();
+    let mut piece = if i % 2 == 0 { vec!(points[0]) } else { vec!() };
+    let segments = if closed { n } else { n - 1 };
+    for j in range(0u, segments) {
+        let (a, b) = (points[j], points[(j + 1) % n]);
+        let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+        let len = (dx * dx + dy * dy).sqrt();
+        if len == o { continue; }
+        let mut pos = o;
+        while len - pos >= left {
+            pos = pos + left;
+            let t = pos / len;
+            let p = [a[0] + t * dx, a[1] + t * dy];
+            if i % 2 == 0 {
+                piece.push(p);
+                res.push(piece);
+                piece = vec!();
+            } else {
+                piece = vec!(p);
+            }
+            i = (i + 1) % m;
+            left = pattern[i];
+        }
+        left = left - (len - pos);
+        if i % 2 == 0 { piece.push(b); }
+    }
+    if piece.len() > 0 { res.push(piece); }
+    res
+}
+
+#[test]
+fn test_dash() {
+    let line: [[f64, ..2], ..2] = [[0.0, 0.0], [10.0, 0.0]];
+    let mut dash = Dash::new(vec!(3.0, 1.0));
+    let pieces = dash_polyline(&line, false, &dash);
+    assert_eq!(pieces, vec!(
+        vec!([0.0, 0.0], [3.0, 0.0]),
+        vec!([4.0, 0.0], [7.0, 0.0]),
+        vec!([8.0, 0.0], [10.0, 0.0])));
+
+    dash.offset = 2.0;
+    let pieces = dash_polyline(&line, false, &dash);
+    assert_eq!(pieces.get(0), &vec!([0.0, 0.0], [1.0, 0.0]));
+    assert_eq!(pieces.get(1), &vec!([2.0, 0.0], [5.0, 0.0]));
+
+    // Dashes continue around corners.
+    let corner: [[f64, ..2], ..3] = [[0.0, 0.0], [2.0, 0.0], [2.0, 2.0]];
+    let pieces = dash_polyline(&corner, false, &Dash::new(vec!(3.0)));
+    assert_eq!(pieces.get(0), &vec!([0.0, 0.0], [2.0, 0.0], [2.0, 1.0]));
+
+    // Dots are pieces of zero length.
+    let pieces = dash_polyline(&line, false, &Dash::dotted(5.0));
+    assert_eq!(pieces.len(), 3);
+    assert_eq!(pieces.get(1), &vec!([5.0, 0.0], [5.0, 0.0]));
+}
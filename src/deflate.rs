@@ -0,0 +1,406 @@
+
+//! Deflate compression as used by zlib streams and PNG images.
+//!
+//! The compressor finds repeated strings with hash chains
+//! and writes them in a single block with the fixed Huffman codes.
+//! The decompressor reads stored blocks and blocks with fixed
+//! or dynamic Huffman codes, so it reads streams of any encoder.
+
+/// The largest distance back to a repeated string.
+static WINDOW: uint = 32768;
+
+/// The shortest repeated string that is encoded as a match.
+static MIN_MATCH: uint = 3;
+
+/// The longest repeated string that is encoded as a match.
+static MAX_MATCH: uint = 258;
+
+/// The number of earlier positions tried when looking for a match.
+static MAX_CHAIN: uint = 64;
+
+/// The number of bits in the hash of three bytes.
+static HASH_BITS: uint = 15;
+
+/// The shortest lengths of length codes 257 to 285.
+static LENGTH_BASE: [uint, ..29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+
+/// The number of extra bits of length codes 257 to 285.
+static LENGTH_EXTRA: [uint, ..29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+
+/// The shortest distances of distance codes 0 to 29.
+static DIST_BASE: [uint, ..30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+
+/// The number of extra bits of distance codes 0 to 29.
+static DIST_EXTRA: [uint, ..30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// The order of code length codes in a dynamic block header.
+static CODE_LENGTH_ORDER: [uint, ..19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4,
+    12, 3, 13, 2, 14, 1, 15];
+
+/// Compresses data into a raw deflate stream.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter { out: Vec::new(), bits: 0, count: 0 };
+    // A final block with fixed Huffman codes.
+    w.write(1, 1);
+    w.write(1, 2);
+
+    let n = data.len();
+    let mut head = Vec::from_elem(1 << HASH_BITS, -1i);
+    let mut prev = Vec::from_elem(n, -1i);
+    let mut i = 0u;
+    while i < n {
+        let (mut best_len, mut best_dist) = (0u, 0u);
+        if i + MIN_MATCH <= n {
+            let h = hash(data, i);
+            let mut candidate = *head.get(h);
+            let mut chain = 0u;
+            while candidate >= 0 && chain < MAX_CHAIN {
+                let c = candidate as uint;
+                if i - c > WINDOW { break; }
+                let max_len = if n - i < MAX_MATCH { n - i } else { MAX_MATCH };
+                let mut len = 0u;
+                while len < max_len && data[c + len] == data[i + len] { len += 1; }
+                if len > best_len {
+                    best_len = len;
+                    best_dist = i - c;
+                    if len == max_len { break; }
+                }
+                candidate = *prev.get(c);
+                chain += 1;
+            }
+        }
+        let step = if best_len >= MIN_MATCH {
+            write_length(&mut w, best_len);
+            write_distance(&mut w, best_dist);
+            best_len
+        } else {
+            write_literal(&mut w, data[i] as uint);
+            1
+        };
+        for j in range(i, i + step) {
+            if j + MIN_MATCH > n { break; }
+            let h = hash(data, j);
+            *prev.get_mut(j) = *head.get(h);
+            *head.get_mut(h) = j as int;
+        }
+        i += step;
+    }
+    write_literal(&mut w, 256);
+    w.flush();
+    w.out
+}
+
+/// Decompresses a raw deflate stream.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, ~str> {
+    let (out, _) = try!(inflate(data));
+    Ok(out)
+}
+
+/// Compresses data into a zlib stream.
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec!(0x78u8, 0x01);
+    out.push_all_move(compress(data));
+    let check = adler32(data);
+    for shift in [24u, 16, 8, 0].iter() {
+        out.push((check >> *shift) as u8);
+    }
+    out
+}
+
+/// Decompresses a zlib stream and verifies its checksum.
+pub fn zlib_decompress(data: &[u8]) -> Result<Vec<u8>, ~str> {
+    if data.len() < 6 { return Err(~"Unexpected end of zlib stream"); }
+    let (cmf, flg) = (data[0] as uint, data[1] as uint);
+    if cmf & 0x0f != 8 || (cmf * 256 + flg) % 31 != 0 {
+        return Err(~"Invalid zlib header");
+    }
+    if flg & 0x20 != 0 { return Err(~"Preset zlib dictionaries are not supported"); }
+    let (out, used) = try!(inflate(data.slice_from(2)));
+    let end = 2 + used;
+    if data.len() < end + 4 { return Err(~"Unexpected end of zlib stream"); }
+    let check = data.slice(end, end + 4).iter().fold(0u32, |sum, &b| (sum << 8) | b as u32);
+    if check != adler32(out.as_slice()) { return Err(~"Wrong zlib checksum"); }
+    Ok(out)
+}
+
+/// Returns the Adler-32 checksum used by zlib.
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &v in data.iter() {
+        a = (a + v as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Writes bits starting with the least significant bit of each byte.
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: uint,
+}
+
+impl BitWriter {
+    /// Writes the lowest bits of a value, least significant first.
+    fn write(&mut self, value: uint, bits: uint) {
+        self.bits |= (value as u32) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, most significant bit first.
+    fn write_code(&mut self, code: uint, bits: uint) {
+        let mut reversed = 0;
+        for k in range(0u, bits) {
+            reversed |= ((code >> k) & 1) << (bits - 1 - k);
+        }
+        self.write(reversed, bits);
+    }
+
+    /// Writes the remaining bits padded to a whole byte.
+    fn flush(&mut self) {
+        if self.count > 0 { self.out.push(self.bits as u8); }
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+/// Returns the hash of the three bytes at a position.
+#[inline(always)]
+fn hash(data: &[u8], i: uint) -> uint {
+    let v = (data[i] as uint << 16) | (data[i + 1] as uint << 8) | data[i + 2] as uint;
+    (v * 2654435761) >> (32 - HASH_BITS) & ((1 << HASH_BITS) - 1)
+}
+
+/// Writes a literal or length symbol with the fixed codes.
+fn write_literal(w: &mut BitWriter, symbol: uint) {
+    match symbol {
+        0..143 => w.write_code(0x30 + symbol, 8),
+        144..255 => w.write_code(0x190 + symbol - 144, 9),
+        256..279 => w.write_code(symbol - 256, 7),
+        _ => w.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+/// Writes the length of a match with the fixed codes.
+fn write_length(w: &mut BitWriter, len: uint) {
+    let mut code = 28;
+    while LENGTH_BASE[code] > len { code -= 1; }
+    write_literal(w, 257 + code);
+    w.write(len - LENGTH_BASE[code], LENGTH_EXTRA[code]);
+}
+
+/// Writes the distance of a match with the fixed codes.
+fn write_distance(w: &mut BitWriter, dist: uint) {
+    let mut code = 29;
+    while DIST_BASE[code] > dist { code -= 1; }
+    w.write_code(code, 5);
+    w.write(dist - DIST_BASE[code], DIST_EXTRA[code]);
+}
+
+/// Reads bits starting with the least significant bit of each byte.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: uint,
+    bits: uint,
+    count: uint,
+}
+
+impl<'a> BitReader<'a> {
+    /// Reads a number of bits, least significant first.
+    fn read(&mut self, bits: uint) -> Result<uint, ~str> {
+        while self.count < bits {
+            if self.pos >= self.data.len() { return Err(~"Unexpected end of deflate stream"); }
+            self.bits |= self.data[self.pos] as uint << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.bits & ((1 << bits) - 1);
+        self.bits >>= bits;
+        self.count -= bits;
+        Ok(value)
+    }
+
+    /// Skips the remaining bits of the current byte.
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code.
+struct Huffman {
+    /// The number of codes of each length.
+    counts: [uint, ..16],
+    /// The symbols sorted by code.
+    symbols: Vec<uint>,
+}
+
+impl Huffman {
+    /// Creates a code from the code length of each symbol.
+    fn new(lengths: &[uint]) -> Huffman {
+        let mut counts = [0u, ..16];
+        for &len in lengths.iter() { counts[len] += 1; }
+        counts[0] = 0;
+        let mut offsets = [0u, ..16];
+        for len in range(1u, 15) { offsets[len + 1] = offsets[len] + counts[len]; }
+        let mut symbols = Vec::from_elem(lengths.len(), 0u);
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len == 0 { continue; }
+            *symbols.get_mut(offsets[len]) = symbol;
+            offsets[len] += 1;
+        }
+        Huffman { counts: counts, symbols: symbols }
+    }
+
+    /// Reads a symbol.
+    fn decode(&self, r: &mut BitReader) -> Result<uint, ~str> {
+        let (mut code, mut first, mut index) = (0u, 0u, 0u);
+        for len in range(1u, 16) {
+            code |= try!(r.read(1));
+            let count = self.counts[len];
+            if code < first + count { return Ok(*self.symbols.get(index + code - first)); }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(~"Invalid Huffman code")
+    }
+}
+
+/// Decompresses a raw deflate stream,
+/// returning the data and the number of bytes used.
+fn inflate(data: &[u8]) -> Result<(Vec<u8>, uint), ~str> {
+    let mut r = BitReader { data: data, pos: 0, bits: 0, count: 0 };
+    let mut out = Vec::new();
+    loop {
+        let last = try!(r.read(1)) == 1;
+        match try!(r.read(2)) {
+            0 => {
+                r.align();
+                if r.pos + 4 > data.len() { return Err(~"Unexpected end of deflate stream"); }
+                let len = data[r.pos] as uint | data[r.pos + 1] as uint << 8;
+                let nlen = data[r.pos + 2] as uint | data[r.pos + 3] as uint << 8;
+                if len != !nlen & 0xffff { return Err(~"Invalid stored block length"); }
+                r.pos += 4;
+                if r.pos + len > data.len() { return Err(~"Unexpected end of deflate stream"); }
+                out.push_all(data.slice(r.pos, r.pos + len));
+                r.pos += len;
+            }
+            1 => {
+                let mut lengths = Vec::from_elem(288, 8u);
+                for i in range(144u, 256) { *lengths.get_mut(i) = 9; }
+                for i in range(256u, 280) { *lengths.get_mut(i) = 7; }
+                let literals = Huffman::new(lengths.as_slice());
+                let distances = Huffman::new(Vec::from_elem(30, 5u).as_slice());
+                try!(inflate_block(&mut r, &mut out, &literals, &distances));
+            }
+            2 => {
+                let (literals, distances) = try!(read_dynamic_codes(&mut r));
+                try!(inflate_block(&mut r, &mut out, &literals, &distances));
+            }
+            _ => return Err(~"Invalid deflate block type"),
+        }
+        if last { break; }
+    }
+    Ok((out, r.pos))
+}
+
+/// Reads the Huffman codes at the start of a dynamic block.
+fn read_dynamic_codes(r: &mut BitReader) -> Result<(Huffman, Huffman), ~str> {
+    let literal_count = try!(r.read(5)) + 257;
+    let distance_count = try!(r.read(5)) + 1;
+    let code_length_count = try!(r.read(4)) + 4;
+    let mut code_lengths = [0u, ..19];
+    for i in range(0u, code_length_count) {
+        code_lengths[CODE_LENGTH_ORDER[i]] = try!(r.read(3));
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+    let n = literal_count + distance_count;
+    let mut lengths: Vec<uint> = Vec::new();
+    while lengths.len() < n {
+        let symbol = try!(code_length_code.decode(r));
+        let (value, repeat) = match symbol {
+            0..15 => (symbol, 1),
+            16 => match lengths.last() {
+                None => return Err(~"Repeated code length without previous length"),
+                Some(&prev) => (prev, 3 + try!(r.read(2))),
+            },
+            17 => (0, 3 + try!(r.read(3))),
+            _ => (0, 11 + try!(r.read(7))),
+        };
+        if lengths.len() + repeat > n { return Err(~"Too many code lengths"); }
+        for _ in range(0u, repeat) { lengths.push(value); }
+    }
+    let lengths = lengths.as_slice();
+    Ok((Huffman::new(lengths.slice_to(literal_count)),
+        Huffman::new(lengths.slice_from(literal_count))))
+}
+
+/// Reads the symbols of a block with Huffman codes until the end of the block.
+fn inflate_block(
+    r: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman
+) -> Result<(), ~str> {
+    loop {
+        let symbol = try!(literals.decode(r));
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 { return Ok(()); }
+        let code = symbol - 257;
+        if code >= 29 { return Err(~"Invalid length code"); }
+        let len = LENGTH_BASE[code] + try!(r.read(LENGTH_EXTRA[code]));
+        let code = try!(distances.decode(r));
+        if code >= 30 { return Err(~"Invalid distance code"); }
+        let dist = DIST_BASE[code] + try!(r.read(DIST_EXTRA[code]));
+        if dist > out.len() { return Err(~"Distance too far back"); }
+        let start = out.len() - dist;
+        for i in range(start, start + len) {
+            let v = *out.get(i);
+            out.push(v);
+        }
+    }
+}
+
+#[test]
+fn test_deflate() {
+    let text = bytes!("Hello, hello, hello! The quick brown fox jumps over the lazy dog.");
+    let mut data = Vec::new();
+    for i in range(0u, 1000) {
+        data.push_all(text);
+        data.push((i % 7) as u8);
+    }
+    let compressed = zlib_compress(data.as_slice());
+    assert!(compressed.len() < data.len() / 10);
+    assert_eq!(zlib_decompress(compressed.as_slice()), Ok(data.clone()));
+    assert_eq!(decompress(compress(&[]).as_slice()), Ok(Vec::new()));
+
+    // Written by zlib with a dynamic block.
+    let dynamic = [0x78u8, 0xda, 0x85, 0xd2, 0xc1, 0x09, 0x84, 0x40, 0x14, 0x04, 0xd1, 0x54,
+        0x7e, 0x00, 0x1e, 0xec, 0x56, 0x77, 0x35, 0x1c, 0x85, 0x11, 0x0f, 0x83, 0x03, 0xbb,
+        0x03, 0xa6, 0x6f, 0x06, 0xbf, 0xce, 0x75, 0x7b, 0xd4, 0x18, 0x47, 0xeb, 0xbd, 0x96,
+        0x7f, 0xb4, 0x33, 0x8e, 0x52, 0x7e, 0xd1, 0xee, 0xe8, 0x57, 0x89, 0x67, 0xaf, 0x75,
+        0x08, 0xe5, 0xd9, 0x79, 0x9e, 0xf2, 0x3c, 0xe7, 0x79, 0xc9, 0xf3, 0x27, 0xcf, 0xdf,
+        0x3c, 0xaf, 0x79, 0xde, 0x80, 0x65, 0x84, 0x0e, 0x6e, 0x02, 0x38, 0x81, 0x9c, 0x80,
+        0x4e, 0x60, 0x27, 0xc0, 0x13, 0xe8, 0x09, 0xf8, 0x04, 0x7e, 0x06, 0x3f, 0xd3, 0x77,
+        0xe0, 0x67, 0xf0, 0x33, 0xf8, 0x19, 0xfc, 0x0c, 0x7e, 0x06, 0x3f, 0x83, 0x9f, 0xc1,
+        0xef, 0x05, 0xdd, 0x7e, 0x3e, 0xc1];
+    let mut expected = Vec::new();
+    for i in range(0u, 30) {
+        expected.push_all(format!("{} bottles of beer on the wall, ", i).as_bytes());
+    }
+    assert_eq!(zlib_decompress(&dynamic), Ok(expected));
+    assert!(zlib_decompress(&[0x78u8, 0xda, 0x07, 0x00, 0x00, 0x00]).is_err());
+}
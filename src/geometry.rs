@@ -0,0 +1,476 @@
+
+//! Boolean operations on polygons.
+//!
+//! A polygon set is made of closed contours, filled with the nonzero winding rule,
+//! so self-intersecting polygons and several contours are allowed as input.
+//! The results of boolean operations have outer contours counterclockwise
+//! and holes clockwise, when the y axis points up.
+//!
+//! The operations split all edges where they intersect,
+//! keep the edges separating the inside of the result from the outside,
+//! and link them into new contours.
+//! Points closer than `EPSILON` are treated as the same point.
+
+use std::cmp::{min, max};
+
+use graphics::{Shader, Polygon};
+use graphics::bounds::winding;
+
+/// The distance below which two points are treated as the same.
+pub static EPSILON: f64 = 1e-9;
+
+/// The boolean operation to apply on two polygon sets.
+#[deriving(Clone, Eq, Show)]
+pub enum BooleanOp {
+    /// Points inside either set.
+    Union,
+    /// Points inside both sets.
+    Intersection,
+    /// Points inside the first set, but not the second.
+    Difference,
+    /// Points inside exactly one of the sets.
+    Xor,
+}
+
+impl BooleanOp {
+    /// Returns whether a point is inside the result,
+    /// given whether it is inside each set.
+    #[inline(always)]
+    pub fn apply(&self, a: bool, b: bool) -> bool {
+        match *self {
+            Union => a || b,
+            Intersection => a && b,
+            Difference => a && !b,
+            Xor => a != b,
+        }
+    }
+}
+
+/// Closed contours filled with the nonzero winding rule.
+#[deriving(Clone, Eq, Show)]
+pub struct PolygonSet {
+    /// The contours, without repeating the first point at the end.
+    pub contours: Vec<Vec<[f64, ..2]>>,
+}
+
+impl PolygonSet {
+    /// Creates an empty polygon set.
+    pub fn new() -> PolygonSet {
+        PolygonSet { contours: Vec::new() }
+    }
+
+    /// Creates a polygon set from the coordinates of a `Polygon` shape [x1, y1, ...].
+    pub fn from_polygon(polygon: &[f64]) -> PolygonSet {
+        let mut res = PolygonSet::new();
+        res.add_polygon(polygon);
+        res
+    }
+
+    /// Adds a contour from the coordinates of a `Polygon` shape [x1, y1, ...].
+    pub fn add_polygon(&mut self, polygon: &[f64]) {
+        let contour = range(0u, polygon.len() / 2)
+            .map(|i| [polygon[i * 2], polygon[i * 2 + 1]]).collect();
+        self.contours.push(contour);
+    }
+
+    /// Returns true if a point is inside the set.
+    pub fn contains(&self, p: [f64, ..2]) -> bool {
+        let mut sum = 0;
+        for contour in self.contours.iter() {
+            sum += winding(contour.as_slice(), p);
+        }
+        sum != 0
+    }
+
+    /// Returns the signed area, where holes count negative.
+    ///
+    /// This is the area of the set when the contours do not overlap,
+    /// which is the case for results of boolean operations.
+    pub fn area(&self) -> f64 {
+        self.contours.iter().fold(0.0, |sum, c| sum + signed_area(c.as_slice()))
+    }
+
+    /// Returns the union with another set.
+    #[inline(always)]
+    pub fn union(&self, other: &PolygonSet) -> PolygonSet {
+        boolean(Union, self, other)
+    }
+
+    /// Returns the intersection with another set.
+    #[inline(always)]
+    pub fn intersection(&self, other: &PolygonSet) -> PolygonSet {
+        boolean(Intersection, self, other)
+    }
+
+    /// Returns this set with another set subtracted.
+    #[inline(always)]
+    pub fn difference(&self, other: &PolygonSet) -> PolygonSet {
+        boolean(Difference, self, other)
+    }
+
+    /// Returns the points inside exactly one of the sets.
+    #[inline(always)]
+    pub fn xor(&self, other: &PolygonSet) -> PolygonSet {
+        boolean(Xor, self, other)
+    }
+
+    /// Returns the coordinates of polygons without holes [x1, y1, ...],
+    /// which can be drawn with `Polygon`.
+    ///
+    /// Each hole is joined with the contour around it through a horizontal cut,
+    /// which does not change the filled area.
+    /// Expects outer contours and holes with opposite orientation,
+    /// as returned by boolean operations.
+    pub fn to_polygons(&self) -> Vec<Vec<f64>> {
+        let mut outers: Vec<(Vec<[f64, ..2]>, f64)> = Vec::new();
+        let mut holes: Vec<&Vec<[f64, ..2]>> = Vec::new();
+        for contour in self.contours.iter() {
+            let area = signed_area(contour.as_slice());
+            if area > 0.0 { outers.push((contour.clone(), area)); }
+            else if area < 0.0 { holes.push(contour); }
+        }
+
+        // Join holes from right to left, so cuts never cross a hole joined later.
+        holes.as_mut_slice().sort_by(|a, b| {
+            let ((_, xa), (_, xb)) = (max_x(a.as_slice()), max_x(b.as_slice()));
+            if xa > xb { Less } else if xa < xb { Greater } else { Equal }
+        });
+        for hole in holes.iter() {
+            let p = hole.as_slice()[0];
+            let mut parent = None;
+            for (i, &(ref outer, area)) in outers.iter().enumerate() {
+                if winding(outer.as_slice(), p) == 0 { continue; }
+                match parent {
+                    Some((_, best)) if best <= area => {}
+                    _ => parent = Some((i, area)),
+                }
+            }
+            match parent {
+                None => {}
+                Some((i, _)) => match *outers.get_mut(i) {
+                    (ref mut outer, _) => join_hole(outer, hole.as_slice()),
+                },
+            }
+        }
+
+        outers.iter().map(|&(ref outer, _)| {
+            let mut res = Vec::with_capacity(outer.len() * 2);
+            for p in outer.iter() {
+                res.push(p[0]);
+                res.push(p[1]);
+            }
+            res
+        }).collect()
+    }
+
+    /// Draws the set as polygons with a shader.
+    pub fn shade<S: Shader>(&self, shader: &mut S) {
+        for polygon in self.to_polygons().iter() {
+            shader.shade(&Polygon(polygon.as_slice()));
+        }
+    }
+}
+
+/// Applies a boolean operation on two polygon sets.
+pub fn boolean(op: BooleanOp, a: &PolygonSet, b: &PolygonSet) -> PolygonSet {
+    let mut segments = Vec::new();
+    for set in [a, b].iter() {
+        for contour in set.contours.iter() {
+            let c = contour.as_slice();
+            let n = c.len();
+            for i in range(0u, n) {
+                let (p, q) = (c[i], c[(i + 1) % n]);
+                if distance(p, q) > EPSILON { segments.push((p, q)); }
+            }
+        }
+    }
+
+    // Collect the points where each segment is split.
+    let n = segments.len();
+    let mut splits: Vec<Vec<[f64, ..2]>> = Vec::from_fn(n, |_| Vec::new());
+    for i in range(0u, n) {
+        for j in range(i + 1, n) {
+            let (p, q) = (*segments.get(i), *segments.get(j));
+            for &point in intersections(p, q).iter() {
+                splits.get_mut(i).push(point);
+                splits.get_mut(j).push(point);
+            }
+        }
+    }
+
+    // Split segments into edges between shared vertices.
+    let mut vertices: Vec<[f64, ..2]> = Vec::new();
+    let mut edges: Vec<(uint, uint)> = Vec::new();
+    for (i, &(p, q)) in segments.iter().enumerate() {
+        let mut points = splits.get(i).clone();
+        points.push(p);
+        points.push(q);
+        points.as_mut_slice().sort_by(|a, b| {
+            let (ta, tb) = (project(p, q, *a), project(p, q, *b));
+            if ta < tb { Less } else if ta > tb { Greater } else { Equal }
+        });
+        let ids: Vec<uint> = points.iter().map(|&r| vertex(&mut vertices, r)).collect();
+        for k in range(1u, ids.len()) {
+            let (u, v) = (*ids.get(k - 1), *ids.get(k));
+            if u != v { edges.push((min(u, v), max(u, v))); }
+        }
+    }
+    edges.as_mut_slice().sort();
+    edges.dedup();
+
+    // Keep edges with the inside of the result on one side only,
+    // oriented with the inside on the left.
+    let mut kept: Vec<(uint, uint)> = Vec::new();
+    for &(u, v) in edges.iter() {
+        let (p, q) = (*vertices.get(u), *vertices.get(v));
+        let len = distance(p, q);
+        let offset = 1e-6 * len;
+        let normal = [-(q[1] - p[1]) / len * offset, (q[0] - p[0]) / len * offset];
+        let mid = [0.5 * (p[0] + q[0]), 0.5 * (p[1] + q[1])];
+        let left = [mid[0] + normal[0], mid[1] + normal[1]];
+        let right = [mid[0] - normal[0], mid[1] - normal[1]];
+        let inside_left = op.apply(a.contains(left), b.contains(left));
+        let inside_right = op.apply(a.contains(right), b.contains(right));
+        if inside_left && !inside_right { kept.push((u, v)); }
+        else if inside_right && !inside_left { kept.push((v, u)); }
+    }
+
+    link(vertices.as_slice(), kept.as_slice())
+}
+
+/// Links directed edges into closed contours.
+///
+/// Where several edges leave a vertex, the one turning most to the left is taken,
+/// which keeps regions touching at a single point apart.
+fn link(vertices: &[[f64, ..2]], edges: &[(uint, uint)]) -> PolygonSet {
+    let mut used = Vec::from_elem(edges.len(), false);
+    let mut res = PolygonSet::new();
+    for start in range(0u, edges.len()) {
+        if *used.get(start) { continue; }
+        *used.get_mut(start) = true;
+        let (first, mut current) = edges[start];
+        let mut prev = first;
+        let mut contour = vec!(vertices[first]);
+        let mut closed = false;
+        loop {
+            if current == first { closed = true; break; }
+            contour.push(vertices[current]);
+            let incoming = direction(vertices[prev], vertices[current]);
+            let mut best: Option<(uint, f64)> = None;
+            for (k, &(u, v)) in edges.iter().enumerate() {
+                if u != current || *used.get(k) { continue; }
+                let out = direction(vertices[u], vertices[v]);
+                let turn = (incoming[0] * out[1] - incoming[1] * out[0])
+                    .atan2(incoming[0] * out[0] + incoming[1] * out[1]);
+                match best {
+                    Some((_, t)) if t >= turn => {}
+                    _ => best = Some((k, turn)),
+                }
+            }
+            match best {
+                None => break,
+                Some((k, _)) => {
+                    *used.get_mut(k) = true;
+                    let (_, next) = edges[k];
+                    prev = current;
+                    current = next;
+                }
+            }
+        }
+        if closed {
+            let contour = remove_collinear(contour);
+            if contour.len() >= 3 { res.contours.push(contour); }
+        }
+    }
+    res
+}
+
+/// Returns the points where two segments touch or cross.
+///
+/// Overlapping parallel segments touch at the end points inside the other segment.
+fn intersections(
+    (p1, p2): ([f64, ..2], [f64, ..2]),
+    (q1, q2): ([f64, ..2], [f64, ..2])
+) -> Vec<[f64, ..2]> {
+    let r = [p2[0] - p1[0], p2[1] - p1[1]];
+    let s = [q2[0] - q1[0], q2[1] - q1[1]];
+    let qp = [q1[0] - p1[0], q1[1] - p1[1]];
+    let denom = cross(r, s);
+    let mut res = Vec::new();
+    if denom.abs() <= EPSILON * length(r) * length(s) {
+        // Parallel, only touching when on the same line.
+        if cross(qp, r).abs() > EPSILON * length(r) { return res; }
+        for &(point, a, b) in [(q1, p1, p2), (q2, p1, p2), (p1, q1, q2), (p2, q1, q2)].iter() {
+            if on_segment(point, a, b) { res.push(point); }
+        }
+        return res;
+    }
+    let t = cross(qp, s) / denom;
+    let u = cross(qp, r) / denom;
+    if t < -EPSILON || t > 1.0 + EPSILON || u < -EPSILON || u > 1.0 + EPSILON { return res; }
+    // Prefer existing end points to keep vertices exact.
+    let lp = length(r);
+    let lq = length(s);
+    res.push(if t * lp <= EPSILON { p1 }
+        else if (1.0 - t) * lp <= EPSILON { p2 }
+        else if u * lq <= EPSILON { q1 }
+        else if (1.0 - u) * lq <= EPSILON { q2 }
+        else { [p1[0] + t * r[0], p1[1] + t * r[1]] });
+    res
+}
+
+/// Returns true if a point lies on a segment, including the end points.
+fn on_segment(p: [f64, ..2], a: [f64, ..2], b: [f64, ..2]) -> bool {
+    let d = [b[0] - a[0], b[1] - a[1]];
+    let len2 = d[0] * d[0] + d[1] * d[1];
+    if len2 == 0.0 { return distance(p, a) <= EPSILON; }
+    let t = ((p[0] - a[0]) * d[0] + (p[1] - a[1]) * d[1]) / len2;
+    t >= 0.0 && t <= 1.0
+}
+
+/// Joins a hole into the contour around it.
+///
+/// A horizontal cut goes from the rightmost point of the hole
+/// to the nearest edge of the contour on the right.
+fn join_hole(outer: &mut Vec<[f64, ..2]>, hole: &[[f64, ..2]]) {
+    let (m, mx) = max_x(hole);
+    let y = hole[m][1];
+    let n = outer.len();
+    let mut best: Option<(uint, f64)> = None;
+    for k in range(0u, n) {
+        let (a, b) = (*outer.get(k), *outer.get((k + 1) % n));
+        if (a[1] <= y) == (b[1] <= y) { continue; }
+        let x = a[0] + (y - a[1]) * (b[0] - a[0]) / (b[1] - a[1]);
+        if x < mx { continue; }
+        match best {
+            Some((_, bx)) if bx <= x => {}
+            _ => best = Some((k, x)),
+        }
+    }
+    let (k, x) = match best {
+        None => return,
+        Some(best) => best,
+    };
+    let cut = [x, y];
+    let mut joined = Vec::with_capacity(n + hole.len() + 3);
+    joined.push_all(outer.as_slice().slice(0, k + 1));
+    joined.push(cut);
+    for i in range(0u, hole.len() + 1) {
+        joined.push(hole[(m + i) % hole.len()]);
+    }
+    joined.push(cut);
+    joined.push_all(outer.as_slice().slice(k + 1, n));
+    *outer = joined;
+}
+
+/// Returns the index and coordinate of the point with largest x.
+fn max_x(contour: &[[f64, ..2]]) -> (uint, f64) {
+    let mut res = (0u, contour[0][0]);
+    for (i, p) in contour.iter().enumerate() {
+        let (_, x) = res;
+        if p[0] > x { res = (i, p[0]); }
+    }
+    res
+}
+
+/// Returns the position of a point along a segment, scaled by its squared length.
+#[inline(always)]
+fn project(a: [f64, ..2], b: [f64, ..2], p: [f64, ..2]) -> f64 {
+    (p[0] - a[0]) * (b[0] - a[0]) + (p[1] - a[1]) * (b[1] - a[1])
+}
+
+/// Removes points lying on a straight line between their neighbours.
+fn remove_collinear(contour: Vec<[f64, ..2]>) -> Vec<[f64, ..2]> {
+    let c = contour.as_slice();
+    let n = c.len();
+    let mut res = Vec::new();
+    for i in range(0u, n) {
+        let (prev, p, next) = (c[(i + n - 1) % n], c[i], c[(i + 1) % n]);
+        let a = [p[0] - prev[0], p[1] - prev[1]];
+        let b = [next[0] - p[0], next[1] - p[1]];
+        let straight = cross(a, b).abs() <= EPSILON * length(a).max(length(b))
+            && a[0] * b[0] + a[1] * b[1] > 0.0;
+        if !straight { res.push(p); }
+    }
+    res
+}
+
+/// Returns the index of a vertex, adding it if there is none close enough.
+fn vertex(vertices: &mut Vec<[f64, ..2]>, p: [f64, ..2]) -> uint {
+    match vertices.iter().position(|&v| distance(v, p) <= EPSILON) {
+        Some(i) => i,
+        None => {
+            vertices.push(p);
+            vertices.len() - 1
+        }
+    }
+}
+
+/// Returns the signed area of a contour, positive when counterclockwise.
+fn signed_area(contour: &[[f64, ..2]]) -> f64 {
+    let n = contour.len();
+    let mut sum = 0.0;
+    for i in range(0u, n) {
+        let (a, b) = (contour[i], contour[(i + 1) % n]);
+        sum += a[0] * b[1] - b[0] * a[1];
+    }
+    0.5 * sum
+}
+
+/// Returns the unit direction from one point to another.
+#[inline(always)]
+fn direction(a: [f64, ..2], b: [f64, ..2]) -> [f64, ..2] {
+    let len = distance(a, b);
+    [(b[0] - a[0]) / len, (b[1] - a[1]) / len]
+}
+
+/// Returns the cross product of two vectors.
+#[inline(always)]
+fn cross(a: [f64, ..2], b: [f64, ..2]) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+/// Returns the length of a vector.
+#[inline(always)]
+fn length(a: [f64, ..2]) -> f64 {
+    (a[0] * a[0] + a[1] * a[1]).sqrt()
+}
+
+/// Returns the distance between two points.
+#[inline(always)]
+fn distance(a: [f64, ..2], b: [f64, ..2]) -> f64 {
+    length([b[0] - a[0], b[1] - a[1]])
+}
+
+#[test]
+fn test_boolean() {
+    let a = PolygonSet::from_polygon(&[0.0, 0.0, 2.0, 0.0, 2.0, 2.0, 0.0, 2.0]);
+    let b = PolygonSet::from_polygon(&[1.0, 1.0, 3.0, 1.0, 3.0, 3.0, 1.0, 3.0]);
+    assert!((a.union(&b).area() - 7.0).abs() < 1e-9);
+    assert!((a.intersection(&b).area() - 1.0).abs() < 1e-9);
+    assert!((a.difference(&b).area() - 3.0).abs() < 1e-9);
+    let xor = a.xor(&b);
+    assert!((xor.area() - 6.0).abs() < 1e-9);
+    assert!(xor.contains([0.5, 0.5]));
+    assert!(!xor.contains([1.5, 1.5]));
+    assert_eq!(a.intersection(&b).contours.get(0).len(), 4);
+
+    // Subtracting an inner square leaves a hole,
+    // which is joined into a single polygon.
+    let outer = PolygonSet::from_polygon(&[0.0, 0.0, 4.0, 0.0, 4.0, 4.0, 0.0, 4.0]);
+    let inner = PolygonSet::from_polygon(&[1.0, 1.0, 3.0, 1.0, 3.0, 3.0, 1.0, 3.0]);
+    let ring = outer.difference(&inner);
+    assert_eq!(ring.contours.len(), 2);
+    assert!((ring.area() - 12.0).abs() < 1e-9);
+    let polygons = ring.to_polygons();
+    assert_eq!(polygons.len(), 1);
+    let joined = PolygonSet::from_polygon(polygons.get(0).as_slice());
+    assert!((joined.area() - 12.0).abs() < 1e-9);
+    assert!(!joined.contains([2.0, 2.0]));
+
+    // A self-intersecting bow tie is cleaned up into two triangles.
+    let bow = PolygonSet::from_polygon(&[0.0, 0.0, 2.0, 2.0, 2.0, 0.0, 0.0, 2.0]);
+    let clean = bow.union(&PolygonSet::new());
+    assert_eq!(clean.contours.len(), 2);
+    assert!((clean.area() - 2.0).abs() < 1e-9);
+}
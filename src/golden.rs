@@ -0,0 +1,336 @@
+
+//! Regression tests comparing rendered images with reference images.
+//!
+//! A test draws a scene of shapes with effects and compares the result
+//! with a PNG image named after the test in a reference directory.
+//! Pixels are compared perceptually: both pixels are put over black
+//! and over white, and the larger distance in OKLab is their difference.
+//! A distance of 0.02 is about the smallest difference people notice,
+//! while black and white are 1 apart.
+//!
+//! When the images differ, the rendered image and a diff image
+//! are written next to the reference as `name.actual.png` and `name.diff.png`.
+//! The diff image shows the reference faded to light gray,
+//! with pixels beyond the tolerance in red
+//! and smaller differences in yellow.
+//!
+//! Missing references are only written in update mode,
+//! which is enabled by setting the environment variable `GOLDEN_UPDATE`.
+//! Update mode also replaces existing references.
+//!
+//! The references of the tests in this file are in the `golden` directory
+//! next to it.
+
+use std::io::File;
+use std::num::Float;
+use std::os;
+
+use graphics::Shape;
+use graphics::advanced_pen::AdvancedPen;
+use graphics::brush::{Brush, SolidBrush};
+use graphics::gradient::ColorGradient;
+use graphics::scene::{Scene, SceneEffect, EffectShader};
+use graphics::raster::RasterBuffer;
+use graphics::color::{Srgb, Oklab};
+use graphics::image::{read_png, write_png};
+
+/// A scene rendered and compared with a reference image.
+pub struct GoldenTest<P=AdvancedPen, B=SolidBrush, G=ColorGradient> {
+    /// The name of the reference image without extension.
+    pub name: ~str,
+    /// The width in pixels.
+    pub width: uint,
+    /// The height in pixels.
+    pub height: uint,
+    /// The color the image is cleared with before drawing.
+    pub background: [f64, ..4],
+    /// The largest difference between pixels considered equal.
+    pub tolerance: f64,
+    /// The number of pixels allowed to differ by more than the tolerance.
+    pub max_differing: uint,
+    /// Whether the reference image is written instead of compared.
+    pub update: bool,
+    /// The shapes to draw.
+    pub scene: Scene<P, B, G>,
+}
+
+impl<P, B, G> GoldenTest<P, B, G> {
+    /// Creates a test with an empty scene on a transparent background.
+    ///
+    /// The tolerance is 0.02 and no pixels may differ by more.
+    /// Update mode is enabled if `GOLDEN_UPDATE` is set.
+    pub fn new(name: &str, width: uint, height: uint) -> GoldenTest<P, B, G> {
+        GoldenTest {
+            name: name.to_owned(),
+            width: width,
+            height: height,
+            background: [0.0, 0.0, 0.0, 0.0],
+            tolerance: 0.02,
+            max_differing: 0,
+            update: os::getenv("GOLDEN_UPDATE").is_some(),
+            scene: Scene::new(),
+        }
+    }
+
+    /// Adds a shape drawn with an effect on top of the others.
+    pub fn add(&mut self, shape: &Shape, effect: SceneEffect<P, B, G>) {
+        let root = self.scene.root();
+        self.scene.add_shape(root, shape, effect);
+    }
+
+    /// Draws the scene with a backend.
+    pub fn render<S: EffectShader<P, B, G>>(&self, backend: &mut S) {
+        self.scene.shade(backend);
+    }
+
+    /// Compares a rendered image with the reference image in a directory.
+    ///
+    /// On mismatch, the rendered image and a diff image are written
+    /// to the directory and an error describes the difference.
+    pub fn check_image(&self, dir: &Path, actual: &RasterBuffer) -> Result<(), ~str> {
+        let reference = dir.join(format!("{}.png", self.name));
+        let actual_path = dir.join(format!("{}.actual.png", self.name));
+        if self.update {
+            return save(actual, &reference);
+        }
+        if !reference.exists() {
+            try!(save(actual, &actual_path));
+            return Err(format!("{}: Missing reference image, \
+                set GOLDEN_UPDATE to write it", reference.display()));
+        }
+        let data = match File::open(&reference).read_to_end() {
+            Ok(data) => data,
+            Err(err) => return Err(format!("{}: {}", reference.display(), err)),
+        };
+        let expected = match read_png(data.as_slice()) {
+            Ok(expected) => expected,
+            Err(err) => return Err(format!("{}: {}", reference.display(), err)),
+        };
+        let comparison = match compare(actual, &expected, self.tolerance) {
+            Ok(comparison) => comparison,
+            Err(err) => {
+                try!(save(actual, &actual_path));
+                return Err(format!("{}: {}", reference.display(), err));
+            }
+        };
+        if comparison.differing <= self.max_differing { return Ok(()); }
+        let diff_path = dir.join(format!("{}.diff.png", self.name));
+        try!(save(actual, &actual_path));
+        try!(save(&comparison.diff, &diff_path));
+        Err(format!("{}: {} pixels differ by more than {}, up to {}, see {}",
+            reference.display(), comparison.differing, self.tolerance,
+            comparison.max_difference, diff_path.display()))
+    }
+}
+
+impl<B: Brush + Clone + Send> GoldenTest<AdvancedPen, B, ColorGradient> {
+    /// Draws the scene into a raster buffer cleared with the background.
+    pub fn render_raster(&self) -> RasterBuffer {
+        let mut buf: RasterBuffer = RasterBuffer::new(self.width, self.height);
+        buf.clear(self.background);
+        self.render(&mut buf);
+        buf
+    }
+
+    /// Renders the scene into a raster buffer
+    /// and compares it with the reference image in a directory.
+    pub fn check(&self, dir: &Path) -> Result<(), ~str> {
+        self.check_image(dir, &self.render_raster())
+    }
+}
+
+/// The result of comparing two images.
+pub struct Comparison {
+    /// The number of pixels differing by more than the tolerance.
+    pub differing: uint,
+    /// The largest difference between two pixels.
+    pub max_difference: f64,
+    /// An image highlighting the differences.
+    pub diff: RasterBuffer,
+}
+
+/// Compares two images of the same size.
+///
+/// Returns an error if the sizes differ.
+pub fn compare(
+    actual: &RasterBuffer,
+    expected: &RasterBuffer,
+    tolerance: f64
+) -> Result<Comparison, ~str> {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return Err(format!("Expected {}x{} image, found {}x{}",
+            expected.width, expected.height, actual.width, actual.height));
+    }
+    let mut diff: RasterBuffer = RasterBuffer::new(expected.width, expected.height);
+    let (mut differing, mut max_difference) = (0u, 0.0f64);
+    {
+        let pixels = diff.pixels.as_mut_slice();
+        for y in range(0u, expected.height) {
+            for x in range(0u, expected.width) {
+                let e = expected.get_pixel(x, y);
+                let d = difference(actual.get_pixel(x, y), e);
+                max_difference = max_difference.max(d);
+                let color = if d > tolerance {
+                    differing += 1;
+                    [255u8, 0, 0, 255]
+                } else if d > 0.0 {
+                    [255u8, 204, 0, 255]
+                } else {
+                    let v = (255.0 * (0.75 + 0.25 * over(e, 1.0).l) + 0.5) as u8;
+                    [v, v, v, 255]
+                };
+                let i = (y * expected.width + x) * 4;
+                for c in range(0u, 4) {
+                    pixels[i + c] = color[c];
+                }
+            }
+        }
+    }
+    Ok(Comparison { differing: differing, max_difference: max_difference, diff: diff })
+}
+
+/// Returns the perceptual difference between two pixels.
+pub fn difference(a: [u8, ..4], b: [u8, ..4]) -> f64 {
+    let mut res: f64 = 0.0;
+    for &background in [0.0, 1.0].iter() {
+        let (p, q) = (over(a, background), over(b, background));
+        let d = ((p.l - q.l).powi(2) + (p.a - q.a).powi(2) + (p.b - q.b).powi(2)).sqrt();
+        res = res.max(d);
+    }
+    res
+}
+
+/// Converts a pixel put over a gray background to OKLab.
+fn over(c: [u8, ..4], background: f64) -> Oklab {
+    let a = c[3] as f64 / 255.0;
+    let mut rgb = [0.0, ..3];
+    for i in range(0u, 3) {
+        rgb[i] = c[i] as f64 / 255.0 * a + background * (1.0 - a);
+    }
+    Srgb::new(rgb[0], rgb[1], rgb[2], 1.0).to_oklab()
+}
+
+/// Writes an image as PNG.
+fn save(buf: &RasterBuffer, path: &Path) -> Result<(), ~str> {
+    let res = match File::create(path) {
+        Ok(mut file) => write_png(buf, &mut file),
+        Err(err) => Err(err),
+    };
+    match res {
+        Ok(()) => Ok(()),
+        Err(err) => Err(format!("{}: {}", path.display(), err)),
+    }
+}
+
+#[test]
+fn test_golden() {
+    use std::io::TempDir;
+    use graphics::Rect;
+    use graphics::scene::FillEffect;
+
+    let dir = TempDir::new("golden").unwrap();
+    let test_with = |color: [f64, ..4], x: f64| {
+        let mut test: GoldenTest = GoldenTest::new("rect", 8, 8);
+        test.update = false;
+        test.background = [1.0, 1.0, 1.0, 1.0];
+        test.add(&Rect(&[x, 1.0, 4.0, 4.0]), FillEffect(SolidBrush::new(color)));
+        test
+    };
+
+    let mut test = test_with([1.0, 0.0, 0.0, 1.0], 1.0);
+    assert!(test.check(dir.path()).is_err());
+    assert!(dir.path().join("rect.actual.png").exists());
+    test.update = true;
+    assert_eq!(test.check(dir.path()), Ok(()));
+    test.update = false;
+    assert_eq!(test.check(dir.path()), Ok(()));
+
+    // A barely visible change passes.
+    assert_eq!(test_with([1.0, 0.01, 0.0, 1.0], 1.0).check(dir.path()), Ok(()));
+
+    // Moving the rectangle changes two columns.
+    assert!(test_with([1.0, 0.0, 0.0, 1.0], 2.0).check(dir.path()).is_err());
+    let data = File::open(&dir.path().join("rect.diff.png")).read_to_end().unwrap();
+    let diff = read_png(data.as_slice()).unwrap();
+    assert_eq!(diff.get_pixel(1, 2), [255, 0, 0, 255]);
+    assert_eq!(diff.get_pixel(5, 2), [255, 0, 0, 255]);
+    assert_eq!(diff.get_pixel(3, 2)[0], diff.get_pixel(3, 2)[1]);
+    assert!(compare(&diff, &RasterBuffer::new(8, 4), 0.02).is_err());
+
+    assert_eq!(difference([10, 20, 30, 255], [10, 20, 30, 255]), 0.0);
+    assert!((difference([0, 0, 0, 255], [255, 255, 255, 255]) - 1.0).abs() < 1e-3);
+    assert!(difference([0, 0, 0, 0], [255, 255, 255, 0]) == 0.0);
+}
+
+/// Returns the directory with the reference images of the tests,
+/// which run from either the project or the `bin` directory.
+#[cfg(test)]
+fn reference_dir() -> Path {
+    let dir = Path::new(file!()).dir_path().join("golden");
+    if dir.exists() { dir } else { Path::new("..").join(dir) }
+}
+
+#[test]
+fn test_golden_references() {
+    use graphics::{Line, Rect, Circle, Triangle, Polygon};
+    use graphics::advanced_pen::{RoundLineCap, SquareLineCap, ArrowLineCap};
+    use graphics::advanced_pen::{RoundLineJoin, SquareLineJoin};
+    use graphics::dash::Dash;
+    use graphics::scene::{StrokeEffect, FillEffect, GradientEffect};
+
+    let dir = reference_dir();
+    let new = |name: &str| {
+        let mut test: GoldenTest = GoldenTest::new(name, 32, 32);
+        test.background = [1.0, 1.0, 1.0, 1.0];
+        test
+    };
+    let pen = |width: f64, color: [f64, ..4]| AdvancedPen {
+        width: width,
+        color: color,
+        line_cap_start: RoundLineCap,
+        line_cap_end: RoundLineCap,
+        line_join: RoundLineJoin,
+        dash: None,
+    };
+    let red = [0.9, 0.1, 0.1, 1.0];
+    let green = [0.1, 0.6, 0.2, 1.0];
+    let blue = [0.1, 0.2, 0.8, 1.0];
+    let black = [0.0, 0.0, 0.0, 1.0];
+
+    let mut fill = new("fill");
+    fill.add(&Rect(&[3.0, 4.0, 12.0, 9.0]), FillEffect(SolidBrush::new(red)));
+    fill.add(&Triangle(&[18.0, 3.0, 29.0, 6.0, 21.0, 15.0]), FillEffect(SolidBrush::new(blue)));
+    fill.add(&Circle(&[10.0, 22.0, 7.0]), FillEffect(SolidBrush::new(green)));
+    fill.add(&Polygon(&[19.0, 18.0, 29.0, 18.0, 29.0, 29.0, 24.0, 29.0, 24.0, 23.0, 19.0, 23.0]),
+        FillEffect(SolidBrush::new([1.0, 0.6, 0.0, 1.0])));
+    assert_eq!(fill.check(&dir), Ok(()));
+
+    let mut stroke = new("stroke");
+    stroke.add(&Circle(&[9.0, 9.0, 6.0]), StrokeEffect(pen(2.0, blue)));
+    stroke.add(&Line(&[18.0, 4.0, 29.0, 13.0]), StrokeEffect(AdvancedPen {
+        line_cap_start: SquareLineCap, line_cap_end: ArrowLineCap, ..pen(1.5, black) }));
+    stroke.add(&Rect(&[4.0, 19.0, 10.0, 8.0]), StrokeEffect(AdvancedPen {
+        line_join: SquareLineJoin, ..pen(2.0, red) }));
+    stroke.add(&Triangle(&[20.0, 19.0, 29.0, 28.0, 18.0, 29.0]), StrokeEffect(pen(1.0, green)));
+    assert_eq!(stroke.check(&dir), Ok(()));
+
+    let mut gradient = new("gradient");
+    let mut linear: ColorGradient = ColorGradient::linear(2.0, 0.0, 30.0, 0.0);
+    linear.add_stop(0.0, [1.0, 0.0, 0.0, 1.0]);
+    linear.add_stop(0.5, [1.0, 1.0, 0.0, 1.0]);
+    linear.add_stop(1.0, [0.0, 0.0, 1.0, 1.0]);
+    gradient.add(&Rect(&[2.0, 2.0, 28.0, 12.0]), GradientEffect(linear));
+    let mut radial: ColorGradient = ColorGradient::radial(16.0, 23.0, 7.0);
+    radial.add_stop(0.0, [1.0, 1.0, 1.0, 1.0]);
+    radial.add_stop(1.0, [0.0, 0.5, 0.0, 1.0]);
+    gradient.add(&Circle(&[16.0, 23.0, 7.0]), GradientEffect(radial));
+    assert_eq!(gradient.check(&dir), Ok(()));
+
+    let mut dash = new("dash");
+    dash.add(&Line(&[3.0, 5.0, 29.0, 5.0]), StrokeEffect(AdvancedPen {
+        line_cap_start: SquareLineCap, line_cap_end: SquareLineCap,
+        dash: Some(Dash::new(vec!(4.0, 4.0))), ..pen(2.0, black) }));
+    dash.add(&Circle(&[16.0, 20.0, 8.0]), StrokeEffect(AdvancedPen {
+        dash: Some(Dash::new(vec!(4.0, 3.0))), ..pen(1.5, blue) }));
+    assert_eq!(dash.check(&dir), Ok(()));
+}
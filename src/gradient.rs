@@ -0,0 +1,207 @@
+
+//! Linear, radial and conic gradients.
+//!
+//! A gradient maps each point to a parameter,
+//! which is turned into a color by the color stops.
+//! The parameter goes from 0 to 1 between the start and end of the gradient,
+//! and the spread mode decides what happens outside this range.
+//!
+//! Gradients have their own transform from gradient coordinates
+//! to shape coordinates.
+
+use std::num::{Float, zero, one};
+
+use graphics::Matrix;
+use graphics::scalar::Scalar;
+use graphics_new::{identity, invert, multiply, transform_point};
+
+/// Decides the color outside the range of the gradient.
+#[deriving(Clone, Eq, Show)]
+pub enum SpreadMode {
+    /// Uses the color at the nearest end.
+    PadSpread,
+    /// Repeats the gradient.
+    RepeatSpread,
+    /// Repeats the gradient, mirroring every other time.
+    ReflectSpread,
+}
+
+/// The geometry of a gradient.
+#[deriving(Clone, Eq, Show)]
+pub enum GradientKind<Fl=f64> {
+    /// Changes along a line [x1, y1, x2, y2].
+    LinearGradient([Fl, ..4]),
+    /// Changes with distance from a center [x, y, radius].
+    RadialGradient([Fl, ..3]),
+    /// Changes with angle around a center [x, y, start angle in radians].
+    ConicGradient([Fl, ..3]),
+}
+
+/// A gradient with color stops.
+#[deriving(Clone, Eq, Show)]
+pub struct ColorGradient<Fl=f64> {
+    /// The geometry.
+    pub kind: GradientKind<Fl>,
+    /// Color stops sorted by offset, [r, g, b, a] in straight alpha.
+    pub stops: Vec<(Fl, [Fl, ..4])>,
+    /// The spread mode.
+    pub spread: SpreadMode,
+    transform: Matrix<Fl>,
+    inverse: Option<Matrix<Fl>>,
+}
+
+impl<Fl: Scalar> ColorGradient<Fl> {
+    /// Creates a gradient without color stops.
+    pub fn new(kind: GradientKind<Fl>) -> ColorGradient<Fl> {
+        ColorGradient {
+            kind: kind,
+            stops: Vec::new(),
+            spread: PadSpread,
+            transform: identity(),
+            inverse: Some(identity()),
+        }
+    }
+
+    /// Creates a linear gradient from one point to another.
+    #[inline(always)]
+    pub fn linear(x1: Fl, y1: Fl, x2: Fl, y2: Fl) -> ColorGradient<Fl> {
+        ColorGradient::new(LinearGradient([x1, y1, x2, y2]))
+    }
+
+    /// Creates a radial gradient from a center to a radius.
+    #[inline(always)]
+    pub fn radial(x: Fl, y: Fl, radius: Fl) -> ColorGradient<Fl> {
+        ColorGradient::new(RadialGradient([x, y, radius]))
+    }
+
+    /// Creates a conic gradient around a center starting at an angle.
+    #[inline(always)]
+    pub fn conic(x: Fl, y: Fl, angle: Fl) -> ColorGradient<Fl> {
+        ColorGradient::new(ConicGradient([x, y, angle]))
+    }
+
+    /// Adds a color stop, keeping the stops sorted.
+    ///
+    /// A stop with same offset as an existing one is put after it,
+    /// which gives a sharp change of color.
+    pub fn add_stop(&mut self, offset: Fl, color: [Fl, ..4]) {
+        let i = self.stops.iter().position(|&(o, _)| o > offset)
+            .unwrap_or(self.stops.len());
+        self.stops.insert(i, (offset, color));
+    }
+
+    /// Returns the transform from gradient coordinates to shape coordinates.
+    #[inline(always)]
+    pub fn get_transform<'a>(&'a self) -> &'a Matrix<Fl> {
+        &self.transform
+    }
+
+    /// Sets the transform from gradient coordinates to shape coordinates.
+    pub fn set_transform(&mut self, m: &Matrix<Fl>) {
+        self.transform = *m;
+        self.inverse = invert(m);
+    }
+
+    /// Applies a matrix on top of the gradient transform.
+    #[inline(always)]
+    pub fn transform(&mut self, m: &Matrix<Fl>) {
+        let m = multiply(m, &self.transform);
+        self.set_transform(&m);
+    }
+
+    /// Returns the parameter at a point in shape coordinates,
+    /// before applying the spread mode.
+    ///
+    /// Returns `None` if the transform is singular.
+    pub fn param(&self, p: [Fl, ..2]) -> Option<Fl> {
+        let (o, l): (Fl, Fl) = (zero(), one());
+        let q = match self.inverse {
+            None => return None,
+            Some(ref inv) => transform_point(inv, p),
+        };
+        Some(match self.kind {
+            LinearGradient(l) => {
+                let (dx, dy) = (l[2] - l[0], l[3] - l[1]);
+                let len2 = dx * dx + dy * dy;
+                if len2 == o { return Some(o); }
+                ((q[0] - l[0]) * dx + (q[1] - l[1]) * dy) / len2
+            }
+            RadialGradient(r) => {
+                if r[2] == o { return Some(l); }
+                let (dx, dy) = (q[0] - r[0], q[1] - r[1]);
+                (dx * dx + dy * dy).sqrt() / r[2]
+            }
+            ConicGradient(c) => {
+                let (pi, two): (Fl, Fl) = (Float::pi(), Scalar::from_f64(2.0));
+                let angle = (q[1] - c[1]).atan2(q[0] - c[0]) - c[2];
+                let t = angle / (two * pi);
+                t - t.floor()
+            }
+        })
+    }
+
+    /// Returns the color at a parameter, after applying the spread mode.
+    pub fn color_at(&self, t: Fl) -> [Fl, ..4] {
+        let (o, l): (Fl, Fl) = (zero(), one());
+        let (half, two): (Fl, Fl) = (Scalar::from_f64(0.5), Scalar::from_f64(2.0));
+        let t = match self.spread {
+            PadSpread => t,
+            RepeatSpread => t - t.floor(),
+            ReflectSpread => {
+                let t = t - two * (half * t).floor();
+                if t > l { two - t } else { t }
+            }
+        };
+        let stops = self.stops.as_slice();
+        let n = stops.len();
+        if n == 0 { return [o, o, o, o]; }
+        let (first, first_color) = stops[0];
+        if t <= first { return first_color; }
+        for i in range(1u, n) {
+            let (b, b_color) = stops[i];
+            if t >= b { continue; }
+            let (a, a_color) = stops[i - 1];
+            let u = (t - a) / (b - a);
+            let mut res = [o, ..4];
+            for c in range(0u, 4) {
+                res[c] = a_color[c] + (b_color[c] - a_color[c]) * u;
+            }
+            return res;
+        }
+        let (_, last_color) = stops[n - 1];
+        last_color
+    }
+
+    /// Returns the color at a point in shape coordinates.
+    ///
+    /// Returns transparent black if the transform is singular.
+    #[inline(always)]
+    pub fn sample(&self, p: [Fl, ..2]) -> [Fl, ..4] {
+        match self.param(p) {
+            None => [zero(), zero(), zero(), zero()],
+            Some(t) => self.color_at(t),
+        }
+    }
+}
+
+#[test]
+fn test_gradient() {
+    let mut g: ColorGradient = ColorGradient::linear(0.0, 0.0, 10.0, 0.0);
+    g.add_stop(1.0, [1.0, 1.0, 1.0, 1.0]);
+    g.add_stop(0.0, [0.0, 0.0, 0.0, 1.0]);
+    assert_eq!(g.sample([5.0, 3.0]), [0.5, 0.5, 0.5, 1.0]);
+    assert_eq!(g.sample([-5.0, 0.0]), [0.0, 0.0, 0.0, 1.0]);
+
+    g.spread = RepeatSpread;
+    assert_eq!(g.sample([12.5, 0.0]), [0.25, 0.25, 0.25, 1.0]);
+    g.spread = ReflectSpread;
+    assert_eq!(g.sample([12.5, 0.0]), [0.75, 0.75, 0.75, 1.0]);
+
+    g.transform(&[2.0, 0.0, 0.0,
+                  0.0, 2.0, 0.0]);
+    g.spread = PadSpread;
+    assert_eq!(g.sample([10.0, 0.0]), [0.5, 0.5, 0.5, 1.0]);
+    g.set_transform(&[0.0, 0.0, 0.0,
+                      0.0, 0.0, 0.0]);
+    assert_eq!(g.param([10.0, 0.0]), None);
+}
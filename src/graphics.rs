@@ -5,6 +5,33 @@
 
 pub mod simple_pen;
 pub mod advanced_pen;
+pub mod raster;
+pub mod svg;
+pub mod stroke;
+pub mod curve;
+pub mod bounds;
+pub mod recorder;
+pub mod gradient;
+pub mod brush;
+pub mod dash;
+pub mod pixel;
+pub mod composite;
+pub mod clip;
+pub mod mesh;
+pub mod scalar;
+pub mod scene;
+pub mod damage;
+pub mod tile;
+pub mod color;
+pub mod deflate;
+pub mod image;
+pub mod golden;
+pub mod pdf;
+
+use std::num::Float;
+
+use graphics::scalar::{Scalar, convert_matrix};
+use graphics_new::{multiply, identity, translate, rotate, scale, shear};
 
 /// Basic shapes.
 pub enum Shape<'a, Fl=f64> {
@@ -52,26 +79,138 @@ pub enum Effect<'a, Pen, Brush, Gradient> {
     Gradient(&'a Gradient),
 }
 
-pub type Matrix = [f64, ..6];
+pub type Matrix<Fl=f64> = [Fl, ..6];
 
 /// Renders a shape.
-pub trait Shader {
+///
+/// Shaders compute with the float type of the shapes they draw.
+pub trait Shader<Fl=f64> {
     /// Draw shape.
-    fn shade(&mut self, shape: &Shape);
+    fn shade(&mut self, shape: &Shape<Fl>);
 
     /// Gets a readonly view.
-    fn get_view<'a>(&'a self) -> &'a View;
+    fn get_view<'a>(&'a self) -> &'a View<Fl>;
 
     /// Gets a mutable view.
-    fn get_mut_view<'a>(&'a mut self) -> &'a mut View;
+    fn get_mut_view<'a>(&'a mut self) -> &'a mut View<Fl>;
 }
 
 /// The transformation of graphics.
-pub struct View {
+///
+/// New transformations are applied on top of the current one,
+/// the same way as `graphics_new::Context` does.
+#[deriving(Clone)]
+pub struct View<Fl=f64> {
     /// Base matrix transformation.
-    pub base: [f64, ..6],
+    pub base: [Fl, ..6],
     /// Current matrix transformation.
-    pub trans: [f64, ..6],
+    pub trans: [Fl, ..6],
+    /// Saved current transformations.
+    pub stack: Vec<[Fl, ..6]>,
+}
+
+impl<Fl: Scalar> View<Fl> {
+    /// Creates a view with identity transformations.
+    pub fn new() -> View<Fl> {
+        View {
+            base: identity(),
+            trans: identity(),
+            stack: Vec::new(),
+        }
+    }
+
+    /// Saves the current transformation.
+    #[inline(always)]
+    pub fn push(&mut self) {
+        self.stack.push(self.trans);
+    }
+
+    /// Restores the last saved transformation.
+    ///
+    /// Returns false if there was nothing to restore.
+    #[inline(always)]
+    pub fn pop(&mut self) -> bool {
+        match self.stack.pop() {
+            None => false,
+            Some(trans) => { self.trans = trans; true }
+        }
+    }
+
+    /// Applies a transformation on top of the current one.
+    #[inline(always)]
+    pub fn transform(&mut self, m: &Matrix<Fl>) {
+        self.trans = multiply(m, &self.trans);
+    }
+
+    /// Translates.
+    #[inline(always)]
+    pub fn trans(&mut self, x: Fl, y: Fl) {
+        self.transform(&translate(x, y));
+    }
+
+    /// Rotates with radians.
+    #[inline(always)]
+    pub fn rot(&mut self, angle: Fl) {
+        self.transform(&rotate(angle));
+    }
+
+    /// Rotates with degrees.
+    #[inline(always)]
+    pub fn rot_deg(&mut self, angle: Fl) {
+        let pi: Fl = Float::pi();
+        let half_turn: Fl = Scalar::from_f64(180.0);
+        self.rot(angle * pi / half_turn);
+    }
+
+    /// Scales.
+    #[inline(always)]
+    pub fn scale(&mut self, sx: Fl, sy: Fl) {
+        self.transform(&scale(sx, sy));
+    }
+
+    /// Shears along x and y.
+    #[inline(always)]
+    pub fn shear(&mut self, sx: Fl, sy: Fl) {
+        self.transform(&shear(sx, sy));
+    }
+
+    /// Resets the current transformation to identity.
+    ///
+    /// The saved transformations are kept.
+    #[inline(always)]
+    pub fn reset(&mut self) {
+        self.trans = identity();
+    }
+
+    /// Returns the current transformation composed with the base.
+    #[inline(always)]
+    pub fn matrix(&self) -> Matrix<Fl> {
+        multiply(&self.base, &self.trans)
+    }
+
+    /// Returns a copy using another float type.
+    pub fn convert<Fl2: Scalar>(&self) -> View<Fl2> {
+        View {
+            base: convert_matrix(&self.base),
+            trans: convert_matrix(&self.trans),
+            stack: self.stack.iter().map(|m| convert_matrix(m)).collect(),
+        }
+    }
+}
+
+#[test]
+fn test_view() {
+    let mut view: View = View::new();
+    view.base = [2.0, 0.0, 0.0,
+                 0.0, 2.0, 0.0];
+    view.trans(10.0, 0.0);
+    view.push();
+    view.trans(5.0, 5.0);
+    assert_eq!(view.matrix()[2], 30.0);
+    assert_eq!(view.matrix()[5], 10.0);
+    assert!(view.pop());
+    assert_eq!(view.matrix()[2], 20.0);
+    assert!(!view.pop());
 }
 
 
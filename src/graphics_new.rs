@@ -1,11 +1,22 @@
 
 
 //! Attempt of creating a cheap drawing context.
+//!
+//! Matrices, colors, contexts and backends are generic over the float type,
+//! defaulting to `f64`, see `graphics::scalar`.
 
-use std::num::Float;
+use std::num::{Float, zero, one};
 
-pub type Matrix2d = [f64, ..6];
-pub type Color = [f64, ..4];
+use graphics::{Shape, Rect, Ellipse, Line};
+use graphics::composite::{CompositeOp, SourceOver};
+use graphics::clip::Clip;
+use graphics::scalar::Scalar;
+
+pub type Matrix2d<Fl=f64> = [Fl, ..6];
+/// A color [r, g, b, a] in sRGB with straight alpha.
+///
+/// See `graphics::color` for other color spaces.
+pub type Color<Fl=f64> = [Fl, ..4];
 
 /// A structure that might contain a value or a borrowed value.
 /// This is to used as building block to create data structure
@@ -28,76 +39,355 @@ impl<'a, T> Maybe<'a, T> {
     }
 }
 
+/// Implemented by backends that draw for a context.
+///
+/// The context hands over the composed transform, color, composite operator and clip,
+/// so the same context can target different backends.
+/// The global alpha of the context is already multiplied into the color.
+/// The clip is in device coordinates.
+pub trait Backend<Fl=f64> {
+    /// Draws a shape with a transform, color and composite operator,
+    /// changing only the parts inside the clip.
+    fn draw(
+        &mut self,
+        transform: &Matrix2d<Fl>,
+        color: &Color<Fl>,
+        op: &CompositeOp,
+        clip: &Clip<Fl>,
+        shape: &Shape<Fl>
+    );
+}
+
 /// Multiplies two matrices.
 #[inline(always)]
-pub fn multiply(m: &[f64, ..6], b: &[f64, ..6]) -> [f64, ..6] {
-    [m[0]*b[0]+m[1]*b[3]+m[2]*0.0,  m[0]*b[1]+m[1]*b[4]+m[2]*0.0,  m[0]*b[2]+m[1]*b[5]+m[2]*1.0,
-     m[3]*b[0]+m[4]*b[3]+m[5]*0.0,  m[3]*b[1]+m[4]*b[4]+m[5]*0.0,  m[3]*b[2]+m[4]*b[5]+m[5]*1.0]
+pub fn multiply<Fl: Scalar>(m: &[Fl, ..6], b: &[Fl, ..6]) -> [Fl, ..6] {
+    [m[0]*b[0]+m[1]*b[3],  m[0]*b[1]+m[1]*b[4],  m[0]*b[2]+m[1]*b[5]+m[2],
+     m[3]*b[0]+m[4]*b[3],  m[3]*b[1]+m[4]*b[4],  m[3]*b[2]+m[4]*b[5]+m[5]]
+}
+
+/// Determinants smaller than this are treated as singular.
+pub static SINGULAR_EPSILON: f64 = 1e-12;
+
+/// Returns the identity matrix.
+#[inline(always)]
+pub fn identity<Fl: Scalar>() -> Matrix2d<Fl> {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    [l, o, o,
+     o, l, o]
+}
+
+/// Returns a translation matrix.
+#[inline(always)]
+pub fn translate<Fl: Scalar>(x: Fl, y: Fl) -> Matrix2d<Fl> {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    [l, o, x,
+     o, l, y]
+}
+
+/// Returns a rotation matrix with radians.
+#[inline(always)]
+pub fn rotate<Fl: Scalar>(angle: Fl) -> Matrix2d<Fl> {
+    let o: Fl = zero();
+    let c = angle.cos();
+    let s = angle.sin();
+    [c, s, o,
+    -s, c, o]
+}
+
+/// Returns a scale matrix.
+#[inline(always)]
+pub fn scale<Fl: Scalar>(sx: Fl, sy: Fl) -> Matrix2d<Fl> {
+    let o: Fl = zero();
+    [sx, o, o,
+     o, sy, o]
+}
+
+/// Returns a shear matrix.
+#[inline(always)]
+pub fn shear<Fl: Scalar>(sx: Fl, sy: Fl) -> Matrix2d<Fl> {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    [l, sx, o,
+     sy, l, o]
+}
+
+/// Returns the determinant of a matrix.
+#[inline(always)]
+pub fn determinant<Fl: Scalar>(m: &Matrix2d<Fl>) -> Fl {
+    m[0] * m[4] - m[1] * m[3]
+}
+
+/// Returns the inverse of a matrix.
+///
+/// Returns `None` if the matrix is singular.
+pub fn invert<Fl: Scalar>(m: &Matrix2d<Fl>) -> Option<Matrix2d<Fl>> {
+    let det = determinant(m);
+    if det.abs().as_f64() < SINGULAR_EPSILON { return None; }
+    let inv = one::<Fl>() / det;
+    Some([ m[4] * inv, -m[1] * inv, (m[1] * m[5] - m[4] * m[2]) * inv,
+          -m[3] * inv,  m[0] * inv, (m[3] * m[2] - m[0] * m[5]) * inv])
+}
+
+/// Transforms a point.
+#[inline(always)]
+pub fn transform_point<Fl: Scalar>(m: &Matrix2d<Fl>, p: [Fl, ..2]) -> [Fl, ..2] {
+    [m[0] * p[0] + m[1] * p[1] + m[2],
+     m[3] * p[0] + m[4] * p[1] + m[5]]
+}
+
+/// Transforms a vector, ignoring translation.
+#[inline(always)]
+pub fn transform_vector<Fl: Scalar>(m: &Matrix2d<Fl>, v: [Fl, ..2]) -> [Fl, ..2] {
+    [m[0] * v[0] + m[1] * v[1],
+     m[3] * v[0] + m[4] * v[1]]
+}
+
+/// The parts of a matrix.
+///
+/// Composing the parts gives back the matrix,
+/// by first scaling, then skewing, rotating and translating.
+#[deriving(Clone, Show)]
+pub struct Decomposed<Fl=f64> {
+    /// Translation [x, y].
+    pub translation: [Fl, ..2],
+    /// Rotation in radians, same direction as `rotate`.
+    pub rotation: Fl,
+    /// Scale [x, y], where y is negative for mirrored matrices.
+    pub scale: [Fl, ..2],
+    /// Skew of x along y.
+    pub skew: Fl,
+}
+
+impl<Fl: Scalar> Decomposed<Fl> {
+    /// Returns the matrix composed from the parts.
+    pub fn compose(&self) -> Matrix2d<Fl> {
+        let m = multiply(&shear(self.skew, zero()), &scale(self.scale[0], self.scale[1]));
+        let m = multiply(&rotate(self.rotation), &m);
+        multiply(&translate(self.translation[0], self.translation[1]), &m)
+    }
+}
+
+/// Splits a matrix into translation, rotation, scale and skew.
+///
+/// Returns `None` if the matrix is singular.
+pub fn decompose<Fl: Scalar>(m: &Matrix2d<Fl>) -> Option<Decomposed<Fl>> {
+    let det = determinant(m);
+    if det.abs().as_f64() < SINGULAR_EPSILON { return None; }
+    let sx = (m[0] * m[0] + m[3] * m[3]).sqrt();
+    let rotation = (-m[3]).atan2(m[0]);
+    let (c, s) = (rotation.cos(), rotation.sin());
+    // Remove the rotation to get the upper triangular part.
+    let shear_sy = c * m[1] - s * m[4];
+    let sy = det / sx;
+    Some(Decomposed {
+        translation: [m[2], m[5]],
+        rotation: rotation,
+        scale: [sx, sy],
+        skew: shear_sy / sy,
+    })
 }
 
 /// Drawing 2d context.
-pub struct Context<'a> {
-    base: Maybe<'a, Matrix2d>,
-    transform: Maybe<'a, Matrix2d>,
-    color: Maybe<'a, Color>,
+pub struct Context<'a, Fl=f64> {
+    base: Maybe<'a, Matrix2d<Fl>>,
+    transform: Maybe<'a, Matrix2d<Fl>>,
+    color: Maybe<'a, Color<Fl>>,
+    composite: Maybe<'a, CompositeOp>,
+    alpha: Maybe<'a, Fl>,
+    clip: Maybe<'a, Clip<Fl>>,
 }
 
-impl<'a> Context<'a> {
+impl<'a, Fl: Scalar> Context<'a, Fl> {
     /// Creates a new drawing context.
-    pub fn new() -> Context {
+    pub fn new() -> Context<Fl> {
+        let (o, l): (Fl, Fl) = (zero(), one());
         Context {
-            base:  Value([1.0, 0.0, 0.0,
-                          0.0, 1.0, 0.0]),
-            transform: Value([1.0, 0.0, 0.0,
-                          0.0, 1.0, 0.0]),
-            color: Value([0.0, 0.0, 0.0, 1.0]),
+            base: Value(identity()),
+            transform: Value(identity()),
+            color: Value([o, o, o, l]),
+            composite: Value(SourceOver),
+            alpha: Value(l),
+            clip: Value(Clip::new()),
         }
     }
 
     /// Returns a translated context.
     #[inline(always)]
-    pub fn trans(&'a self, x: f64, y: f64) -> Context<'a> {
+    pub fn trans(&'a self, x: Fl, y: Fl) -> Context<'a, Fl> {
+        self.with_transform(&translate(x, y))
+    }
+
+    /// Rotates with degrees.
+    #[inline(always)]
+    pub fn rot_deg(&'a self, angle: Fl) -> Context<'a, Fl> {
+        let pi: Fl = Float::pi();
+        let half_turn: Fl = Scalar::from_f64(180.0);
+        self.rot(angle * pi / half_turn)
+    }
+
+    /// Returns a rotated context.
+    #[inline(always)]
+    pub fn rot(&'a self, angle: Fl) -> Context<'a, Fl> {
+        self.with_transform(&rotate(angle))
+    }
+
+    /// Returns a scaled context.
+    #[inline(always)]
+    pub fn scale(&'a self, sx: Fl, sy: Fl) -> Context<'a, Fl> {
+        self.with_transform(&scale(sx, sy))
+    }
+
+    /// Returns a sheared context.
+    #[inline(always)]
+    pub fn shear(&'a self, sx: Fl, sy: Fl) -> Context<'a, Fl> {
+        self.with_transform(&shear(sx, sy))
+    }
+
+    /// Returns a context flipped horizontally.
+    #[inline(always)]
+    pub fn flip_h(&'a self) -> Context<'a, Fl> {
+        self.scale(-one::<Fl>(), one())
+    }
+
+    /// Returns a context flipped vertically.
+    #[inline(always)]
+    pub fn flip_v(&'a self) -> Context<'a, Fl> {
+        self.scale(one(), -one::<Fl>())
+    }
+
+    /// Returns a context with identity transform, keeping the base.
+    #[inline(always)]
+    pub fn reset(&'a self) -> Context<'a, Fl> {
         Context {
             base: Borrowed(self.base.get()),
-            transform: Value({
-                let trans: [f64, ..6] = [1.0, 0.0, x,
-                                         0.0, 1.0, y];
-                 multiply(&trans, self.transform.get())
-            }),
+            transform: Value(identity()),
             color: Borrowed(self.color.get()),
+            composite: Borrowed(self.composite.get()),
+            alpha: Borrowed(self.alpha.get()),
+            clip: Borrowed(self.clip.get()),
         }
     }
 
-    /// Rotates with degrees.
+    /// Returns a context with a matrix applied on top of the transform.
     #[inline(always)]
-    pub fn rot_deg(&'a self, angle: f64) -> Context<'a> {
-        let pi: f64 = Float::pi();
-        self.rot(angle * pi / 180.0)
+    pub fn with_transform(&'a self, m: &Matrix2d<Fl>) -> Context<'a, Fl> {
+        Context {
+            base: Borrowed(self.base.get()),
+            transform: Value(multiply(m, self.transform.get())),
+            color: Borrowed(self.color.get()),
+            composite: Borrowed(self.composite.get()),
+            alpha: Borrowed(self.alpha.get()),
+            clip: Borrowed(self.clip.get()),
+        }
     }
 
-    /// Returns a rotated context.
+    /// Returns a context with another color.
+    #[inline(always)]
+    pub fn rgba(&'a self, r: Fl, g: Fl, b: Fl, a: Fl) -> Context<'a, Fl> {
+        Context {
+            base: Borrowed(self.base.get()),
+            transform: Borrowed(self.transform.get()),
+            color: Value([r, g, b, a]),
+            composite: Borrowed(self.composite.get()),
+            alpha: Borrowed(self.alpha.get()),
+            clip: Borrowed(self.clip.get()),
+        }
+    }
+
+    /// Returns a context with another composite operator.
+    #[inline(always)]
+    pub fn composite(&'a self, op: CompositeOp) -> Context<'a, Fl> {
+        Context {
+            base: Borrowed(self.base.get()),
+            transform: Borrowed(self.transform.get()),
+            color: Borrowed(self.color.get()),
+            composite: Value(op),
+            alpha: Borrowed(self.alpha.get()),
+            clip: Borrowed(self.clip.get()),
+        }
+    }
+
+    /// Returns a context with another global alpha.
+    ///
+    /// The global alpha is multiplied with the alpha of the color when drawing.
+    #[inline(always)]
+    pub fn alpha(&'a self, alpha: Fl) -> Context<'a, Fl> {
+        Context {
+            base: Borrowed(self.base.get()),
+            transform: Borrowed(self.transform.get()),
+            color: Borrowed(self.color.get()),
+            composite: Borrowed(self.composite.get()),
+            alpha: Value(alpha),
+            clip: Borrowed(self.clip.get()),
+        }
+    }
+
+    /// Returns a context clipped to a rectangle.
+    ///
+    /// The clip intersects with the clip of this context.
+    #[inline(always)]
+    pub fn clip_rect(&'a self, x: Fl, y: Fl, w: Fl, h: Fl) -> Context<'a, Fl> {
+        let clip = self.clip.get().intersect_rect(&self.composed(), [x, y, w, h]);
+        self.with_clip(clip)
+    }
+
+    /// Returns a context clipped to the area of a shape.
+    ///
+    /// The clip intersects with the clip of this context.
+    #[inline(always)]
+    pub fn clip_shape(&'a self, shape: &Shape<Fl>) -> Context<'a, Fl> {
+        let clip = self.clip.get().intersect_shape(&self.composed(), shape);
+        self.with_clip(clip)
+    }
+
+    /// Returns a context with another clip.
     #[inline(always)]
-    pub fn rot(&'a self, angle: f64) -> Context<'a> {
+    fn with_clip(&'a self, clip: Clip<Fl>) -> Context<'a, Fl> {
         Context {
             base: Borrowed(self.base.get()),
-            transform: Value({
-                let c = angle.cos();
-                let s = angle.sin();
-                let rot: [f64, ..6] = [c, s, 0.0,
-                                      -s, c, 0.0];
-                multiply(&rot, self.transform.get())
-            }),
+            transform: Borrowed(self.transform.get()),
             color: Borrowed(self.color.get()),
+            composite: Borrowed(self.composite.get()),
+            alpha: Borrowed(self.alpha.get()),
+            clip: Value(clip),
         }
     }
 
-    /// Draw to screen.
-    pub fn draw(&self) {}
+    /// Returns the transform composed with the base.
+    #[inline(always)]
+    pub fn composed(&self) -> Matrix2d<Fl> {
+        multiply(self.base.get(), self.transform.get())
+    }
+
+    /// Draws a shape with a backend.
+    #[inline(always)]
+    pub fn draw<B: Backend<Fl>>(&self, back_end: &mut B, shape: &Shape<Fl>) {
+        let c = self.color.get();
+        let color = [c[0], c[1], c[2], c[3] * *self.alpha.get()];
+        back_end.draw(&self.composed(), &color, self.composite.get(), self.clip.get(), shape);
+    }
+
+    /// Draws a rectangle.
+    #[inline(always)]
+    pub fn rect<B: Backend<Fl>>(&self, back_end: &mut B, x: Fl, y: Fl, w: Fl, h: Fl) {
+        self.draw(back_end, &Rect(&[x, y, w, h]));
+    }
+
+    /// Draws an ellipse inside a rectangle.
+    #[inline(always)]
+    pub fn ellipse<B: Backend<Fl>>(&self, back_end: &mut B, x: Fl, y: Fl, w: Fl, h: Fl) {
+        self.draw(back_end, &Ellipse(&[x, y, w, h]));
+    }
+
+    /// Draws a line.
+    #[inline(always)]
+    pub fn line<B: Backend<Fl>>(&self, back_end: &mut B, x1: Fl, y1: Fl, x2: Fl, y2: Fl) {
+        self.draw(back_end, &Line(&[x1, y1, x2, y2]));
+    }
 }
 
 #[test]
 fn test_context() {
-    let c = Context::new();
+    let c: Context = Context::new();
     {
         let d = c.trans(20.0, 40.0);
         let d = d.trans(10.0, 10.0);
@@ -112,3 +402,80 @@ fn test_context() {
     assert!((c.transform.get()[1] - 1.0).abs() < 0.00001);
 }
 
+#[test]
+fn test_matrix() {
+    let m = multiply(&translate(3.0, -2.0),
+        &multiply(&rotate(0.5), &multiply(&shear(0.25, 0.0), &scale(2.0, 3.0))));
+    let inv = invert(&m).unwrap();
+    let p = transform_point(&inv, transform_point(&m, [7.0, 11.0]));
+    assert!((p[0] - 7.0).abs() < 1e-9 && (p[1] - 11.0).abs() < 1e-9);
+    let v = transform_vector(&m, [0.0, 0.0]);
+    assert_eq!(v, [0.0, 0.0]);
+
+    let d = decompose(&m).unwrap();
+    assert!((d.rotation - 0.5).abs() < 1e-9);
+    assert!((d.scale[0] - 2.0).abs() < 1e-9);
+    assert!((d.scale[1] - 3.0).abs() < 1e-9);
+    assert!((d.skew - 0.25).abs() < 1e-9);
+    let c = d.compose();
+    for i in range(0u, 6) {
+        assert!((c[i] - m[i]).abs() < 1e-9);
+    }
+
+    assert!(invert(&scale(0.0, 1.0)).is_none());
+    let c: Context = Context::new();
+    let c = c.flip_h();
+    assert_eq!(c.transform.get()[0], -1.0);
+}
+
+#[test]
+fn test_backend() {
+    use graphics::composite::Multiply;
+
+    struct Recorder {
+        calls: Vec<(Matrix2d, Color, CompositeOp, bool)>,
+    }
+
+    impl Backend for Recorder {
+        fn draw(
+            &mut self,
+            transform: &Matrix2d,
+            color: &Color,
+            op: &CompositeOp,
+            clip: &Clip,
+            _shape: &Shape
+        ) {
+            self.calls.push((*transform, *color, *op, clip.contains([12.0, 22.0])));
+        }
+    }
+
+    let mut recorder = Recorder { calls: Vec::new() };
+    let c: Context = Context::new();
+    let d = c.trans(10.0, 20.0);
+    let d = d.rgba(1.0, 0.0, 0.0, 1.0);
+    d.rect(&mut recorder, 0.0, 0.0, 5.0, 5.0);
+    c.line(&mut recorder, 0.0, 0.0, 1.0, 1.0);
+    let e = d.alpha(0.5);
+    let e = e.composite(Multiply);
+    e.rect(&mut recorder, 0.0, 0.0, 5.0, 5.0);
+    let f = d.clip_rect(0.0, 0.0, 5.0, 5.0);
+    let f = f.trans(5.0, 0.0);
+    let f = f.clip_rect(0.0, 0.0, 5.0, 5.0);
+    f.rect(&mut recorder, 0.0, 0.0, 5.0, 5.0);
+    assert_eq!(recorder.calls.len(), 4);
+    let (transform, color, op, inside) = recorder.calls.as_slice()[0];
+    assert!(inside);
+    assert_eq!(transform[2], 10.0);
+    assert_eq!(transform[5], 20.0);
+    assert_eq!(color, [1.0, 0.0, 0.0, 1.0]);
+    assert_eq!(op, SourceOver);
+    let (transform, color, _, _) = recorder.calls.as_slice()[1];
+    assert_eq!(transform[2], 0.0);
+    assert_eq!(color, [0.0, 0.0, 0.0, 1.0]);
+    let (_, color, op, _) = recorder.calls.as_slice()[2];
+    assert_eq!(color, [1.0, 0.0, 0.0, 0.5]);
+    assert_eq!(op, Multiply);
+    // The nested clips do not overlap.
+    let (_, _, _, inside) = recorder.calls.as_slice()[3];
+    assert!(!inside);
+}
@@ -0,0 +1,548 @@
+
+//! Encoding and decoding of images in the PNG and Netpbm formats.
+//!
+//! Raster buffers are written as 8 bit RGBA PNG images,
+//! compressed with the built-in deflate encoder,
+//! so no system libraries are needed.
+//! Each row uses the PNG filter with the smallest sum of absolute values,
+//! which usually compresses best.
+//! PPM images (P6) drop the alpha channel, while PAM images (P7) keep it.
+//!
+//! Non-interlaced PNG images of any color type and bit depth can be read,
+//! as well as the Netpbm formats P2, P3, P5, P6 and P7.
+//! Decoded images are converted to 8 bit RGBA with straight alpha,
+//! so they can be used with an `ImageBrush` or compared with rendered output.
+
+use std::io::{IoResult, Writer};
+use std::num::CheckedMul;
+use std::str;
+
+use graphics::raster::RasterBuffer;
+use graphics::deflate::{zlib_compress, zlib_decompress};
+
+/// The first eight bytes of every PNG image.
+static PNG_SIGNATURE: [u8, ..8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// The CRC-32 of each byte value, with the polynomial used by PNG.
+static CRC_TABLE: [u32, ..256] = [
+    0x00000000, 0x77073096, 0xee0e612c, 0x990951ba, 0x076dc419, 0x706af48f,
+    0xe963a535, 0x9e6495a3, 0x0edb8832, 0x79dcb8a4, 0xe0d5e91e, 0x97d2d988,
+    0x09b64c2b, 0x7eb17cbd, 0xe7b82d07, 0x90bf1d91, 0x1db71064, 0x6ab020f2,
+    0xf3b97148, 0x84be41de, 0x1adad47d, 0x6ddde4eb, 0xf4d4b551, 0x83d385c7,
+    0x136c9856, 0x646ba8c0, 0xfd62f97a, 0x8a65c9ec, 0x14015c4f, 0x63066cd9,
+    0xfa0f3d63, 0x8d080df5, 0x3b6e20c8, 0x4c69105e, 0xd56041e4, 0xa2677172,
+    0x3c03e4d1, 0x4b04d447, 0xd20d85fd, 0xa50ab56b, 0x35b5a8fa, 0x42b2986c,
+    0xdbbbc9d6, 0xacbcf940, 0x32d86ce3, 0x45df5c75, 0xdcd60dcf, 0xabd13d59,
+    0x26d930ac, 0x51de003a, 0xc8d75180, 0xbfd06116, 0x21b4f4b5, 0x56b3c423,
+    0xcfba9599, 0xb8bda50f, 0x2802b89e, 0x5f058808, 0xc60cd9b2, 0xb10be924,
+    0x2f6f7c87, 0x58684c11, 0xc1611dab, 0xb6662d3d, 0x76dc4190, 0x01db7106,
+    0x98d220bc, 0xefd5102a, 0x71b18589, 0x06b6b51f, 0x9fbfe4a5, 0xe8b8d433,
+    0x7807c9a2, 0x0f00f934, 0x9609a88e, 0xe10e9818, 0x7f6a0dbb, 0x086d3d2d,
+    0x91646c97, 0xe6635c01, 0x6b6b51f4, 0x1c6c6162, 0x856530d8, 0xf262004e,
+    0x6c0695ed, 0x1b01a57b, 0x8208f4c1, 0xf50fc457, 0x65b0d9c6, 0x12b7e950,
+    0x8bbeb8ea, 0xfcb9887c, 0x62dd1ddf, 0x15da2d49, 0x8cd37cf3, 0xfbd44c65,
+    0x4db26158, 0x3ab551ce, 0xa3bc0074, 0xd4bb30e2, 0x4adfa541, 0x3dd895d7,
+    0xa4d1c46d, 0xd3d6f4fb, 0x4369e96a, 0x346ed9fc, 0xad678846, 0xda60b8d0,
+    0x44042d73, 0x33031de5, 0xaa0a4c5f, 0xdd0d7cc9, 0x5005713c, 0x270241aa,
+    0xbe0b1010, 0xc90c2086, 0x5768b525, 0x206f85b3, 0xb966d409, 0xce61e49f,
+    0x5edef90e, 0x29d9c998, 0xb0d09822, 0xc7d7a8b4, 0x59b33d17, 0x2eb40d81,
+    0xb7bd5c3b, 0xc0ba6cad, 0xedb88320, 0x9abfb3b6, 0x03b6e20c, 0x74b1d29a,
+    0xead54739, 0x9dd277af, 0x04db2615, 0x73dc1683, 0xe3630b12, 0x94643b84,
+    0x0d6d6a3e, 0x7a6a5aa8, 0xe40ecf0b, 0x9309ff9d, 0x0a00ae27, 0x7d079eb1,
+    0xf00f9344, 0x8708a3d2, 0x1e01f268, 0x6906c2fe, 0xf762575d, 0x806567cb,
+    0x196c3671, 0x6e6b06e7, 0xfed41b76, 0x89d32be0, 0x10da7a5a, 0x67dd4acc,
+    0xf9b9df6f, 0x8ebeeff9, 0x17b7be43, 0x60b08ed5, 0xd6d6a3e8, 0xa1d1937e,
+    0x38d8c2c4, 0x4fdff252, 0xd1bb67f1, 0xa6bc5767, 0x3fb506dd, 0x48b2364b,
+    0xd80d2bda, 0xaf0a1b4c, 0x36034af6, 0x41047a60, 0xdf60efc3, 0xa867df55,
+    0x316e8eef, 0x4669be79, 0xcb61b38c, 0xbc66831a, 0x256fd2a0, 0x5268e236,
+    0xcc0c7795, 0xbb0b4703, 0x220216b9, 0x5505262f, 0xc5ba3bbe, 0xb2bd0b28,
+    0x2bb45a92, 0x5cb36a04, 0xc2d7ffa7, 0xb5d0cf31, 0x2cd99e8b, 0x5bdeae1d,
+    0x9b64c2b0, 0xec63f226, 0x756aa39c, 0x026d930a, 0x9c0906a9, 0xeb0e363f,
+    0x72076785, 0x05005713, 0x95bf4a82, 0xe2b87a14, 0x7bb12bae, 0x0cb61b38,
+    0x92d28e9b, 0xe5d5be0d, 0x7cdcefb7, 0x0bdbdf21, 0x86d3d2d4, 0xf1d4e242,
+    0x68ddb3f8, 0x1fda836e, 0x81be16cd, 0xf6b9265b, 0x6fb077e1, 0x18b74777,
+    0x88085ae6, 0xff0f6a70, 0x66063bca, 0x11010b5c, 0x8f659eff, 0xf862ae69,
+    0x616bffd3, 0x166ccf45, 0xa00ae278, 0xd70dd2ee, 0x4e048354, 0x3903b3c2,
+    0xa7672661, 0xd06016f7, 0x4969474d, 0x3e6e77db, 0xaed16a4a, 0xd9d65adc,
+    0x40df0b66, 0x37d83bf0, 0xa9bcae53, 0xdebb9ec5, 0x47b2cf7f, 0x30b5ffe9,
+    0xbdbdf21c, 0xcabac28a, 0x53b39330, 0x24b4a3a6, 0xbad03605, 0xcdd70693,
+    0x54de5729, 0x23d967bf, 0xb3667a2e, 0xc4614ab8, 0x5d681b02, 0x2a6f2b94,
+    0xb40bbe37, 0xc30c8ea1, 0x5a05df1b, 0x2d02ef8d
+];
+
+/// Writes a raster buffer as a PNG image.
+pub fn write_png<W: Writer>(buf: &RasterBuffer, w: &mut W) -> IoResult<()> {
+    try!(w.write(&PNG_SIGNATURE));
+    let mut header = Vec::new();
+    push_be_u32(&mut header, buf.width as u32);
+    push_be_u32(&mut header, buf.height as u32);
+    // 8 bits per sample, RGBA, deflate, adaptive filters, no interlacing.
+    header.push_all(&[8u8, 6, 0, 0, 0]);
+    try!(write_chunk(w, bytes!("IHDR"), header.as_slice()));
+
+    let stride = buf.width * 4;
+    let pixels = buf.pixels.as_slice();
+    let zeros = Vec::from_elem(stride, 0u8);
+    let mut filtered = Vec::with_capacity((stride + 1) * buf.height);
+    for y in range(0u, buf.height) {
+        let row = pixels.slice(y * stride, (y + 1) * stride);
+        let prev = if y == 0 { zeros.as_slice() }
+                   else { pixels.slice((y - 1) * stride, y * stride) };
+        let (mut best_filter, mut best_line, mut best_cost) = (0u8, Vec::new(), 0u);
+        for filter in range(0u8, 5) {
+            let line = filter_row(filter, row, prev, 4);
+            let cost = line.iter().fold(0u, |sum, &v| sum + (v as i8 as int).abs() as uint);
+            if filter == 0 || cost < best_cost {
+                best_filter = filter;
+                best_line = line;
+                best_cost = cost;
+            }
+        }
+        filtered.push(best_filter);
+        filtered.push_all_move(best_line);
+    }
+    try!(write_chunk(w, bytes!("IDAT"), zlib_compress(filtered.as_slice()).as_slice()));
+    write_chunk(w, bytes!("IEND"), &[])
+}
+
+/// Writes a raster buffer as a binary PPM image, dropping the alpha channel.
+pub fn write_ppm<W: Writer>(buf: &RasterBuffer, w: &mut W) -> IoResult<()> {
+    try!(write!(w, "P6\n{} {}\n255\n", buf.width, buf.height));
+    let mut rgb = Vec::with_capacity(buf.width * buf.height * 3);
+    for p in buf.pixels.as_slice().chunks(4) {
+        rgb.push_all(p.slice_to(3));
+    }
+    w.write(rgb.as_slice())
+}
+
+/// Writes a raster buffer as a PAM image with alpha.
+pub fn write_pam<W: Writer>(buf: &RasterBuffer, w: &mut W) -> IoResult<()> {
+    try!(write!(w, "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+        buf.width, buf.height));
+    w.write(buf.pixels.as_slice())
+}
+
+/// Reads a PNG image.
+///
+/// Interlaced images are not supported.
+pub fn read_png(data: &[u8]) -> Result<RasterBuffer, ~str> {
+    let signature: &[u8] = &PNG_SIGNATURE;
+    if data.len() < 8 || data.slice_to(8) != signature {
+        return Err(~"Not a PNG image");
+    }
+
+    // Width, height, bit depth, color type and number of channels.
+    let mut header: Option<(uint, uint, uint, uint, uint)> = None;
+    let mut palette = Vec::new();
+    let mut transparency = Vec::new();
+    let mut compressed = Vec::new();
+    let mut pos = 8;
+    loop {
+        if data.len() < pos + 12 { return Err(~"Unexpected end of PNG image"); }
+        let len = be_u32(data.slice(pos, pos + 4)) as uint;
+        if data.len() < pos + 12 + len { return Err(~"Unexpected end of PNG image"); }
+        let kind = data.slice(pos + 4, pos + 8);
+        let body = data.slice(pos + 8, pos + 8 + len);
+        if crc32(data.slice(pos + 4, pos + 8 + len)) != be_u32(data.slice_from(pos + 8 + len)) {
+            return Err(~"Wrong PNG chunk checksum");
+        }
+        pos += 12 + len;
+
+        if kind == bytes!("IHDR") {
+            if len != 13 { return Err(~"Invalid PNG header"); }
+            let (depth, color_type) = (body[8] as uint, body[9] as uint);
+            let channels = match (color_type, depth) {
+                (0, 1) | (0, 2) | (0, 4) | (0, 8) | (0, 16) => 1,
+                (2, 8) | (2, 16) => 3,
+                (3, 1) | (3, 2) | (3, 4) | (3, 8) => 1,
+                (4, 8) | (4, 16) => 2,
+                (6, 8) | (6, 16) => 4,
+                _ => return Err(format!("Unsupported PNG color type {} with bit depth {}",
+                    color_type, depth)),
+            };
+            if body[10] != 0 || body[11] != 0 { return Err(~"Invalid PNG header"); }
+            if body[12] != 0 { return Err(~"Interlaced PNG images are not supported"); }
+            let (width, height) = (be_u32(body), be_u32(body.slice_from(4)));
+            if width > 0x7fffffff || height > 0x7fffffff {
+                return Err(~"Invalid PNG image size");
+            }
+            header = Some((width as uint, height as uint, depth, color_type, channels));
+        } else if header.is_none() {
+            return Err(~"Expected PNG header");
+        } else if kind == bytes!("PLTE") {
+            palette = Vec::from_slice(body);
+        } else if kind == bytes!("tRNS") {
+            transparency = Vec::from_slice(body);
+        } else if kind == bytes!("IDAT") {
+            compressed.push_all(body);
+        } else if kind == bytes!("IEND") {
+            break;
+        } else if kind[0] & 0x20 == 0 {
+            return Err(~"Unknown critical PNG chunk");
+        }
+    }
+
+    let (width, height, depth, color_type, channels) = header.unwrap();
+    let stride = match width.checked_mul(&(channels * depth)) {
+        None => return Err(~"Image too large"),
+        Some(bits) => bits / 8 + if bits % 8 == 0 { 0 } else { 1 },
+    };
+    let filtered = match (stride + 1).checked_mul(&height) {
+        None => return Err(~"Image too large"),
+        Some(filtered) => filtered,
+    };
+    let count = match width.checked_mul(&height).and_then(|n| n.checked_mul(&4)) {
+        None => return Err(~"Image too large"),
+        Some(count) => count,
+    };
+    let data = try!(zlib_decompress(compressed.as_slice()));
+    if data.len() < filtered { return Err(~"Unexpected end of PNG image data"); }
+    let bpp = if channels * depth < 8 { 1 } else { channels * depth / 8 };
+    let mut raw = Vec::with_capacity(stride * height);
+    let mut prev = Vec::from_elem(stride, 0u8);
+    for y in range(0u, height) {
+        let start = y * (stride + 1);
+        let mut row = Vec::from_slice(data.slice(start + 1, start + 1 + stride));
+        try!(unfilter_row(*data.get(start), row.as_mut_slice(), prev.as_slice(), bpp));
+        raw.push_all(row.as_slice());
+        prev = row;
+    }
+
+    // The transparent color of gray and RGB images, as 16 bit samples.
+    let key: Vec<uint> = if color_type == 0 || color_type == 2 {
+        transparency.as_slice().chunks(2).filter(|c| c.len() == 2)
+            .map(|c| be_u16(c)).collect()
+    } else {
+        Vec::new()
+    };
+    let max = (1u << depth) - 1;
+    let mut pixels = Vec::with_capacity(count);
+    for y in range(0u, height) {
+        let row = raw.slice(y * stride, (y + 1) * stride);
+        for x in range(0u, width) {
+            if color_type == 3 {
+                let i = sample(row, x, depth);
+                if palette.len() < i * 3 + 3 { return Err(~"PNG palette index out of range"); }
+                let alpha = if i < transparency.len() { *transparency.get(i) } else { 255 };
+                pixels.push_all(palette.slice(i * 3, i * 3 + 3));
+                pixels.push(alpha);
+                continue;
+            }
+            let mut s = [0u, ..4];
+            for c in range(0u, channels) {
+                s[c] = sample(row, x * channels + c, depth);
+            }
+            let mut rgba = to_rgba(s.slice_to(channels), max);
+            if key.len() == channels && key.as_slice() == s.slice_to(channels) {
+                rgba[3] = 0;
+            }
+            pixels.push_all(&rgba);
+        }
+    }
+    Ok(image(width, height, pixels))
+}
+
+/// Reads a Netpbm image in the PGM, PPM or PAM format.
+///
+/// Both the plain text and binary variants are supported.
+/// PAM images with 1 to 4 channels are read as gray, gray with alpha,
+/// RGB or RGBA, whatever their tuple type.
+pub fn read_ppm(data: &[u8]) -> Result<RasterBuffer, ~str> {
+    let mut tokens = Tokens { data: data, pos: 0 };
+    let magic = match tokens.word() {
+        None => return Err(~"Not a PNM image"),
+        Some(magic) => magic,
+    };
+    let (width, height, channels, max, binary) = if magic == bytes!("P7") {
+        let (width, height, channels, max) = try!(read_pam_header(&mut tokens));
+        (width, height, channels, max, true)
+    } else {
+        let (channels, binary) = if magic == bytes!("P2") { (1, false) }
+            else if magic == bytes!("P3") { (3, false) }
+            else if magic == bytes!("P5") { (1, true) }
+            else if magic == bytes!("P6") { (3, true) }
+            else { return Err(~"Not a PNM image") };
+        let width = try!(tokens.number());
+        let height = try!(tokens.number());
+        let max = try!(tokens.number());
+        (width, height, channels, max, binary)
+    };
+    if max == 0 || max > 65535 { return Err(~"Invalid maximum sample value"); }
+
+    let count = match width.checked_mul(&height).and_then(|n| n.checked_mul(&channels)) {
+        None => return Err(~"Image too large"),
+        Some(count) => count,
+    };
+    // Every sample takes at least one byte, so this bounds the allocations.
+    if data.len() < count { return Err(~"Unexpected end of image data"); }
+    let mut samples = Vec::with_capacity(count);
+    if binary {
+        // A single whitespace character separates the header from the samples.
+        let start = tokens.pos + 1;
+        let size = if max < 256 { 1 } else { 2 };
+        if data.len() < start + count * size { return Err(~"Unexpected end of image data"); }
+        let bytes = data.slice(start, start + count * size);
+        for i in range(0u, count) {
+            samples.push(if size == 1 { bytes[i] as uint }
+                         else { be_u16(bytes.slice(i * 2, i * 2 + 2)) });
+        }
+    } else {
+        for _ in range(0u, count) {
+            samples.push(try!(tokens.number()));
+        }
+    }
+    if samples.iter().any(|&v| v > max) { return Err(~"Sample larger than maximum value"); }
+
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for s in samples.as_slice().chunks(channels) {
+        pixels.push_all(&to_rgba(s, max));
+    }
+    Ok(image(width, height, pixels))
+}
+
+/// Reads the header fields of a PAM image up to ENDHDR.
+///
+/// Returns the width, height, number of channels and maximum sample value.
+fn read_pam_header(tokens: &mut Tokens) -> Result<(uint, uint, uint, uint), ~str> {
+    let (mut width, mut height, mut depth, mut max) = (None, None, None, None);
+    loop {
+        match tokens.word() {
+            None => return Err(~"Unexpected end of image header"),
+            Some(w) if w == bytes!("ENDHDR") => break,
+            Some(w) if w == bytes!("WIDTH") => width = Some(try!(tokens.number())),
+            Some(w) if w == bytes!("HEIGHT") => height = Some(try!(tokens.number())),
+            Some(w) if w == bytes!("DEPTH") => depth = Some(try!(tokens.number())),
+            Some(w) if w == bytes!("MAXVAL") => max = Some(try!(tokens.number())),
+            Some(w) if w == bytes!("TUPLTYPE") => { tokens.word(); }
+            Some(_) => return Err(~"Unknown PAM header field"),
+        }
+    }
+    match (width, height, depth, max) {
+        (Some(width), Some(height), Some(depth), Some(max)) if depth >= 1 && depth <= 4 =>
+            Ok((width, height, depth, max)),
+        _ => Err(~"Incomplete PAM header"),
+    }
+}
+
+/// Reads whitespace separated words from a Netpbm header,
+/// skipping comments.
+struct Tokens<'a> {
+    data: &'a [u8],
+    pos: uint,
+}
+
+impl<'a> Tokens<'a> {
+    /// Reads the next word.
+    fn word(&mut self) -> Option<&'a [u8]> {
+        let data = self.data;
+        loop {
+            if self.pos >= data.len() { return None; }
+            let c = data[self.pos];
+            if c == '#' as u8 {
+                while self.pos < data.len() && data[self.pos] != '\n' as u8 { self.pos += 1; }
+            } else if is_space(c) {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        let start = self.pos;
+        while self.pos < data.len() && !is_space(data[self.pos]) { self.pos += 1; }
+        Some(data.slice(start, self.pos))
+    }
+
+    /// Reads the next word as a number.
+    fn number(&mut self) -> Result<uint, ~str> {
+        match self.word() {
+            None => Err(~"Unexpected end of image"),
+            Some(word) => match str::from_utf8(word).and_then(|s| from_str::<uint>(s)) {
+                None => Err(~"Expected number in image"),
+                Some(v) => Ok(v),
+            },
+        }
+    }
+}
+
+/// Returns true for whitespace in Netpbm headers.
+#[inline(always)]
+fn is_space(c: u8) -> bool {
+    c == ' ' as u8 || c == '\t' as u8 || c == '\n' as u8 || c == '\r' as u8
+        || c == 0x0b || c == 0x0c
+}
+
+/// Creates a raster buffer holding decoded pixels.
+fn image(width: uint, height: uint, pixels: Vec<u8>) -> RasterBuffer {
+    let mut buf = RasterBuffer::new(width, height);
+    buf.pixels = pixels;
+    buf
+}
+
+/// Converts gray, gray with alpha, RGB or RGBA samples to 8 bit RGBA.
+fn to_rgba(s: &[uint], max: uint) -> [u8, ..4] {
+    match s.len() {
+        1 => [scale(s[0], max), scale(s[0], max), scale(s[0], max), 255],
+        2 => [scale(s[0], max), scale(s[0], max), scale(s[0], max), scale(s[1], max)],
+        3 => [scale(s[0], max), scale(s[1], max), scale(s[2], max), 255],
+        _ => [scale(s[0], max), scale(s[1], max), scale(s[2], max), scale(s[3], max)],
+    }
+}
+
+/// Scales a sample from 0 to a maximum value into a byte, rounding to nearest.
+#[inline(always)]
+fn scale(v: uint, max: uint) -> u8 {
+    ((v * 255 + max / 2) / max) as u8
+}
+
+/// Returns a sample of a PNG row, with samples of less than
+/// a byte packed from the most significant bit.
+fn sample(row: &[u8], i: uint, depth: uint) -> uint {
+    match depth {
+        16 => be_u16(row.slice(i * 2, i * 2 + 2)),
+        8 => row[i] as uint,
+        _ => {
+            let bit = i * depth;
+            (row[bit / 8] as uint >> (8 - depth - bit % 8)) & ((1 << depth) - 1)
+        }
+    }
+}
+
+/// Returns the value a PNG filter predicts from the bytes to the left,
+/// above and above left.
+fn predict(filter: u8, a: u8, b: u8, c: u8) -> u8 {
+    match filter {
+        1 => a,
+        2 => b,
+        3 => ((a as uint + b as uint) / 2) as u8,
+        4 => {
+            let p = a as int + b as int - c as int;
+            let (pa, pb, pc) = ((p - a as int).abs(), (p - b as int).abs(), (p - c as int).abs());
+            if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+        }
+        _ => 0,
+    }
+}
+
+/// Filters a row of bytes given the previous row.
+fn filter_row(filter: u8, row: &[u8], prev: &[u8], bpp: uint) -> Vec<u8> {
+    Vec::from_fn(row.len(), |i| {
+        let (a, c) = if i >= bpp { (row[i - bpp], prev[i - bpp]) } else { (0, 0) };
+        row[i] - predict(filter, a, prev[i], c)
+    })
+}
+
+/// Reverses the filter of a row in place given the previous unfiltered row.
+fn unfilter_row(filter: u8, row: &mut [u8], prev: &[u8], bpp: uint) -> Result<(), ~str> {
+    if filter > 4 { return Err(format!("Unknown PNG filter {}", filter)); }
+    for i in range(0u, row.len()) {
+        let (a, c) = if i >= bpp { (row[i - bpp], prev[i - bpp]) } else { (0, 0) };
+        row[i] += predict(filter, a, prev[i], c);
+    }
+    Ok(())
+}
+
+/// Writes a PNG chunk with its length and checksum.
+fn write_chunk<W: Writer>(w: &mut W, kind: &[u8], body: &[u8]) -> IoResult<()> {
+    try!(w.write_be_u32(body.len() as u32));
+    let mut checked = Vec::from_slice(kind);
+    checked.push_all(body);
+    try!(w.write(checked.as_slice()));
+    w.write_be_u32(crc32(checked.as_slice()))
+}
+
+/// Returns the CRC-32 checksum used by PNG chunks.
+fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(0xffffffffu32, |crc, &v| {
+        CRC_TABLE[((crc ^ v as u32) & 0xff) as uint] ^ (crc >> 8)
+    });
+    crc ^ 0xffffffff
+}
+
+/// Appends a big endian 32 bit number.
+fn push_be_u32(out: &mut Vec<u8>, v: u32) {
+    for shift in [24u, 16, 8, 0].iter() {
+        out.push((v >> *shift) as u8);
+    }
+}
+
+/// Reads a big endian 32 bit number.
+#[inline(always)]
+fn be_u32(b: &[u8]) -> u32 {
+    b.slice_to(4).iter().fold(0u32, |sum, &v| (sum << 8) | v as u32)
+}
+
+/// Reads a big endian 16 bit number.
+#[inline(always)]
+fn be_u16(b: &[u8]) -> uint {
+    (b[0] as uint << 8) | b[1] as uint
+}
+
+#[test]
+fn test_image() {
+    use std::io::MemWriter;
+    use graphics::{Shader, Rect, Ellipse};
+
+    let mut buf: RasterBuffer = RasterBuffer::new(13, 7);
+    buf.color = [0.2, 0.4, 1.0, 0.5];
+    buf.shade(&Ellipse(&[1.0, 1.0, 11.0, 5.0]));
+    buf.color = [1.0, 0.5, 0.0, 1.0];
+    buf.shade(&Rect(&[6.0, 0.0, 4.0, 3.0]));
+
+    let mut w = MemWriter::new();
+    write_png(&buf, &mut w).unwrap();
+    let png = read_png(w.get_ref()).unwrap();
+    assert_eq!((png.width, png.height), (13, 7));
+    assert!(png.pixels == buf.pixels);
+    let mut corrupt = Vec::from_slice(w.get_ref());
+    *corrupt.get_mut(20) ^= 1;
+    assert!(read_png(corrupt.as_slice()).is_err());
+
+    // Sizes that do not fit are rejected before allocating.
+    let resize = |width: u32, height: u32| {
+        let mut data = Vec::from_slice(w.get_ref());
+        for i in range(0u, 4) {
+            *data.get_mut(16 + i) = (width >> (24 - 8 * i)) as u8;
+            *data.get_mut(20 + i) = (height >> (24 - 8 * i)) as u8;
+        }
+        let crc = crc32(data.slice(12, 29));
+        for i in range(0u, 4) {
+            *data.get_mut(29 + i) = (crc >> (24 - 8 * i)) as u8;
+        }
+        read_png(data.as_slice())
+    };
+    assert!(resize(13, 7).is_ok());
+    assert_eq!(resize(0x80000000, 1).err(), Some(~"Invalid PNG image size"));
+    assert!(resize(0x7fffffff, 0x7fffffff).is_err());
+
+    let mut w = MemWriter::new();
+    write_pam(&buf, &mut w).unwrap();
+    assert!(read_ppm(w.get_ref()).unwrap().pixels == buf.pixels);
+    let mut w = MemWriter::new();
+    write_ppm(&buf, &mut w).unwrap();
+    let ppm = read_ppm(w.get_ref()).unwrap();
+    assert_eq!(ppm.get_pixel(7, 1), [255, 128, 0, 255]);
+    assert_eq!(ppm.get_pixel(0, 0), [0, 0, 0, 255]);
+
+    // Written by another encoder: a 3x2 palette image with 2 bits per pixel,
+    // a transparent palette entry and the Sub and Up filters.
+    let palette = [0x89u8, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d,
+        0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x02, 0x02, 0x03,
+        0x00, 0x00, 0x00, 0xe0, 0x1a, 0x8e, 0x89, 0x00, 0x00, 0x00, 0x0c, 0x50, 0x4c, 0x54,
+        0x45, 0xff, 0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xfb,
+        0x00, 0x60, 0xf6, 0x00, 0x00, 0x00, 0x02, 0x74, 0x52, 0x4e, 0x53, 0xff, 0x80, 0x08,
+        0x0f, 0xb3, 0x6a, 0x00, 0x00, 0x00, 0x0c, 0x49, 0x44, 0x41, 0x54, 0x78, 0xda, 0x63,
+        0x94, 0x60, 0x3a, 0x03, 0x00, 0x01, 0x20, 0x00, 0xe8, 0x5c, 0xef, 0x6e, 0xbd, 0x00,
+        0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82];
+    let png = read_png(&palette).unwrap();
+    assert_eq!(png.get_pixel(1, 0), [0, 255, 0, 128]);
+    assert_eq!(png.get_pixel(0, 1), [255, 255, 255, 255]);
+    assert_eq!(png.get_pixel(1, 1), [0, 0, 255, 255]);
+
+    let text = bytes!("P3\n# A comment\n2 1 15\n15 0 0  0 15 7\n");
+    let ppm = read_ppm(text).unwrap();
+    assert_eq!(ppm.get_pixel(1, 0), [0, 255, 119, 255]);
+    assert!(read_ppm(bytes!("P3 2 1 15 15 0 0 0 16 7")).is_err());
+    assert!(read_ppm(bytes!("P4 1 1")).is_err());
+    // Huge sizes fail before allocating.
+    assert!(read_ppm(bytes!("P6 4294967296 4294967296 255\n")).is_err());
+    assert!(read_ppm(bytes!("P5 100000 100000 255\n\x00")).is_err());
+}
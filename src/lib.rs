@@ -6,10 +6,12 @@
 //! A library of experiments with types and data structures..
 
 extern crate collections;
+extern crate sync;
 
 pub mod touch;
 pub mod state;
 pub mod physics;
 pub mod graphics;
 pub mod graphics_new;
+pub mod geometry;
 
@@ -0,0 +1,257 @@
+
+//! Triangle meshes made from shapes.
+//!
+//! Outlines are triangulated by ear clipping,
+//! which handles concave contours but expects them not to cross themselves.
+//! Open outlines are closed by a straight line, like the rasterizer fills them.
+//! Meshes can be drawn as `Triangle` shapes with any shader,
+//! or written as Wavefront OBJ, either flat or extruded along z.
+
+use std::io::{IoResult, Writer};
+use std::num::{Float, zero};
+use collections::HashSet;
+
+use graphics::{Shader, Shape, Triangle};
+use graphics::curve::outline;
+use graphics::scalar::Scalar;
+use graphics_new::identity;
+
+/// Vertices with triangles indexing into them.
+#[deriving(Clone, Eq, Show)]
+pub struct Mesh<Fl=f64> {
+    /// The vertices.
+    pub vertices: Vec<[Fl, ..2]>,
+    /// Counterclockwise triangles as indices into the vertices.
+    pub triangles: Vec<[uint, ..3]>,
+}
+
+impl<Fl: Scalar> Mesh<Fl> {
+    /// Creates an empty mesh.
+    pub fn new() -> Mesh<Fl> {
+        Mesh { vertices: Vec::new(), triangles: Vec::new() }
+    }
+
+    /// Creates a mesh covering the area of a shape.
+    ///
+    /// The tolerance is the maximum error when approximating round parts.
+    /// Lines have no area and give an empty mesh.
+    pub fn from_shape(shape: &Shape<Fl>, tolerance: Fl) -> Mesh<Fl> {
+        let mut res = Mesh::new();
+        res.add_shape(shape, tolerance);
+        res
+    }
+
+    /// Adds the area of a shape.
+    ///
+    /// A `Triangle` is added as it is,
+    /// other shapes are triangulated from their outline.
+    pub fn add_shape(&mut self, shape: &Shape<Fl>, tolerance: Fl) {
+        match *shape {
+            Triangle(t) => self.add_triangles(t),
+            _ => {
+                for &(ref contour, _) in outline(shape, &identity(), tolerance).iter() {
+                    self.add_contour(contour.as_slice());
+                }
+            }
+        }
+    }
+
+    /// Adds a list of triangles [x1, y1, x2, y2, x3, y3, ...].
+    pub fn add_triangles(&mut self, triangles: &[Fl]) {
+        for t in triangles.chunks(6) {
+            if t.len() < 6 { break; }
+            let start = self.vertices.len();
+            self.vertices.push([t[0], t[1]]);
+            self.vertices.push([t[2], t[3]]);
+            self.vertices.push([t[4], t[5]]);
+            let (a, b, c) = ([t[0], t[1]], [t[2], t[3]], [t[4], t[5]]);
+            self.triangles.push(if cross(a, b, c) < zero() {
+                [start, start + 2, start + 1]
+            } else {
+                [start, start + 1, start + 2]
+            });
+        }
+    }
+
+    /// Adds the area inside a closed contour.
+    pub fn add_contour(&mut self, contour: &[[Fl, ..2]]) {
+        let start = self.vertices.len();
+        self.vertices.push_all(contour);
+        for t in triangulate(contour).iter() {
+            self.triangles.push([start + t[0], start + t[1], start + t[2]]);
+        }
+    }
+
+    /// Returns the area covered by the triangles.
+    pub fn area(&self) -> Fl {
+        let half: Fl = Scalar::from_f64(0.5);
+        let v = self.vertices.as_slice();
+        self.triangles.iter().fold(zero(), |sum, t|
+            sum + half * cross(v[t[0]], v[t[1]], v[t[2]]))
+    }
+
+    /// Draws the triangles with a shader.
+    pub fn shade<S: Shader<Fl>>(&self, shader: &mut S) {
+        let v = self.vertices.as_slice();
+        for t in self.triangles.iter() {
+            let (a, b, c) = (v[t[0]], v[t[1]], v[t[2]]);
+            shader.shade(&Triangle(&[a[0], a[1], b[0], b[1], c[0], c[1]]));
+        }
+    }
+
+    /// Writes the mesh as Wavefront OBJ in the plane z = 0.
+    pub fn write_obj<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        for v in self.vertices.iter() {
+            try!(write!(w, "v {} {} 0\n", v[0], v[1]));
+        }
+        for t in self.triangles.iter() {
+            try!(write!(w, "f {} {} {}\n", t[0] + 1, t[1] + 1, t[2] + 1));
+        }
+        Ok(())
+    }
+
+    /// Writes the mesh as Wavefront OBJ extruded from z = 0 to a depth.
+    ///
+    /// The top and bottom face outwards,
+    /// and each outline edge becomes a side of two triangles.
+    pub fn write_extruded_obj<W: Writer>(&self, w: &mut W, depth: Fl) -> IoResult<()> {
+        let n = self.vertices.len();
+        for &z in [zero(), depth].iter() {
+            for v in self.vertices.iter() {
+                try!(write!(w, "v {} {} {}\n", v[0], v[1], z));
+            }
+        }
+        for t in self.triangles.iter() {
+            try!(write!(w, "f {} {} {}\n", t[0] + 1, t[2] + 1, t[1] + 1));
+            try!(write!(w, "f {} {} {}\n", t[0] + n + 1, t[1] + n + 1, t[2] + n + 1));
+        }
+        for &(a, b) in self.outline_edges().iter() {
+            try!(write!(w, "f {} {} {}\n", a + 1, b + 1, b + n + 1));
+            try!(write!(w, "f {} {} {}\n", a + 1, b + n + 1, a + n + 1));
+        }
+        Ok(())
+    }
+
+    /// Returns the directed edges used by only one triangle.
+    fn outline_edges(&self) -> Vec<(uint, uint)> {
+        let mut edges = Vec::new();
+        for t in self.triangles.iter() {
+            for i in range(0u, 3) {
+                edges.push((t[i], t[(i + 1) % 3]));
+            }
+        }
+        let directed: HashSet<(uint, uint)> = edges.iter().map(|&e| e).collect();
+        edges.move_iter().filter(|&(a, b)| !directed.contains(&(b, a))).collect()
+    }
+}
+
+/// Triangulates a closed contour by ear clipping.
+///
+/// Returns counterclockwise triangles as indices into the contour.
+/// Repeated points and collinear parts do not give triangles.
+pub fn triangulate<Fl: Scalar>(contour: &[[Fl, ..2]]) -> Vec<[uint, ..3]> {
+    let o: Fl = zero();
+    let mut res = Vec::new();
+    let mut indices: Vec<uint> = Vec::new();
+    for i in range(0u, contour.len()) {
+        if indices.last().map_or(false, |&j| contour[j] == contour[i]) { continue; }
+        indices.push(i);
+    }
+    while indices.len() > 1 && contour[indices.as_slice()[0]] == contour[*indices.last().unwrap()] {
+        indices.pop();
+    }
+    let area = range(0u, indices.len()).fold(o, |sum, i| {
+        let (a, b) = (contour[*indices.get(i)], contour[*indices.get((i + 1) % indices.len())]);
+        sum + a[0] * b[1] - b[0] * a[1]
+    });
+    if area < o { indices.as_mut_slice().reverse(); }
+
+    while indices.len() >= 3 {
+        let m = indices.len();
+        let mut ear = None;
+        for i in range(0u, m) {
+            let (a, b, c) = corner(indices.as_slice(), i);
+            if cross(contour[a], contour[b], contour[c]) <= o { continue; }
+            let blocked = indices.iter().any(|&j| {
+                let p = contour[j];
+                p != contour[a] && p != contour[b] && p != contour[c]
+                && inside_triangle(contour[a], contour[b], contour[c], p)
+            });
+            if !blocked { ear = Some(i); break; }
+        }
+        // Without an ear the rest is degenerate,
+        // so the most convex corner is cut off to make progress.
+        let i = match ear {
+            Some(i) => i,
+            None => {
+                let mut best = (0u, Float::neg_infinity());
+                for i in range(0u, m) {
+                    let (a, b, c) = corner(indices.as_slice(), i);
+                    let convex = cross(contour[a], contour[b], contour[c]);
+                    let (_, best_convex) = best;
+                    if convex > best_convex { best = (i, convex); }
+                }
+                let (i, _) = best;
+                i
+            }
+        };
+        let (a, b, c) = corner(indices.as_slice(), i);
+        if cross(contour[a], contour[b], contour[c]) > o { res.push([a, b, c]); }
+        indices.remove(i);
+    }
+    res
+}
+
+/// Returns the previous, current and next index of a corner.
+#[inline(always)]
+fn corner(indices: &[uint], i: uint) -> (uint, uint, uint) {
+    let m = indices.len();
+    (indices[(i + m - 1) % m], indices[i], indices[(i + 1) % m])
+}
+
+/// Returns twice the signed area of a triangle,
+/// positive when counterclockwise.
+#[inline(always)]
+fn cross<Fl: Scalar>(a: [Fl, ..2], b: [Fl, ..2], c: [Fl, ..2]) -> Fl {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Returns true if a point is inside or on the edge of a counterclockwise triangle.
+#[inline(always)]
+fn inside_triangle<Fl: Scalar>(a: [Fl, ..2], b: [Fl, ..2], c: [Fl, ..2], p: [Fl, ..2]) -> bool {
+    let o: Fl = zero();
+    cross(a, b, p) >= o && cross(b, c, p) >= o && cross(c, a, p) >= o
+}
+
+#[test]
+fn test_mesh() {
+    use std::io::MemWriter;
+    use std::str;
+    use graphics::{Polygon, Ellipse, Line, QuadraticBezierFree};
+
+    // An L shape is concave.
+    let l = [0.0, 0.0, 2.0, 0.0, 2.0, 1.0, 1.0, 1.0, 1.0, 2.0, 0.0, 2.0];
+    let mesh: Mesh = Mesh::from_shape(&Polygon(&l), 0.1);
+    assert_eq!(mesh.triangles.len(), 4);
+    assert!((mesh.area() - 3.0).abs() < 1e-9);
+
+    let ellipse: Mesh = Mesh::from_shape(&Ellipse(&[0.0, 0.0, 2.0, 2.0]), 0.001);
+    let pi: f64 = Float::pi();
+    assert!((ellipse.area() - pi).abs() < 0.01);
+
+    // An open curve is closed by a straight line, a line has no area.
+    let curve: Shape = QuadraticBezierFree(&[0.0, 0.0, 0.0, 3.0, 3.0, 3.0, 3.0, 0.0]);
+    assert!((Mesh::from_shape(&curve, 0.001).area() - 5.4).abs() < 0.01);
+    let line: Shape = Line(&[0.0, 0.0, 1.0, 1.0]);
+    assert_eq!(Mesh::from_shape(&line, 0.1).triangles.len(), 0);
+
+    let mut mesh: Mesh = Mesh::new();
+    mesh.add_triangles(&[0.0, 0.0, 0.0, 1.0, 1.0, 0.0]);
+    assert!((mesh.area() - 0.5).abs() < 1e-9);
+    let mut w = MemWriter::new();
+    mesh.write_extruded_obj(&mut w, 1.0).unwrap();
+    let text = str::from_utf8(w.get_ref()).unwrap();
+    assert_eq!(text.lines().filter(|line| line.starts_with("v ")).count(), 6);
+    // Top, bottom and two triangles for each of the three sides.
+    assert_eq!(text.lines().filter(|line| line.starts_with("f ")).count(), 8);
+}
@@ -0,0 +1,744 @@
+
+//! A backend writing shapes to a PDF document.
+//!
+//! Each shape becomes a path painted inside its own graphics state,
+//! with the view written as `cm` matrices.
+//! Pages have the origin in the top left corner with y pointing down,
+//! like the other backends.
+//! The effect set with `set_effect` decides how following shapes are painted.
+//! Pens, brushes and gradients are translated through the
+//! `PdfPen`, `PdfBrush` and `PdfGradient` traits,
+//! so other types can be used by implementing them.
+//! Strokes with caps or joins PDF does not have, or different caps at both ends,
+//! are written as filled paths by `EffectShader::shade_effect`.
+//!
+//! Opacity and blend modes are written as graphics state parameters.
+//! PDF has no other Porter-Duff operators and no additive blending,
+//! so they are drawn with source-over.
+//! Linear and radial gradients become shadings,
+//! which ignore the alpha of the stops and always pad.
+//! Conic gradients are drawn with their average color.
+//!
+//! Each region of the clip becomes a clipping path,
+//! so shapes are clipped to the intersection of the regions.
+
+use std::io::{IoResult, Writer};
+use std::mem;
+use std::num::{zero, one};
+
+use graphics::{Shader, Shape, View, Matrix, Effect, Stroke, Fill, Gradient};
+use graphics::{Pixel, PixelHorizontalLine, PixelVerticalLine, PixelLine};
+use graphics::{Line, Rect, RoundRect, Ellipse, Circle, Triangle, Polygon};
+use graphics::{Catmull, QuadraticBezierBound, QuadraticBezierFree};
+use graphics::simple_pen::Pen;
+use graphics::advanced_pen::{AdvancedPen, RoundLineCap, SquareLineCap};
+use graphics::advanced_pen::{RoundLineJoin, SquareLineJoin};
+use graphics::stroke::stroke_shape;
+use graphics::curve::{Curve, LineSegment, QuadraticSegment, CubicSegment};
+use graphics::gradient::{ColorGradient, LinearGradient, RadialGradient, ConicGradient};
+use graphics::brush::{FillRule, NonZero, EvenOdd, SolidBrush};
+use graphics::dash::Dash;
+use graphics::composite::{CompositeOp, SourceOver, Multiply, Screen, Overlay};
+use graphics::composite::{Darken, Lighten, Difference};
+use graphics::clip::Clip;
+use graphics::deflate::zlib_compress;
+use graphics::scalar::Scalar;
+use graphics::scene::EffectShader;
+use graphics::damage::ClipShader;
+use graphics_new::{Backend, Matrix2d, Color, identity};
+
+/// The distance of Bezier control points for a quarter circle of radius 1.
+static KAPPA: f64 = 0.5522847498307936;
+
+/// Implemented by pens that can be written as PDF stroke parameters.
+pub trait PdfPen<Fl=f64> {
+    /// Returns the stroke color.
+    fn pdf_color(&self) -> [Fl, ..4];
+    /// Returns operators setting the line width, caps, joins and dashes.
+    fn pdf_stroke_state(&self) -> ~str;
+
+    /// Returns a pen to stroke with as a filled path,
+    /// when the stroke can not be written as parameters.
+    fn pdf_outline<'a>(&'a self) -> Option<&'a AdvancedPen<Fl>> {
+        None
+    }
+}
+
+/// Implemented by brushes that can be written as PDF fill colors.
+pub trait PdfBrush<Fl=f64> {
+    /// Returns the fill color.
+    fn pdf_color(&self) -> [Fl, ..4];
+
+    /// Returns the fill rule.
+    fn pdf_fill_rule(&self) -> FillRule {
+        NonZero
+    }
+}
+
+/// Implemented by gradients that can be written as PDF shadings.
+pub trait PdfGradient<Fl=f64> {
+    /// Returns a shading dictionary together with the transform
+    /// from shading coordinates to shape coordinates,
+    /// or `None` if the gradient is drawn with a single color.
+    fn pdf_shading(&self) -> Option<(~str, Matrix<Fl>)>;
+    /// Returns the color used when the gradient can not be a shading.
+    fn pdf_color(&self) -> [Fl, ..4];
+}
+
+impl<Fl: Scalar> PdfPen<Fl> for Pen<Fl> {
+    fn pdf_color(&self) -> [Fl, ..4] {
+        self.color
+    }
+
+    fn pdf_stroke_state(&self) -> ~str {
+        format!("{} w 1 J 1 j{}", num(self.width.as_f64()), dash_ops(&self.dash))
+    }
+}
+
+impl<Fl: Scalar> PdfPen<Fl> for AdvancedPen<Fl> {
+    fn pdf_color(&self) -> [Fl, ..4] {
+        self.color
+    }
+
+    fn pdf_stroke_state(&self) -> ~str {
+        let cap = match self.line_cap_start {
+            RoundLineCap => 1,
+            SquareLineCap => 2,
+            _ => 0,
+        };
+        let join = match self.line_join {
+            RoundLineJoin => 1,
+            SquareLineJoin => 0,
+            _ => 2,
+        };
+        format!("{} w {} J {} j{}", num(self.width.as_f64()), cap, join, dash_ops(&self.dash))
+    }
+
+    fn pdf_outline<'a>(&'a self) -> Option<&'a AdvancedPen<Fl>> {
+        if self.has_standard_ends() { None } else { Some(self) }
+    }
+}
+
+impl<Fl: Scalar> PdfBrush<Fl> for [Fl, ..4] {
+    fn pdf_color(&self) -> [Fl, ..4] {
+        *self
+    }
+}
+
+impl<Fl: Scalar> PdfBrush<Fl> for SolidBrush<Fl> {
+    fn pdf_color(&self) -> [Fl, ..4] {
+        self.color
+    }
+
+    fn pdf_fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+impl<Fl: Scalar> PdfGradient<Fl> for ColorGradient<Fl> {
+    fn pdf_shading(&self) -> Option<(~str, Matrix<Fl>)> {
+        if self.stops.len() == 0 { return None; }
+        let (kind, coords) = match self.kind {
+            LinearGradient(l) => (2, nums(l)),
+            RadialGradient(r) => (3, format!("{} {} 0 {}",
+                num(r[0].as_f64()), num(r[1].as_f64()), nums(r))),
+            ConicGradient(_) => return None,
+        };
+        Some((format!("<< /ShadingType {} /ColorSpace /DeviceRGB /Coords [{}] \
+            /Function {} /Extend [true true] >>",
+            kind, coords, stops_function(self.stops.as_slice())), *self.get_transform()))
+    }
+
+    fn pdf_color(&self) -> [Fl, ..4] {
+        let n: Fl = Scalar::from_f64(self.stops.len() as f64);
+        let mut color = [zero(), ..4];
+        for &(_, c) in self.stops.iter() {
+            for i in range(0u, 4) { color[i] = color[i] + c[i] / n; }
+        }
+        color
+    }
+}
+
+/// A page of a document.
+struct Page<Fl> {
+    size: [Fl, ..2],
+    content: ~str,
+}
+
+/// Writes shapes to a PDF document with one or more pages.
+pub struct PdfShader<Fl=f64> {
+    /// The width of new pages.
+    pub width: Fl,
+    /// The height of new pages.
+    pub height: Fl,
+    /// Whether page contents are compressed.
+    pub compress: bool,
+    /// How following shapes combine with the shapes below.
+    pub composite: CompositeOp,
+    /// The clip in page coordinates.
+    pub clip: Clip<Fl>,
+    pages: Vec<Page<Fl>>,
+    states: Vec<(Fl, CompositeOp)>,
+    shadings: Vec<~str>,
+    color: [Fl, ..4],
+    shading: Option<(uint, Matrix<Fl>)>,
+    fill_rule: FillRule,
+    stroke: Option<~str>,
+    view: View<Fl>,
+}
+
+impl<Fl: Scalar> PdfShader<Fl> {
+    /// Creates a document with one empty page, painting with black fill.
+    pub fn new(width: Fl, height: Fl) -> PdfShader<Fl> {
+        let (o, l): (Fl, Fl) = (zero(), one());
+        PdfShader {
+            width: width,
+            height: height,
+            compress: true,
+            composite: SourceOver,
+            clip: Clip::new(),
+            pages: vec!(Page { size: [width, height], content: ~"" }),
+            states: Vec::new(),
+            shadings: Vec::new(),
+            color: [o, o, o, l],
+            shading: None,
+            fill_rule: NonZero,
+            stroke: None,
+            view: View::new(),
+        }
+    }
+
+    /// Starts a new page with the current width and height.
+    ///
+    /// Following shapes are drawn on the new page.
+    pub fn new_page(&mut self) {
+        self.pages.push(Page { size: [self.width, self.height], content: ~"" });
+    }
+
+    /// Returns the number of pages.
+    #[inline(always)]
+    pub fn page_count(&self) -> uint {
+        self.pages.len()
+    }
+
+    /// Sets how following shapes are painted.
+    pub fn set_effect<P: PdfPen<Fl>, B: PdfBrush<Fl>, G: PdfGradient<Fl>>(
+        &mut self,
+        effect: &Effect<P, B, G>
+    ) {
+        match *effect {
+            Stroke(pen) => {
+                self.color = pen.pdf_color();
+                self.shading = None;
+                self.stroke = Some(pen.pdf_stroke_state());
+            }
+            Fill(brush) => {
+                self.color = brush.pdf_color();
+                self.shading = None;
+                self.fill_rule = brush.pdf_fill_rule();
+                self.stroke = None;
+            }
+            Gradient(gradient) => {
+                self.color = gradient.pdf_color();
+                self.shading = match gradient.pdf_shading() {
+                    None => None,
+                    Some((shading, m)) => {
+                        let found = self.shadings.iter().position(|s| *s == shading);
+                        let i = match found {
+                            Some(i) => i,
+                            None => {
+                                self.shadings.push(shading);
+                                self.shadings.len() - 1
+                            }
+                        };
+                        Some((i, m))
+                    }
+                };
+                self.fill_rule = NonZero;
+                self.stroke = None;
+            }
+        }
+    }
+
+    /// Strokes the outline of a shape with an advanced pen.
+    ///
+    /// The stroke is written as a filled path,
+    /// such that caps and joins look the same as in other backends.
+    pub fn stroke(&mut self, shape: &Shape<Fl>, pen: &AdvancedPen<Fl>) {
+        let precision: Fl = Scalar::from_f64(0.01);
+        let polygons = stroke_shape(shape, pen, precision * pen.width);
+        let mut path = ~"";
+        for polygon in polygons.iter() {
+            path = path + contour_path(polygon.as_slice());
+        }
+        self.paint(path, format!("{} rg\n", rgb(&pen.color)), ~"f\n", pen.color[3]);
+    }
+
+    /// Writes the document.
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        let mut doc = Document { out: Vec::new(), offsets: Vec::new() };
+        doc.out.push_all(bytes!("%PDF-1.4\n"));
+        // A comment with high bytes marks the file as binary.
+        doc.out.push_all(&[0x25u8, 0xe2, 0xe3, 0xcf, 0xd3, 0x0a]);
+
+        let n = self.pages.len();
+        let kids: Vec<~str> = range(0u, n).map(|i| format!("{} 0 R", 4 + 2 * i)).collect();
+        doc.object(bytes!("<< /Type /Catalog /Pages 2 0 R >>"));
+        doc.object(format!("<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.as_slice().connect(" "), n).as_bytes());
+
+        let mut states = ~"";
+        for (i, &(opacity, op)) in self.states.iter().enumerate() {
+            states = states + format!("/GS{} << /ca {} /CA {} /BM {} >> ",
+                i + 1, num(opacity.as_f64()), num(opacity.as_f64()), blend_mode(op));
+        }
+        let mut shadings = ~"";
+        for (i, shading) in self.shadings.iter().enumerate() {
+            shadings = shadings + format!("/Sh{} {} ", i + 1, *shading);
+        }
+        doc.object(format!("<< /ExtGState << {}>> /Shading << {}>> >>",
+            states, shadings).as_bytes());
+
+        for (i, page) in self.pages.iter().enumerate() {
+            doc.object(format!("<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                /Resources 3 0 R /Contents {} 0 R >>",
+                num(page.size[0].as_f64()), num(page.size[1].as_f64()), 5 + 2 * i).as_bytes());
+            // Flips the page such that y points down.
+            let content = format!("1 0 0 -1 0 {} cm\n{}", num(page.size[1].as_f64()),
+                page.content);
+            let (data, filter) = if self.compress {
+                (zlib_compress(content.as_bytes()), " /Filter /FlateDecode")
+            } else {
+                (Vec::from_slice(content.as_bytes()), "")
+            };
+            let mut stream = Vec::from_slice(
+                format!("<< /Length {}{} >>\nstream\n", data.len(), filter).as_bytes());
+            stream.push_all(data.as_slice());
+            stream.push_all(bytes!("\nendstream"));
+            doc.object(stream.as_slice());
+        }
+
+        let xref = doc.out.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", doc.offsets.len() + 1);
+        for &offset in doc.offsets.iter() {
+            table = table + format!("{:010} 00000 n \n", offset);
+        }
+        table = table + format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            doc.offsets.len() + 1, xref);
+        doc.out.push_all(table.as_bytes());
+        w.write(doc.out.as_slice())
+    }
+
+    /// Returns the operators setting the fill paint and filling a path.
+    fn fill_paint(&self) -> (~str, ~str, Fl) {
+        let (fill, clip) = match self.fill_rule {
+            NonZero => ("f", "W"),
+            EvenOdd => ("f*", "W*"),
+        };
+        match self.shading {
+            Some((i, ref m)) => (~"", format!("{} n\n{} cm\n/Sh{} sh\n", clip, matrix(m), i + 1),
+                one()),
+            None => (format!("{} rg\n", rgb(&self.color)), format!("{}\n", fill), self.color[3]),
+        }
+    }
+
+    /// Returns the operators setting the stroke paint and stroking a path.
+    fn stroke_paint(&self, state: &str) -> (~str, ~str, Fl) {
+        (format!("{} RG\n{}\n", rgb(&self.color), state), ~"S\n", self.color[3])
+    }
+
+    /// Paints a path inside its own graphics state,
+    /// with the clip, opacity, blend mode and view.
+    ///
+    /// The paint operators before the path set colors,
+    /// and the operators after the path paint it.
+    fn paint(&mut self, path: ~str, before: ~str, after: ~str, opacity: Fl) {
+        if path.len() == 0 { return; }
+        let mut ops = ~"q\n";
+        for region in self.clip.regions.iter() {
+            for contour in region.iter() {
+                ops = ops + contour_path(contour.as_slice());
+            }
+            ops = ops + "W n\n";
+        }
+        match self.state(opacity) {
+            Some(i) => ops = ops + format!("/GS{} gs\n", i + 1),
+            None => {}
+        }
+        let id: Matrix<Fl> = identity();
+        for m in [self.view.base, self.view.trans].iter() {
+            if *m == id { continue; }
+            ops = ops + format!("{} cm\n", matrix(m));
+        }
+        ops = ops + before + path + after + "Q\n";
+        let page = self.pages.mut_last().unwrap();
+        page.content = page.content + ops;
+    }
+
+    /// Returns the index of the graphics state with an opacity
+    /// and the current blend mode, or `None` if none is needed.
+    fn state(&mut self, opacity: Fl) -> Option<uint> {
+        let l: Fl = one();
+        let opacity = opacity.max(zero()).min(l);
+        if opacity == l && blend_mode(self.composite) == "/Normal" { return None; }
+        let key = (opacity, self.composite);
+        let found = self.states.iter().position(|s| *s == key);
+        match found {
+            Some(i) => Some(i),
+            None => {
+                self.states.push(key);
+                Some(self.states.len() - 1)
+            }
+        }
+    }
+}
+
+impl<Fl: Scalar> Shader<Fl> for PdfShader<Fl> {
+    fn shade(&mut self, shape: &Shape<Fl>) {
+        let (before, after, opacity) = match *shape {
+            Pixel(_) | PixelHorizontalLine(_) | PixelVerticalLine(_) => self.fill_paint(),
+            // A line width of zero is the thinnest line the device can draw.
+            PixelLine(_) => self.stroke_paint("0 w"),
+            Line(_) if self.stroke.is_none() => self.stroke_paint("1 w"),
+            _ => match self.stroke {
+                Some(ref state) => self.stroke_paint(state.as_slice()),
+                None => self.fill_paint(),
+            },
+        };
+        self.paint(path(shape), before, after, opacity);
+    }
+
+    fn get_view<'a>(&'a self) -> &'a View<Fl> {
+        &self.view
+    }
+
+    fn get_mut_view<'a>(&'a mut self) -> &'a mut View<Fl> {
+        &mut self.view
+    }
+}
+
+impl<Fl: Scalar> ClipShader<Fl> for PdfShader<Fl> {
+    fn get_mut_clip<'a>(&'a mut self) -> &'a mut Clip<Fl> {
+        &mut self.clip
+    }
+}
+
+impl<Fl: Scalar> Backend<Fl> for PdfShader<Fl> {
+    fn draw(
+        &mut self,
+        transform: &Matrix2d<Fl>,
+        color: &Color<Fl>,
+        op: &CompositeOp,
+        clip: &Clip<Fl>,
+        shape: &Shape<Fl>
+    ) {
+        let (base, trans, old_op) = (self.view.base, self.view.trans, self.composite);
+        let old_color = mem::replace(&mut self.color, *color);
+        let old_shading = self.shading.take();
+        let old_stroke = self.stroke.take();
+        let old_fill_rule = mem::replace(&mut self.fill_rule, NonZero);
+        self.view.base = identity();
+        self.view.trans = *transform;
+        self.composite = *op;
+        let old_clip = mem::replace(&mut self.clip, clip.clone());
+        self.shade(shape);
+        self.clip = old_clip;
+        self.view.base = base;
+        self.view.trans = trans;
+        self.composite = old_op;
+        self.color = old_color;
+        self.shading = old_shading;
+        self.stroke = old_stroke;
+        self.fill_rule = old_fill_rule;
+    }
+}
+
+impl<Fl: Scalar, P: PdfPen<Fl>, B: PdfBrush<Fl>, G: PdfGradient<Fl>>
+EffectShader<P, B, G, Fl> for PdfShader<Fl> {
+    fn shade_effect(&mut self, shape: &Shape<Fl>, effect: &Effect<P, B, G>) {
+        match *effect {
+            Stroke(pen) => match pen.pdf_outline() {
+                Some(pen) => { self.stroke(shape, pen); return; }
+                None => {}
+            },
+            _ => {}
+        }
+        self.set_effect(effect);
+        self.shade(shape);
+    }
+}
+
+/// Collects numbered objects and their offsets in the file.
+struct Document {
+    out: Vec<u8>,
+    offsets: Vec<uint>,
+}
+
+impl Document {
+    /// Writes the next object.
+    fn object(&mut self, body: &[u8]) {
+        self.offsets.push(self.out.len());
+        self.out.push_all(format!("{} 0 obj\n", self.offsets.len()).as_bytes());
+        self.out.push_all(body);
+        self.out.push_all(bytes!("\nendobj\n"));
+    }
+}
+
+/// Formats a number with at most four decimals,
+/// since PDF does not allow exponents.
+fn num(v: f64) -> ~str {
+    let mut s = format!("{:.4f}", v);
+    while s.ends_with("0") { s.pop_char(); }
+    if s.ends_with(".") { s.pop_char(); }
+    if s.as_slice() == "-0" { ~"0" } else { s }
+}
+
+/// Formats numbers separated by spaces.
+fn nums<Fl: Scalar>(values: &[Fl]) -> ~str {
+    let res: Vec<~str> = values.iter().map(|&v| num(v.as_f64())).collect();
+    res.as_slice().connect(" ")
+}
+
+/// Formats a point.
+#[inline(always)]
+fn point<Fl: Scalar>(p: [Fl, ..2]) -> ~str {
+    format!("{} {}", num(p[0].as_f64()), num(p[1].as_f64()))
+}
+
+/// Formats a matrix as the operands of `cm`.
+fn matrix<Fl: Scalar>(m: &Matrix<Fl>) -> ~str {
+    nums(&[m[0], m[3], m[1], m[4], m[2], m[5]])
+}
+
+/// Formats the red, green and blue of a color, ignoring alpha.
+fn rgb<Fl: Scalar>(color: &[Fl, ..4]) -> ~str {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    nums(&[color[0].max(o).min(l), color[1].max(o).min(l), color[2].max(o).min(l)])
+}
+
+/// Returns the name of the blend mode of a composite operator.
+fn blend_mode(op: CompositeOp) -> &'static str {
+    match op {
+        Multiply => "/Multiply",
+        Screen => "/Screen",
+        Overlay => "/Overlay",
+        Darken => "/Darken",
+        Lighten => "/Lighten",
+        Difference => "/Difference",
+        _ => "/Normal",
+    }
+}
+
+/// Returns the dash operator with a leading space,
+/// or nothing for a solid line.
+fn dash_ops<Fl: Scalar>(dash: &Option<Dash<Fl>>) -> ~str {
+    match *dash {
+        Some(ref dash) if !dash.is_solid() =>
+            format!(" [{}] {} d", nums(dash.array.as_slice()), num(dash.offset.as_f64())),
+        _ => ~"",
+    }
+}
+
+/// Returns a function interpolating the colors of gradient stops,
+/// padded with the first and last color.
+fn stops_function<Fl: Scalar>(stops: &[(Fl, [Fl, ..4])]) -> ~str {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    let mut s = Vec::from_slice(stops);
+    let (first, first_color) = stops[0];
+    let (last, last_color) = stops[stops.len() - 1];
+    if first > o { s.unshift((o, first_color)); }
+    if last < l { s.push((l, last_color)); }
+    let s = s.as_slice();
+    let functions: Vec<~str> = range(1u, s.len()).map(|i| {
+        let ((_, a), (_, b)) = (s[i - 1], s[i]);
+        format!("<< /FunctionType 2 /Domain [0 1] /C0 [{}] /C1 [{}] /N 1 >>", rgb(&a), rgb(&b))
+    }).collect();
+    if functions.len() == 1 { return functions.get(0).clone(); }
+    let bounds: Vec<Fl> = range(1u, s.len() - 1).map(|i| { let (t, _) = s[i]; t }).collect();
+    let encode: Vec<&str> = range(0u, functions.len()).map(|_| "0 1").collect();
+    format!("<< /FunctionType 3 /Domain [0 1] /Functions [{}] /Bounds [{}] /Encode [{}] >>",
+        functions.as_slice().connect(" "), nums(bounds.as_slice()),
+        encode.as_slice().connect(" "))
+}
+
+/// Returns the path operators of a shape.
+fn path<Fl: Scalar>(shape: &Shape<Fl>) -> ~str {
+    let half: Fl = Scalar::from_f64(0.5);
+    match *shape {
+        Pixel(p) => format!("{} 1 1 re\n", point(*p)),
+        PixelHorizontalLine(l) =>
+            format!("{} {} 1 re\n", point([l[0], l[1]]), num(l[2].as_f64())),
+        PixelVerticalLine(l) =>
+            format!("{} 1 {} re\n", point([l[0], l[1]]), num(l[2].as_f64())),
+        PixelLine(l) | Line(l) =>
+            format!("{} m\n{} l\n", point([l[0], l[1]]), point([l[2], l[3]])),
+        Rect(r) => format!("{} re\n", nums(r)),
+        RoundRect(r) => round_rect_path(r),
+        Ellipse(e) => ellipse_path([e[0] + half * e[2], e[1] + half * e[3]],
+                                   [half * e[2], half * e[3]]),
+        Circle(c) => ellipse_path([c[0], c[1]], [c[2], c[2]]),
+        Triangle(t) => polygon_path(t),
+        Polygon(p) => polygon_path(p),
+        Catmull(_) | QuadraticBezierBound(_) | QuadraticBezierFree(_) =>
+            curve_path(&Curve::from_shape(shape).unwrap()),
+    }
+}
+
+/// Returns the path operators of a closed contour.
+fn contour_path<Fl: Scalar>(contour: &[[Fl, ..2]]) -> ~str {
+    let mut res = ~"";
+    for (i, &p) in contour.iter().enumerate() {
+        res = res + format!("{} {}\n", point(p), if i == 0 { "m" } else { "l" });
+    }
+    if res.len() == 0 { res } else { res + "h\n" }
+}
+
+/// Returns the path operators of a closed polygon [x1, y1, ...].
+fn polygon_path<Fl: Scalar>(p: &[Fl]) -> ~str {
+    let contour: Vec<[Fl, ..2]> = range(0u, p.len() / 2)
+        .map(|i| [p[i * 2], p[i * 2 + 1]]).collect();
+    contour_path(contour.as_slice())
+}
+
+/// Returns the path operators of an ellipse made of four cubic curves.
+fn ellipse_path<Fl: Scalar>(c: [Fl, ..2], r: [Fl, ..2]) -> ~str {
+    let kappa: Fl = Scalar::from_f64(KAPPA);
+    let (kx, ky) = (kappa * r[0], kappa * r[1]);
+    let p = [[c[0] + r[0], c[1]],
+             [c[0] + r[0], c[1] + ky], [c[0] + kx, c[1] + r[1]], [c[0], c[1] + r[1]],
+             [c[0] - kx, c[1] + r[1]], [c[0] - r[0], c[1] + ky], [c[0] - r[0], c[1]],
+             [c[0] - r[0], c[1] - ky], [c[0] - kx, c[1] - r[1]], [c[0], c[1] - r[1]],
+             [c[0] + kx, c[1] - r[1]], [c[0] + r[0], c[1] - ky], [c[0] + r[0], c[1]]];
+    let mut res = format!("{} m\n", point(p[0]));
+    for i in range(0u, 4) {
+        res = res + format!("{} {} {} c\n",
+            point(p[i * 3 + 1]), point(p[i * 3 + 2]), point(p[i * 3 + 3]));
+    }
+    res + "h\n"
+}
+
+/// Returns the path operators of a rectangle [x, y, w, h, radius]
+/// with corners made of cubic curves.
+fn round_rect_path<Fl: Scalar>(r: &[Fl, ..5]) -> ~str {
+    let (o, l, half): (Fl, Fl, Fl) = (zero(), one(), Scalar::from_f64(0.5));
+    let kappa: Fl = Scalar::from_f64(KAPPA);
+    let radius = r[4].min(half * r[2].abs()).min(half * r[3].abs()).max(o);
+    if radius == o { return format!("{} re\n", nums(r.slice_to(4))); }
+    let (x1, y1, x2, y2) = (r[0], r[1], r[0] + r[2], r[1] + r[3]);
+    let (a, k) = (radius, (l - kappa) * radius);
+    let corners = [
+        ([x2 - a, y1], [x2 - k, y1], [x2, y1 + k], [x2, y1 + a]),
+        ([x2, y2 - a], [x2, y2 - k], [x2 - k, y2], [x2 - a, y2]),
+        ([x1 + a, y2], [x1 + k, y2], [x1, y2 - k], [x1, y2 - a]),
+        ([x1, y1 + a], [x1, y1 + k], [x1 + k, y1], [x1 + a, y1]),
+    ];
+    let mut res = format!("{} m\n", point([x1 + a, y1]));
+    for &(start, c1, c2, end) in corners.iter() {
+        res = res + format!("{} l\n{} {} {} c\n", point(start), point(c1), point(c2), point(end));
+    }
+    res + "h\n"
+}
+
+/// Returns the path operators of a curve,
+/// with quadratic segments written as cubic curves.
+fn curve_path<Fl: Scalar>(curve: &Curve<Fl>) -> ~str {
+    let two_thirds: Fl = Scalar::from_f64(2.0 / 3.0);
+    let mut res = ~"";
+    for (i, segment) in curve.segments.iter().enumerate() {
+        if i == 0 { res = res + format!("{} m\n", point(segment.start())); }
+        res = res + match *segment {
+            LineSegment(p) => format!("{} l\n", point(p[1])),
+            QuadraticSegment(p) => {
+                let c1 = [p[0][0] + two_thirds * (p[1][0] - p[0][0]),
+                          p[0][1] + two_thirds * (p[1][1] - p[0][1])];
+                let c2 = [p[2][0] + two_thirds * (p[1][0] - p[2][0]),
+                          p[2][1] + two_thirds * (p[1][1] - p[2][1])];
+                format!("{} {} {} c\n", point(c1), point(c2), point(p[2]))
+            }
+            CubicSegment(p) => format!("{} {} {} c\n", point(p[1]), point(p[2]), point(p[3])),
+        };
+    }
+    if curve.closed && res.len() > 0 { res + "h\n" } else { res }
+}
+
+#[test]
+fn test_pdf() {
+    use std::io::MemWriter;
+    use std::str;
+    use graphics::advanced_pen::{ArrowLineCap, DiamonLineJoin};
+    use graphics::deflate::zlib_decompress;
+
+    let mut pdf: PdfShader = PdfShader::new(100.0, 50.0);
+    pdf.compress = false;
+    let brush: SolidBrush = SolidBrush::new([1.0, 0.0, 0.0, 0.5]);
+    let fill: Effect<AdvancedPen, SolidBrush, ColorGradient> = Fill(&brush);
+    pdf.shade_effect(&Rect(&[10.0, 5.0, 20.0, 10.0]), &fill);
+    let pen: AdvancedPen = AdvancedPen {
+        width: 2.0,
+        color: [0.0, 0.0, 1.0, 1.0],
+        line_cap_start: SquareLineCap,
+        line_cap_end: ArrowLineCap,
+        line_join: DiamonLineJoin(1.0),
+        dash: Some(Dash::new(vec!(4.0, 1.5))),
+    };
+    let stroke: Effect<AdvancedPen, SolidBrush, ColorGradient> = Stroke(&pen);
+    pdf.get_mut_view().trans(5.0, 0.0);
+    pdf.shade_effect(&Line(&[0.0, 0.0, 10.0, 10.0]), &stroke);
+    let square = AdvancedPen { line_cap_end: SquareLineCap, line_join: SquareLineJoin,
+        dash: pen.dash.clone(), ..pen };
+    let stroke: Effect<AdvancedPen, SolidBrush, ColorGradient> = Stroke(&square);
+    pdf.shade_effect(&Line(&[0.0, 0.0, 10.0, 10.0]), &stroke);
+    pdf.get_mut_view().reset();
+    let mut gradient: ColorGradient = ColorGradient::linear(0.0, 0.0, 10.0, 0.0);
+    gradient.add_stop(0.25, [1.0, 1.0, 0.0, 1.0]);
+    gradient.add_stop(0.75, [0.0, 1.0, 1.0, 1.0]);
+    let gradient: Effect<AdvancedPen, SolidBrush, ColorGradient> = Gradient(&gradient);
+    pdf.shade_effect(&Circle(&[50.0, 25.0, 10.0]), &gradient);
+
+    pdf.new_page();
+    pdf.set_effect(&fill);
+    let shapes = [Pixel(&[1.0, 2.0]), PixelHorizontalLine(&[1.0, 2.0, 3.0]),
+        PixelVerticalLine(&[1.0, 2.0, 3.0]), PixelLine(&[1.0, 2.0, 3.0, 4.0]),
+        Line(&[1.0, 2.0, 3.0, 4.0]), Rect(&[1.0, 2.0, 3.0, 4.0]),
+        RoundRect(&[1.0, 2.0, 3.0, 4.0, 0.5]), Ellipse(&[1.0, 2.0, 3.0, 4.0]),
+        Circle(&[1.0, 2.0, 3.0]), Triangle(&[1.0, 2.0, 3.0, 4.0, 5.0, 1.0]),
+        Polygon(&[1.0, 2.0, 3.0, 4.0, 5.0, 1.0]), Catmull(&[1.0, 2.0, 3.0, 4.0, 5.0, 1.0]),
+        QuadraticBezierBound(&[1.0, 2.0, 3.0, 4.0, 5.0, 1.0]),
+        QuadraticBezierFree(&[1.0, 2.0, 3.0, 4.0, 5.0, 1.0, 6.0, 6.0])];
+    for shape in shapes.iter() {
+        pdf.shade(shape);
+    }
+    assert_eq!(pdf.page_count(), 2);
+
+    let mut w = MemWriter::new();
+    pdf.write_to(&mut w).unwrap();
+    assert!(w.get_ref().starts_with(bytes!("%PDF-1.4\n")));
+    let text = str::from_utf8(w.get_ref().slice_from(15)).unwrap();
+    assert!(text.contains("/Count 2"));
+    assert!(text.contains("/GS1 << /ca 0.5 /CA 0.5 /BM /Normal >>"));
+    assert!(text.contains("/GS1 gs\n1 0 0 rg\n10 5 20 10 re\nf\n"));
+    // The arrow is filled, square caps are written as parameters.
+    assert!(text.contains("1 0 0 1 5 0 cm\n0 0 1 rg\n"));
+    assert!(text.contains("1 0 0 1 5 0 cm\n0 0 1 RG\n2 w 2 J 0 j [4 1.5] 0 d\n0 0 m\n10 10 l\nS\n"));
+    assert!(text.contains("/Bounds [0.25 0.75]"));
+    assert!(text.contains("W n\n1 0 0 1 0 0 cm\n/Sh1 sh\n"));
+    assert_eq!(text.split_str("Q\n").count() - 1, 4 + shapes.len());
+
+    // The cross-reference table points at the objects.
+    let start = text.find_str("startxref\n").unwrap() + 10;
+    let xref: uint = from_str(text.slice_from(start).lines().next().unwrap()).unwrap();
+    assert!(w.get_ref().slice_from(xref).starts_with(bytes!("xref\n0 8\n")));
+    let offset: uint = from_str(text.slice_from(text.find_str("n \n").unwrap() - 17)
+        .slice_to(10)).unwrap();
+    assert!(w.get_ref().slice_from(offset).starts_with(bytes!("1 0 obj\n")));
+
+    pdf.compress = true;
+    let mut w = MemWriter::new();
+    pdf.write_to(&mut w).unwrap();
+    let data = w.get_ref();
+    let start = data.windows(7).position(|s| s == bytes!("stream\n")).unwrap() + 7;
+    let end = start + data.slice_from(start).windows(10)
+        .position(|s| s == bytes!("\nendstream")).unwrap();
+    let content = zlib_decompress(data.slice(start, end)).unwrap();
+    assert!(content.as_slice().starts_with(bytes!("1 0 0 -1 0 50 cm\nq\n")));
+}
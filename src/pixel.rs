@@ -0,0 +1,214 @@
+
+//! Rasterization of the one pixel wide lines.
+//!
+//! Lines are given in pixel coordinates, where pixel (x, y)
+//! covers the square from (x, y) to (x + 1, y + 1).
+//! End points may lie anywhere inside a pixel.
+//! The result is a list of pixels with coverage between 0 and 1,
+//! containing only pixels whose centers are inside the bounds.
+//! Only the end points and the mode decide which pixels are covered,
+//! so drawing the same line twice gives the same pixels.
+
+use std::mem;
+use std::num::{zero, one};
+
+use graphics::scalar::Scalar;
+
+/// Decides how a one pixel wide line covers pixels.
+#[deriving(Clone, Eq, Show)]
+pub enum PixelMode {
+    /// Covers one pixel fully per step along the major axis.
+    ///
+    /// The pixel is the one containing the line at the pixel center,
+    /// which is the same as Bresenham's algorithm for end points at pixel centers.
+    /// Both end points are included.
+    Bresenham,
+    /// Spreads the coverage over two pixels per step along the major axis.
+    ///
+    /// Uses Xiaolin Wu's algorithm, with partial coverage of the end pixels.
+    /// A line through pixel centers covers the pixels fully and stays crisp.
+    /// A line of zero length covers nothing.
+    XiaolinWu,
+}
+
+/// Returns the covered pixels of a line as (x, y, coverage).
+///
+/// The bounds are [x1, y1, x2, y2] in pixel coordinates.
+pub fn rasterize_line<Fl: Scalar>(
+    mode: PixelMode,
+    a: [Fl, ..2],
+    b: [Fl, ..2],
+    bounds: [Fl, ..4]
+) -> Vec<(int, int, Fl)> {
+    let mut res = Vec::new();
+    match mode {
+        Bresenham => bresenham(&mut res, a, b, bounds),
+        XiaolinWu => xiaolin_wu(&mut res, a, b, bounds),
+    }
+    res
+}
+
+/// Clips a line to a rectangle [x1, y1, x2, y2].
+///
+/// Returns `None` if the line is completely outside.
+/// End points inside the rectangle are kept exactly.
+pub fn clip_line<Fl: Scalar>(
+    a: [Fl, ..2],
+    b: [Fl, ..2],
+    bounds: [Fl, ..4]
+) -> Option<([Fl, ..2], [Fl, ..2])> {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let (mut t0, mut t1) = (o, l);
+    let edges = [(-dx, a[0] - bounds[0]), (dx, bounds[2] - a[0]),
+                 (-dy, a[1] - bounds[1]), (dy, bounds[3] - a[1])];
+    for &(p, q) in edges.iter() {
+        if p == o {
+            if q < o { return None; }
+            continue;
+        }
+        let r = q / p;
+        if p < o {
+            if r > t1 { return None; }
+            if r > t0 { t0 = r; }
+        } else {
+            if r < t0 { return None; }
+            if r < t1 { t1 = r; }
+        }
+    }
+    let start = if t0 == o { a } else { [a[0] + t0 * dx, a[1] + t0 * dy] };
+    let end = if t1 == l { b } else { [a[0] + t1 * dx, a[1] + t1 * dy] };
+    Some((start, end))
+}
+
+/// Covers one pixel per column or row.
+fn bresenham<Fl: Scalar>(
+    res: &mut Vec<(int, int, Fl)>,
+    a: [Fl, ..2],
+    b: [Fl, ..2],
+    bounds: [Fl, ..4]
+) {
+    let (o, l, half): (Fl, Fl, Fl) = (zero(), one(), Scalar::from_f64(0.5));
+    let (a, b) = match clip_line(a, b, bounds) {
+        None => return,
+        Some(line) => line,
+    };
+    let steep = (b[1] - a[1]).abs() > (b[0] - a[0]).abs();
+    let (mut a, mut b) = (flip(a, steep), flip(b, steep));
+    if a[0] > b[0] { mem::swap(&mut a, &mut b); }
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    for u in range(a[0].floor().as_f64() as int, b[0].floor().as_f64() as int + 1) {
+        let x: Fl = Scalar::from_f64(u as f64);
+        let center = (x + half).max(a[0]).min(b[0]);
+        let v = if dx == o { a[1] } else { a[1] + (center - a[0]) * dy / dx };
+        push(res, bounds, steep, u, v.floor().as_f64() as int, l);
+    }
+}
+
+/// Covers two pixels per column or row with Xiaolin Wu's algorithm.
+fn xiaolin_wu<Fl: Scalar>(
+    res: &mut Vec<(int, int, Fl)>,
+    a: [Fl, ..2],
+    b: [Fl, ..2],
+    bounds: [Fl, ..4]
+) {
+    let (o, l, half): (Fl, Fl, Fl) = (zero(), one(), Scalar::from_f64(0.5));
+    // Keep one pixel outside the bounds, so clipped ends get full coverage.
+    let grown = [bounds[0] - l, bounds[1] - l, bounds[2] + l, bounds[3] + l];
+    let (a, b) = match clip_line(a, b, grown) {
+        None => return,
+        Some(line) => line,
+    };
+    let steep = (b[1] - a[1]).abs() > (b[0] - a[0]).abs();
+    // Move pixel centers to integer coordinates.
+    let (mut a, mut b) = (flip([a[0] - half, a[1] - half], steep),
+                          flip([b[0] - half, b[1] - half], steep));
+    if a[0] > b[0] { mem::swap(&mut a, &mut b); }
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let gradient = if dx == o { o } else { dy / dx };
+    let first = (a[0] + half).floor();
+    let last = (b[0] + half).floor();
+    if first == last {
+        let middle = half * (a[0] + b[0]);
+        let v = a[1] + gradient * (middle - a[0]);
+        wu_pair(res, bounds, steep, first.as_f64() as int, v, dx);
+        return;
+    }
+
+    let start_gap = l - fract(a[0] + half);
+    let v = a[1] + gradient * (first - a[0]);
+    wu_pair(res, bounds, steep, first.as_f64() as int, v, start_gap);
+    for u in range(first.as_f64() as int + 1, last.as_f64() as int) {
+        let x: Fl = Scalar::from_f64(u as f64);
+        let v = a[1] + gradient * (x - a[0]);
+        wu_pair(res, bounds, steep, u, v, l);
+    }
+    let end_gap = fract(b[0] + half);
+    let v = b[1] + gradient * (last - b[0]);
+    wu_pair(res, bounds, steep, last.as_f64() as int, v, end_gap);
+}
+
+/// Splits coverage between the two pixels nearest to a minor coordinate.
+fn wu_pair<Fl: Scalar>(
+    res: &mut Vec<(int, int, Fl)>,
+    bounds: [Fl, ..4],
+    steep: bool,
+    u: int,
+    v: Fl,
+    coverage: Fl
+) {
+    let l: Fl = one();
+    let base = v.floor();
+    let f = v - base;
+    push(res, bounds, steep, u, base.as_f64() as int, coverage * (l - f));
+    push(res, bounds, steep, u, base.as_f64() as int + 1, coverage * f);
+}
+
+/// Adds a pixel given in major and minor coordinates.
+///
+/// Skips pixels without coverage or with centers outside the bounds.
+fn push<Fl: Scalar>(
+    res: &mut Vec<(int, int, Fl)>,
+    bounds: [Fl, ..4],
+    steep: bool,
+    u: int,
+    v: int,
+    coverage: Fl
+) {
+    if coverage <= zero() { return; }
+    let (x, y) = if steep { (v, u) } else { (u, v) };
+    let (cx, cy): (Fl, Fl) = (Scalar::from_f64(x as f64 + 0.5), Scalar::from_f64(y as f64 + 0.5));
+    if cx < bounds[0] || cy < bounds[1] || cx >= bounds[2] || cy >= bounds[3] { return; }
+    res.push((x, y, coverage.min(one())));
+}
+
+/// Swaps the coordinates of a point when the line is steep.
+#[inline(always)]
+fn flip<Fl: Scalar>(p: [Fl, ..2], steep: bool) -> [Fl, ..2] {
+    if steep { [p[1], p[0]] } else { p }
+}
+
+/// Returns the fractional part of a number.
+#[inline(always)]
+fn fract<Fl: Scalar>(v: Fl) -> Fl {
+    v - v.floor()
+}
+
+#[test]
+fn test_pixel() {
+    let bounds: [f64, ..4] = [0.0, 0.0, 4.0, 4.0];
+    let line = rasterize_line(Bresenham, [0.5, 0.5], [3.5, 2.5], bounds);
+    assert_eq!(line, vec!((0, 0, 1.0), (1, 1, 1.0), (2, 1, 1.0), (3, 2, 1.0)));
+
+    // Clipped to the bounds.
+    let line = rasterize_line(Bresenham, [-10.0, 1.5], [10.0, 1.5], bounds);
+    assert_eq!(line, vec!((0, 1, 1.0), (1, 1, 1.0), (2, 1, 1.0), (3, 1, 1.0)));
+
+    // Crisp through pixel centers, half coverage at the end.
+    let line = rasterize_line(XiaolinWu, [0.0, 1.5], [2.5, 1.5], bounds);
+    assert_eq!(line, vec!((0, 1, 1.0), (1, 1, 1.0), (2, 1, 0.5)));
+
+    // Between two rows the coverage is split.
+    let line = rasterize_line(XiaolinWu, [1.0, 2.0], [2.0, 2.0], bounds);
+    assert_eq!(line, vec!((1, 1, 0.5), (1, 2, 0.5)));
+}
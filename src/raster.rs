@@ -0,0 +1,500 @@
+
+//! A software rasterizer drawing shapes into an in-memory RGBA buffer.
+//!
+//! Shapes are transformed by the view into pixel coordinates,
+//! converted into closed contours and filled with scanlines.
+//! A pixel is covered when its center is inside the contours,
+//! using the fill rule of the brush.
+//! The one pixel shapes are drawn directly in pixel coordinates,
+//! with the pixel mode deciding whether they are anti-aliased.
+//! Colors are combined with existing pixels using the composite operator,
+//! which only changes pixels covered by the shape.
+//! With gamma correction, colors are combined in linear RGB
+//! and stored as sRGB, which gives evenly weighted anti-aliasing.
+//! Pixels with centers outside the clip are never changed.
+//!
+//! A buffer can also hold a tile of a larger image.
+//! Shapes are then rasterized the same way as for the whole image,
+//! only keeping the pixels inside the tile.
+
+use std::cmp::{min, max};
+use std::mem;
+use std::num::{Float, zero, one};
+
+use graphics::{Shader, Shape, View, Matrix, Effect, Stroke, Fill, Gradient};
+use graphics::{Pixel, PixelHorizontalLine, PixelVerticalLine, PixelLine};
+use graphics::Line;
+use graphics::advanced_pen::AdvancedPen;
+use graphics::stroke::stroke_shape;
+use graphics::curve::{outline, transform};
+use graphics::brush::{Brush, NonZero, EvenOdd};
+use graphics::pixel::{PixelMode, Bresenham, rasterize_line};
+use graphics::composite::{CompositeOp, SourceOver, composite};
+use graphics::clip::Clip;
+use graphics::color::{srgb_to_linear, linear_to_srgb};
+use graphics::gradient::ColorGradient;
+use graphics::scalar::Scalar;
+use graphics::scene::EffectShader;
+use graphics::damage::ClipShader;
+use graphics_new::{Backend, Matrix2d, Color, identity, invert};
+
+/// The maximum distance in pixels between a curve and its polyline.
+static TOLERANCE: f64 = 0.25;
+
+/// An RGBA framebuffer with 8 bits per channel.
+///
+/// Shapes are rasterized with the float type of the buffer.
+pub struct RasterBuffer<Fl=f64> {
+    /// The width in pixels.
+    pub width: uint,
+    /// The height in pixels.
+    pub height: uint,
+    /// Pixels stored row by row as [r, g, b, a] with straight alpha.
+    pub pixels: Vec<u8>,
+    /// The color used when drawing shapes.
+    pub color: [Fl, ..4],
+    /// The width of lines in shape coordinates.
+    pub line_width: Fl,
+    /// A brush used instead of the color when filling shapes.
+    ///
+    /// The one pixel shapes and strokes always use a single color.
+    /// Without a brush, shapes are filled using the nonzero rule.
+    pub brush: Option<~Brush<Fl>>,
+    /// How the one pixel lines cover pixels.
+    pub pixel_mode: PixelMode,
+    /// How colors combine with existing pixels.
+    pub composite: CompositeOp,
+    /// Whether colors are combined in linear RGB instead of sRGB.
+    pub gamma_correct: bool,
+    /// The clip in pixel coordinates.
+    pub clip: Clip<Fl>,
+    origin: [uint, ..2],
+    image_size: [uint, ..2],
+    view: View<Fl>,
+}
+
+impl<Fl: Scalar> RasterBuffer<Fl> {
+    /// Creates a transparent framebuffer.
+    ///
+    /// The view maps shape coordinates directly to pixels.
+    pub fn new(width: uint, height: uint) -> RasterBuffer<Fl> {
+        RasterBuffer::new_tile(width, height, 0, 0, width, height)
+    }
+
+    /// Creates a transparent framebuffer holding a tile of a larger image.
+    ///
+    /// The tile starts at pixel (x, y) of the image.
+    /// The view maps shape coordinates directly to pixels of the image.
+    pub fn new_tile(
+        image_width: uint,
+        image_height: uint,
+        x: uint,
+        y: uint,
+        width: uint,
+        height: uint
+    ) -> RasterBuffer<Fl> {
+        let (o, l): (Fl, Fl) = (zero(), one());
+        RasterBuffer {
+            width: width,
+            height: height,
+            pixels: Vec::from_elem(width * height * 4, 0u8),
+            color: [o, o, o, l],
+            line_width: l,
+            brush: None,
+            pixel_mode: Bresenham,
+            composite: SourceOver,
+            gamma_correct: false,
+            clip: Clip::new(),
+            origin: [x, y],
+            image_size: [image_width, image_height],
+            view: View::new(),
+        }
+    }
+
+    /// Returns the location in the image of the first pixel.
+    #[inline(always)]
+    pub fn origin(&self) -> [uint, ..2] {
+        self.origin
+    }
+
+    /// Fills the whole buffer with a color, ignoring the view.
+    pub fn clear(&mut self, color: [Fl, ..4]) {
+        let rgba = [to_u8(color[0]), to_u8(color[1]),
+                    to_u8(color[2]), to_u8(color[3])];
+        let pixels = self.pixels.as_mut_slice();
+        for i in range(0u, self.width * self.height) {
+            for c in range(0u, 4) {
+                pixels[i * 4 + c] = rgba[c];
+            }
+        }
+    }
+
+    /// Returns the pixel at a location in the buffer.
+    #[inline(always)]
+    pub fn get_pixel(&self, x: uint, y: uint) -> [u8, ..4] {
+        let i = (y * self.width + x) * 4;
+        let p = self.pixels.as_slice();
+        [p[i], p[i + 1], p[i + 2], p[i + 3]]
+    }
+
+    /// Combines a color with a pixel of the image using the composite operator.
+    ///
+    /// Pixels outside the buffer are ignored.
+    #[inline(always)]
+    pub fn blend_pixel(&mut self, x: int, y: int, color: [Fl, ..4]) {
+        self.cover_pixel(x, y, color, one());
+    }
+
+    /// Combines a color with a part of a pixel.
+    ///
+    /// The coverage mixes the result with the old pixel.
+    fn cover_pixel(&mut self, x: int, y: int, color: [Fl, ..4], coverage: Fl) {
+        let (left, top) = (self.origin[0] as int, self.origin[1] as int);
+        if x < left || y < top
+        || x >= left + self.width as int || y >= top + self.height as int { return; }
+        if !self.clip.is_unbounded() && !self.clip.contains([
+            Scalar::from_f64(x as f64 + 0.5), Scalar::from_f64(y as f64 + 0.5)
+        ]) { return; }
+
+        let i = ((y - top) as uint * self.width + (x - left) as uint) * 4;
+        let (op, gamma) = (self.composite, self.gamma_correct);
+        let p = self.pixels.as_mut_slice();
+        let dst: [Fl, ..4] = [
+            Scalar::from_f64(p[i] as f64 / 255.0), Scalar::from_f64(p[i + 1] as f64 / 255.0),
+            Scalar::from_f64(p[i + 2] as f64 / 255.0), Scalar::from_f64(p[i + 3] as f64 / 255.0)
+        ];
+        let (color, dst) = if gamma {
+            (to_linear(color), to_linear(dst))
+        } else {
+            (color, dst)
+        };
+        let src = composite(op, color, dst);
+        let coverage = clamp(coverage);
+        let out_a = dst[3] + (src[3] - dst[3]) * coverage;
+        for c in range(0u, 3) {
+            let (s, d) = (src[c] * src[3], dst[c] * dst[3]);
+            p[i + c] = if out_a > zero() {
+                let v = (d + (s - d) * coverage) / out_a;
+                to_u8(if gamma { Scalar::from_f64(linear_to_srgb(v.as_f64())) } else { v })
+            } else { 0 };
+        }
+        p[i + 3] = to_u8(out_a);
+    }
+
+    /// Strokes the outline of a shape with an advanced pen.
+    pub fn stroke(&mut self, shape: &Shape<Fl>, pen: &AdvancedPen<Fl>) {
+        let m = self.matrix();
+        let tolerance: Fl = Scalar::from_f64(TOLERANCE);
+        let contours = stroke_shape(shape, pen, tolerance / scale(&m));
+        let contours: Vec<Vec<[Fl, ..2]>> = contours.iter().map(|c|
+            c.iter().map(|&p| transform(&m, p)).collect()
+        ).collect();
+        let (color, brush) = (self.color, self.brush.take());
+        self.color = pen.color;
+        self.fill_contours(contours.as_slice());
+        self.color = color;
+        self.brush = brush;
+    }
+
+    /// Returns the matrix from shape coordinates to pixels.
+    #[inline(always)]
+    fn matrix(&self) -> Matrix<Fl> {
+        self.view.matrix()
+    }
+
+    /// Draws a one pixel wide line between two points in pixels.
+    fn pixel_line(&mut self, a: [Fl, ..2], b: [Fl, ..2]) {
+        let color = self.color;
+        let bounds = [zero(), zero(), Scalar::from_f64(self.image_size[0] as f64),
+                      Scalar::from_f64(self.image_size[1] as f64)];
+        for &(x, y, coverage) in rasterize_line(self.pixel_mode, a, b, bounds).iter() {
+            self.cover_pixel(x, y, color, coverage);
+        }
+    }
+
+    /// Fills contours given in pixel coordinates.
+    fn fill_contours(&mut self, contours: &[Vec<[Fl, ..2]>]) {
+        let (o, half): (Fl, Fl) = (zero(), Scalar::from_f64(0.5));
+        let mut min_y: Fl = Float::infinity();
+        let mut max_y: Fl = Float::neg_infinity();
+        for contour in contours.iter() {
+            for p in contour.iter() {
+                min_y = min_y.min(p[1]);
+                max_y = max_y.max(p[1]);
+            }
+        }
+        if min_y > max_y { return; }
+
+        let top = self.origin[1];
+        let mut start = max((min_y - half).ceil().max(o).as_f64() as uint, top);
+        let mut end = min((max_y - half).ceil().max(o).as_f64() as uint, top + self.height);
+        let (mut left, mut right) = (self.origin[0] as int, (self.origin[0] + self.width) as int);
+        // Only pixels with their center inside the clip box can change.
+        match self.clip.bounding_box() {
+            None => {}
+            Some(b) => {
+                start = max(start, (b[1] - half).ceil().max(o).as_f64() as uint);
+                end = min(end, (b[3] - half).ceil().max(o).as_f64() as uint);
+                left = max(left, (b[0] - half).ceil().as_f64() as int);
+                right = min(right, (b[2] - half).ceil().as_f64() as int);
+            }
+        }
+        let inv = invert(&self.matrix());
+        let fill_rule = match self.brush {
+            None => NonZero,
+            Some(ref brush) => brush.fill_rule(),
+        };
+        let mut crossings: Vec<(Fl, int)> = Vec::new();
+        for y in range(start, end) {
+            let yc: Fl = Scalar::from_f64(y as f64 + 0.5);
+            crossings.clear();
+            for contour in contours.iter() {
+                let n = contour.len();
+                for i in range(0u, n) {
+                    let a = contour.as_slice()[i];
+                    let b = contour.as_slice()[(i + 1) % n];
+                    if a[1] == b[1] { continue; }
+                    let (lo, hi) = if a[1] < b[1] { (a[1], b[1]) } else { (b[1], a[1]) };
+                    if yc < lo || yc >= hi { continue; }
+                    let x = a[0] + (yc - a[1]) * (b[0] - a[0]) / (b[1] - a[1]);
+                    crossings.push((x, if b[1] > a[1] { 1 } else { -1 }));
+                }
+            }
+            crossings.as_mut_slice().sort_by(|&(a, _), &(b, _)| cmp_float(a, b));
+
+            let mut winding = 0;
+            for i in range(0u, crossings.len()) {
+                let (x, dir) = crossings.as_slice()[i];
+                winding += dir;
+                let inside = match fill_rule {
+                    NonZero => winding != 0,
+                    EvenOdd => winding % 2 != 0,
+                };
+                if !inside || i + 1 >= crossings.len() { continue; }
+                let (next_x, _) = crossings.as_slice()[i + 1];
+                let from = (x - half).ceil().as_f64() as int;
+                let to = (next_x - half).ceil().as_f64() as int;
+                for px in range(max(from, left), min(to, right)) {
+                    let color = match (&self.brush, inv) {
+                        (&Some(ref brush), Some(ref inv)) => {
+                            let xc: Fl = Scalar::from_f64(px as f64 + 0.5);
+                            brush.sample(transform(inv, [xc, yc]))
+                        }
+                        _ => self.color,
+                    };
+                    self.blend_pixel(px, y as int, color);
+                }
+            }
+        }
+    }
+}
+
+impl<Fl: Scalar> Shader<Fl> for RasterBuffer<Fl> {
+    fn shade(&mut self, shape: &Shape<Fl>) {
+        let m = self.matrix();
+        match *shape {
+            Pixel(p) => {
+                let p = transform(&m, [p[0], p[1]]);
+                let color = self.color;
+                self.blend_pixel(p[0].floor().as_f64() as int, p[1].floor().as_f64() as int,
+                                 color);
+            }
+            PixelHorizontalLine(l) => {
+                let a = transform(&m, [l[0], l[1]]);
+                let b = transform(&m, [l[0] + l[2], l[1]]);
+                self.pixel_line(a, b);
+            }
+            PixelVerticalLine(l) => {
+                let a = transform(&m, [l[0], l[1]]);
+                let b = transform(&m, [l[0], l[1] + l[2]]);
+                self.pixel_line(a, b);
+            }
+            PixelLine(l) => {
+                let a = transform(&m, [l[0], l[1]]);
+                let b = transform(&m, [l[2], l[3]]);
+                self.pixel_line(a, b);
+            }
+            _ => {
+                let contours = shape_contours(shape, self.line_width, &m);
+                let contours: Vec<Vec<[Fl, ..2]>> = contours.iter().map(|c|
+                    c.iter().map(|&p| transform(&m, p)).collect()
+                ).collect();
+                self.fill_contours(contours.as_slice());
+            }
+        }
+    }
+
+    fn get_view<'a>(&'a self) -> &'a View<Fl> {
+        &self.view
+    }
+
+    fn get_mut_view<'a>(&'a mut self) -> &'a mut View<Fl> {
+        &mut self.view
+    }
+}
+
+impl<Fl: Scalar> ClipShader<Fl> for RasterBuffer<Fl> {
+    fn get_mut_clip<'a>(&'a mut self) -> &'a mut Clip<Fl> {
+        &mut self.clip
+    }
+}
+
+impl<Fl: Scalar> Backend<Fl> for RasterBuffer<Fl> {
+    fn draw(
+        &mut self,
+        transform: &Matrix2d<Fl>,
+        color: &Color<Fl>,
+        op: &CompositeOp,
+        clip: &Clip<Fl>,
+        shape: &Shape<Fl>
+    ) {
+        let (base, trans, old_color) = (self.view.base, self.view.trans, self.color);
+        let (brush, old_op) = (self.brush.take(), self.composite);
+        self.view.base = identity();
+        self.view.trans = *transform;
+        self.color = *color;
+        self.composite = *op;
+        let old_clip = mem::replace(&mut self.clip, clip.clone());
+        self.shade(shape);
+        self.clip = old_clip;
+        self.view.base = base;
+        self.view.trans = trans;
+        self.color = old_color;
+        self.brush = brush;
+        self.composite = old_op;
+    }
+}
+
+impl<Fl: Scalar, B: Brush<Fl> + Clone + Send>
+EffectShader<AdvancedPen<Fl>, B, ColorGradient<Fl>, Fl> for RasterBuffer<Fl> {
+    fn shade_effect(
+        &mut self,
+        shape: &Shape<Fl>,
+        effect: &Effect<AdvancedPen<Fl>, B, ColorGradient<Fl>>
+    ) {
+        let brush = match *effect {
+            Stroke(pen) => { self.stroke(shape, pen); return; }
+            Fill(brush) => ~brush.clone() as ~Brush<Fl>,
+            Gradient(gradient) => ~gradient.clone() as ~Brush<Fl>,
+        };
+        let old_brush = mem::replace(&mut self.brush, Some(brush));
+        self.shade(shape);
+        self.brush = old_brush;
+    }
+}
+
+/// Converts a shape into closed contours in shape coordinates.
+///
+/// The matrix to pixels decides how many segments are used for curves.
+fn shape_contours<Fl: Scalar>(
+    shape: &Shape<Fl>,
+    line_width: Fl,
+    m: &Matrix<Fl>
+) -> Vec<Vec<[Fl, ..2]>> {
+    let (half, tolerance): (Fl, Fl) = (Scalar::from_f64(0.5), Scalar::from_f64(TOLERANCE));
+    match *shape {
+        Line(l) => vec!(line_quad([l[0], l[1]], [l[2], l[3]], half * line_width)),
+        _ => outline(shape, m, tolerance).move_iter().map(|(p, _)| p).collect(),
+    }
+}
+
+/// Returns a rectangle around a line segment.
+fn line_quad<Fl: Scalar>(a: [Fl, ..2], b: [Fl, ..2], half_width: Fl) -> Vec<[Fl, ..2]> {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == zero() { return vec!(); }
+    let (nx, ny) = (-dy / len * half_width, dx / len * half_width);
+    vec!([a[0] + nx, a[1] + ny], [b[0] + nx, b[1] + ny],
+         [b[0] - nx, b[1] - ny], [a[0] - nx, a[1] - ny])
+}
+
+/// Returns the average scale factor of a matrix.
+#[inline(always)]
+fn scale<Fl: Scalar>(m: &Matrix<Fl>) -> Fl {
+    (m[0] * m[4] - m[1] * m[3]).abs().sqrt()
+}
+
+/// Compares two numbers for sorting.
+#[inline(always)]
+fn cmp_float<Fl: Scalar>(a: Fl, b: Fl) -> Ordering {
+    if a < b { Less } else if a > b { Greater } else { Equal }
+}
+
+/// Clamps a color channel to the range 0 to 1.
+#[inline(always)]
+fn clamp<Fl: Scalar>(v: Fl) -> Fl {
+    v.max(zero()).min(one())
+}
+
+/// Converts the channels of a color from sRGB to linear RGB.
+///
+/// The transfer function is computed with `f64`.
+#[inline(always)]
+fn to_linear<Fl: Scalar>(c: [Fl, ..4]) -> [Fl, ..4] {
+    [Scalar::from_f64(srgb_to_linear(c[0].as_f64())),
+     Scalar::from_f64(srgb_to_linear(c[1].as_f64())),
+     Scalar::from_f64(srgb_to_linear(c[2].as_f64())), c[3]]
+}
+
+/// Converts a color channel to a byte.
+#[inline(always)]
+fn to_u8<Fl: Scalar>(v: Fl) -> u8 {
+    (clamp(v).as_f64() * 255.0 + 0.5).floor() as u8
+}
+
+#[test]
+fn test_raster_buffer() {
+    use graphics::Rect;
+    use graphics::pixel::XiaolinWu;
+    use graphics::composite::DestinationOut;
+
+    let mut buf: RasterBuffer = RasterBuffer::new(8, 8);
+    buf.color = [1.0, 0.0, 0.0, 1.0];
+    buf.get_mut_view().trans = [1.0, 0.0, 2.0,
+                                0.0, 1.0, 2.0];
+    buf.shade(&Rect(&[0.0, 0.0, 3.0, 2.0]));
+    assert_eq!(buf.get_pixel(2, 2), [255, 0, 0, 255]);
+    assert_eq!(buf.get_pixel(4, 3), [255, 0, 0, 255]);
+    assert_eq!(buf.get_pixel(5, 3), [0, 0, 0, 0]);
+    assert_eq!(buf.get_pixel(2, 4), [0, 0, 0, 0]);
+
+    buf.color = [0.0, 0.0, 1.0, 1.0];
+    buf.shade(&PixelLine(&[0.0, 4.0, 3.0, 4.0]));
+    assert_eq!(buf.get_pixel(5, 6), [0, 0, 255, 255]);
+
+    buf.pixel_mode = XiaolinWu;
+    buf.shade(&PixelHorizontalLine(&[0.0, 4.0, 1.0]));
+    assert_eq!(buf.get_pixel(2, 5), [0, 0, 255, 128]);
+
+    buf.composite = DestinationOut;
+    buf.shade(&Pixel(&[0.0, 0.0]));
+    assert_eq!(buf.get_pixel(2, 2), [0, 0, 0, 0]);
+
+    // Half white over black is half the light, which is brighter than half in sRGB.
+    let mut buf: RasterBuffer = RasterBuffer::new(2, 1);
+    buf.clear([0.0, 0.0, 0.0, 1.0]);
+    buf.blend_pixel(0, 0, [1.0, 1.0, 1.0, 0.5]);
+    buf.gamma_correct = true;
+    buf.blend_pixel(1, 0, [1.0, 1.0, 1.0, 0.5]);
+    assert_eq!(buf.get_pixel(0, 0), [128, 128, 128, 255]);
+    assert_eq!(buf.get_pixel(1, 0), [188, 188, 188, 255]);
+}
+
+#[test]
+fn test_raster_stroke_circle() {
+    use graphics::Circle;
+    use graphics::brush::SolidBrush;
+    use graphics::advanced_pen::{RoundLineCap, RoundLineJoin};
+
+    let pen: AdvancedPen = AdvancedPen { width: 2.0, color: [0.0, 0.0, 1.0, 1.0],
+        line_cap_start: RoundLineCap, line_cap_end: RoundLineCap,
+        line_join: RoundLineJoin, dash: None };
+    let mut buf: RasterBuffer = RasterBuffer::new(24, 24);
+    let effect: Effect<AdvancedPen, SolidBrush, ColorGradient> = Stroke(&pen);
+    buf.shade_effect(&Circle(&[12.0, 12.0, 8.0]), &effect);
+    // The ring is drawn, the inside and outside are not.
+    assert_eq!(buf.get_pixel(19, 12), [0, 0, 255, 255]);
+    assert_eq!(buf.get_pixel(12, 4), [0, 0, 255, 255]);
+    assert_eq!(buf.get_pixel(12, 12), [0, 0, 0, 0]);
+    assert_eq!(buf.get_pixel(1, 1), [0, 0, 0, 0]);
+}
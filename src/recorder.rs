@@ -0,0 +1,359 @@
+
+//! Records shapes into a display list that can be replayed later.
+//!
+//! The display list owns copies of the shapes together with the view
+//! that was active when they were drawn.
+//! It can be written as text, one shape per line:
+//!
+//! ```ignore
+//! rect b0 b1 b2 b3 b4 b5 t0 t1 t2 t3 t4 t5 x y w h
+//! ```
+//!
+//! where `b` is the base matrix and `t` the current transformation.
+//! Numbers that do not read back exactly in decimal are written
+//! as an integer times a power of two, such as `7205759403792794p-56` for 0.1.
+//! Numbers are written as `f64`, which holds every `f32` exactly.
+
+use std::io::{IoResult, Writer};
+use std::num::Float;
+
+use graphics::{Shader, Shape, View, Matrix};
+use graphics::{Pixel, PixelHorizontalLine, PixelVerticalLine, PixelLine};
+use graphics::{Line, Rect, RoundRect, Ellipse, Circle, Triangle, Polygon};
+use graphics::{Catmull, QuadraticBezierBound, QuadraticBezierFree};
+use graphics::scalar::Scalar;
+
+/// An owned copy of a shape.
+#[deriving(Clone, Eq, Show)]
+pub enum RecordedShape<Fl=f64> {
+    /// Owned `Pixel`.
+    RecordedPixel([f64, ..2]),
+    /// Owned `PixelHorizontalLine`.
+    RecordedPixelHorizontalLine([f64, ..3]),
+    /// Owned `PixelVerticalLine`.
+    RecordedPixelVerticalLine([f64, ..3]),
+    /// Owned `PixelLine`.
+    RecordedPixelLine([f64, ..4]),
+    /// Owned `Line`.
+    RecordedLine([f64, ..4]),
+    /// Owned `Rect`.
+    RecordedRect([f64, ..4]),
+    /// Owned `RoundRect`.
+    RecordedRoundRect([f64, ..5]),
+    /// Owned `Ellipse`.
+    RecordedEllipse([f64, ..4]),
+    /// Owned `Circle`.
+    RecordedCircle([f64, ..3]),
+    /// Owned `Triangle`.
+    RecordedTriangle([f64, ..6]),
+    /// Owned `Polygon`.
+    RecordedPolygon(Vec<f64>),
+    /// Owned `Catmull`.
+    RecordedCatmull(Vec<f64>),
+    /// Owned `QuadraticBezierBound`.
+    RecordedQuadraticBezierBound(Vec<f64>),
+    /// Owned `QuadraticBezierFree`.
+    RecordedQuadraticBezierFree(Vec<f64>),
+}
+
+impl<Fl: Scalar> RecordedShape<Fl> {
+    /// Copies a shape.
+    pub fn from_shape(shape: &Shape<Fl>) -> RecordedShape<Fl> {
+        match *shape {
+            Pixel(p) => RecordedPixel(*p),
+            PixelHorizontalLine(p) => RecordedPixelHorizontalLine(*p),
+            PixelVerticalLine(p) => RecordedPixelVerticalLine(*p),
+            PixelLine(p) => RecordedPixelLine(*p),
+            Line(p) => RecordedLine(*p),
+            Rect(p) => RecordedRect(*p),
+            RoundRect(p) => RecordedRoundRect(*p),
+            Ellipse(p) => RecordedEllipse(*p),
+            Circle(p) => RecordedCircle(*p),
+            Triangle(p) => RecordedTriangle(*p),
+            Polygon(p) => RecordedPolygon(Vec::from_slice(p)),
+            Catmull(p) => RecordedCatmull(Vec::from_slice(p)),
+            QuadraticBezierBound(p) => RecordedQuadraticBezierBound(Vec::from_slice(p)),
+            QuadraticBezierFree(p) => RecordedQuadraticBezierFree(Vec::from_slice(p)),
+        }
+    }
+
+    /// Returns a shape borrowing the owned data.
+    pub fn as_shape<'a>(&'a self) -> Shape<'a, Fl> {
+        match *self {
+            RecordedPixel(ref p) => Pixel(p),
+            RecordedPixelHorizontalLine(ref p) => PixelHorizontalLine(p),
+            RecordedPixelVerticalLine(ref p) => PixelVerticalLine(p),
+            RecordedPixelLine(ref p) => PixelLine(p),
+            RecordedLine(ref p) => Line(p),
+            RecordedRect(ref p) => Rect(p),
+            RecordedRoundRect(ref p) => RoundRect(p),
+            RecordedEllipse(ref p) => Ellipse(p),
+            RecordedCircle(ref p) => Circle(p),
+            RecordedTriangle(ref p) => Triangle(p),
+            RecordedPolygon(ref p) => Polygon(p.as_slice()),
+            RecordedCatmull(ref p) => Catmull(p.as_slice()),
+            RecordedQuadraticBezierBound(ref p) => QuadraticBezierBound(p.as_slice()),
+            RecordedQuadraticBezierFree(ref p) => QuadraticBezierFree(p.as_slice()),
+        }
+    }
+
+    /// Returns the name used in the text format.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            RecordedPixel(_) => "pixel",
+            RecordedPixelHorizontalLine(_) => "pixel_horizontal_line",
+            RecordedPixelVerticalLine(_) => "pixel_vertical_line",
+            RecordedPixelLine(_) => "pixel_line",
+            RecordedLine(_) => "line",
+            RecordedRect(_) => "rect",
+            RecordedRoundRect(_) => "round_rect",
+            RecordedEllipse(_) => "ellipse",
+            RecordedCircle(_) => "circle",
+            RecordedTriangle(_) => "triangle",
+            RecordedPolygon(_) => "polygon",
+            RecordedCatmull(_) => "catmull",
+            RecordedQuadraticBezierBound(_) => "quadratic_bezier_bound",
+            RecordedQuadraticBezierFree(_) => "quadratic_bezier_free",
+        }
+    }
+
+    /// Returns the numbers of the shape.
+    pub fn data<'a>(&'a self) -> &'a [Fl] {
+        match *self {
+            RecordedPixel(ref p) => p.as_slice(),
+            RecordedPixelHorizontalLine(ref p) => p.as_slice(),
+            RecordedPixelVerticalLine(ref p) => p.as_slice(),
+            RecordedPixelLine(ref p) => p.as_slice(),
+            RecordedLine(ref p) => p.as_slice(),
+            RecordedRect(ref p) => p.as_slice(),
+            RecordedRoundRect(ref p) => p.as_slice(),
+            RecordedEllipse(ref p) => p.as_slice(),
+            RecordedCircle(ref p) => p.as_slice(),
+            RecordedTriangle(ref p) => p.as_slice(),
+            RecordedPolygon(ref p) => p.as_slice(),
+            RecordedCatmull(ref p) => p.as_slice(),
+            RecordedQuadraticBezierBound(ref p) => p.as_slice(),
+            RecordedQuadraticBezierFree(ref p) => p.as_slice(),
+        }
+    }
+
+    /// Creates a shape from the name and numbers used in the text format.
+    pub fn parse(name: &str, d: &[Fl]) -> Result<RecordedShape<Fl>, ~str> {
+        let expected = match name {
+            "pixel" => 2,
+            "pixel_horizontal_line" | "pixel_vertical_line" | "circle" => 3,
+            "pixel_line" | "line" | "rect" | "ellipse" => 4,
+            "round_rect" => 5,
+            "triangle" => 6,
+            "polygon" | "catmull" | "quadratic_bezier_bound" | "quadratic_bezier_free" => {
+                if d.len() % 2 != 0 {
+                    return Err(format!("Expected pairs of numbers for `{}`", name));
+                }
+                d.len()
+            }
+            _ => return Err(format!("Unknown shape `{}`", name)),
+        };
+        if d.len() != expected {
+            return Err(format!("Expected {} numbers for `{}`, found {}",
+                expected, name, d.len()));
+        }
+        Ok(match name {
+            "pixel" => RecordedPixel([d[0], d[1]]),
+            "pixel_horizontal_line" => RecordedPixelHorizontalLine([d[0], d[1], d[2]]),
+            "pixel_vertical_line" => RecordedPixelVerticalLine([d[0], d[1], d[2]]),
+            "pixel_line" => RecordedPixelLine([d[0], d[1], d[2], d[3]]),
+            "line" => RecordedLine([d[0], d[1], d[2], d[3]]),
+            "rect" => RecordedRect([d[0], d[1], d[2], d[3]]),
+            "round_rect" => RecordedRoundRect([d[0], d[1], d[2], d[3], d[4]]),
+            "ellipse" => RecordedEllipse([d[0], d[1], d[2], d[3]]),
+            "circle" => RecordedCircle([d[0], d[1], d[2]]),
+            "triangle" => RecordedTriangle([d[0], d[1], d[2], d[3], d[4], d[5]]),
+            "polygon" => RecordedPolygon(Vec::from_slice(d)),
+            "catmull" => RecordedCatmull(Vec::from_slice(d)),
+            "quadratic_bezier_bound" => RecordedQuadraticBezierBound(Vec::from_slice(d)),
+            _ => RecordedQuadraticBezierFree(Vec::from_slice(d)),
+        })
+    }
+}
+
+/// A shape with the view it was drawn with.
+#[deriving(Clone, Eq, Show)]
+pub struct Record<Fl=f64> {
+    /// The base matrix of the view.
+    pub base: Matrix<Fl>,
+    /// The current transformation of the view.
+    pub trans: Matrix<Fl>,
+    /// The shape.
+    pub shape: RecordedShape<Fl>,
+}
+
+/// A list of recorded shapes.
+#[deriving(Clone, Eq, Show)]
+pub struct DisplayList<Fl=f64> {
+    /// The records in drawing order.
+    pub records: Vec<Record<Fl>>,
+}
+
+impl<Fl: Scalar> DisplayList<Fl> {
+    /// Creates an empty display list.
+    pub fn new() -> DisplayList<Fl> {
+        DisplayList { records: Vec::new() }
+    }
+
+    /// Draws the recorded shapes with another shader.
+    ///
+    /// The view of the shader is restored afterwards.
+    pub fn replay<S: Shader<Fl>>(&self, shader: &mut S) {
+        let (base, trans) = {
+            let view = shader.get_view();
+            (view.base, view.trans)
+        };
+        for record in self.records.iter() {
+            {
+                let view = shader.get_mut_view();
+                view.base = record.base;
+                view.trans = record.trans;
+            }
+            shader.shade(&record.shape.as_shape());
+        }
+        let view = shader.get_mut_view();
+        view.base = base;
+        view.trans = trans;
+    }
+
+    /// Writes the display list as text.
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        for record in self.records.iter() {
+            try!(w.write_str(record.shape.name()));
+            for &v in record.base.iter().chain(record.trans.iter())
+                .chain(record.shape.data().iter()) {
+                try!(write!(w, " {}", number_to_str(v.as_f64())));
+            }
+            try!(w.write_str("\n"));
+        }
+        Ok(())
+    }
+
+    /// Reads a display list from text.
+    pub fn parse(text: &str) -> Result<DisplayList<Fl>, ~str> {
+        let mut records = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let mut words = line.words();
+            let name = match words.next() {
+                None => continue,
+                Some(name) => name,
+            };
+            let mut numbers: Vec<Fl> = Vec::new();
+            for word in words {
+                match number_from_str(word) {
+                    None => return Err(format!("Line {}: Expected number, found `{}`",
+                        i + 1, word)),
+                    Some(v) => numbers.push(Scalar::from_f64(v)),
+                }
+            }
+            if numbers.len() < 12 {
+                return Err(format!("Line {}: Expected view matrices", i + 1));
+            }
+            let n = numbers.as_slice();
+            let shape = match RecordedShape::parse(name, n.slice_from(12)) {
+                Err(err) => return Err(format!("Line {}: {}", i + 1, err)),
+                Ok(shape) => shape,
+            };
+            records.push(Record {
+                base: [n[0], n[1], n[2], n[3], n[4], n[5]],
+                trans: [n[6], n[7], n[8], n[9], n[10], n[11]],
+                shape: shape,
+            });
+        }
+        Ok(DisplayList { records: records })
+    }
+}
+
+/// Writes a number such that it reads back exactly.
+fn number_to_str(v: f64) -> ~str {
+    let s = format!("{}", v);
+    if v.is_nan() || from_str::<f64>(s.as_slice()) == Some(v) { return s; }
+    let (m, e) = v.frexp();
+    format!("{}p{}", (m * 9007199254740992.0) as i64, e - 53)
+}
+
+/// Reads a number written by `number_to_str`.
+fn number_from_str(s: &str) -> Option<f64> {
+    match s.find('p') {
+        None => from_str::<f64>(s),
+        Some(i) => match (from_str::<i64>(s.slice_to(i)), from_str::<int>(s.slice_from(i + 1))) {
+            (Some(m), Some(e)) => { let v: f64 = Float::ldexp(m as f64, e); Some(v) }
+            _ => None,
+        },
+    }
+}
+
+/// A shader that records shapes into a display list.
+pub struct Recorder<Fl=f64> {
+    /// The recorded shapes.
+    pub list: DisplayList<Fl>,
+    view: View<Fl>,
+}
+
+impl<Fl: Scalar> Recorder<Fl> {
+    /// Creates a recorder with an empty display list.
+    pub fn new() -> Recorder<Fl> {
+        Recorder {
+            list: DisplayList::new(),
+            view: View::new(),
+        }
+    }
+}
+
+impl<Fl: Scalar> Shader<Fl> for Recorder<Fl> {
+    fn shade(&mut self, shape: &Shape<Fl>) {
+        self.list.records.push(Record {
+            base: self.view.base,
+            trans: self.view.trans,
+            shape: RecordedShape::from_shape(shape),
+        });
+    }
+
+    fn get_view<'a>(&'a self) -> &'a View<Fl> {
+        &self.view
+    }
+
+    fn get_mut_view<'a>(&'a mut self) -> &'a mut View<Fl> {
+        &mut self.view
+    }
+}
+
+#[test]
+fn test_recorder() {
+    use std::io::MemWriter;
+    use std::str;
+
+    let mut recorder: Recorder = Recorder::new();
+    recorder.shade(&Rect(&[1.0, 2.0, 3.0, 4.0]));
+    recorder.get_mut_view().trans(10.0, 0.0);
+    recorder.shade(&Polygon(&[0.0, 0.0, 1.0, 0.0, 0.5, 1.0]));
+
+    let mut copy: Recorder = Recorder::new();
+    recorder.list.replay(&mut copy);
+    assert_eq!(copy.list, recorder.list);
+    assert_eq!(copy.get_view().trans[2], 0.0);
+
+    let mut w = MemWriter::new();
+    recorder.list.write_to(&mut w).unwrap();
+    let text = str::from_utf8(w.get_ref()).unwrap();
+    assert_eq!(DisplayList::parse(text), Ok(recorder.list.clone()));
+    let short: Result<DisplayList, ~str> = DisplayList::parse("rect 1 2 3");
+    assert!(short.is_err());
+
+    // Numbers survive writing and reading bit for bit.
+    let mut recorder: Recorder = Recorder::new();
+    recorder.shade(&Circle(&[0.1, 1.0 / 3.0, 2.0f64.sqrt()]));
+    recorder.get_mut_view().trans(1e-300, -123456789.123456789);
+    recorder.shade(&Line(&[0.2 + 0.1, 1.0, 5e-324, 1.7976931348623157e308]));
+    let mut w = MemWriter::new();
+    recorder.list.write_to(&mut w).unwrap();
+    let text = str::from_utf8(w.get_ref()).unwrap();
+    assert_eq!(DisplayList::parse(text), Ok(recorder.list.clone()));
+    assert_eq!(number_from_str(number_to_str(0.1).as_slice()), Some(0.1));
+    assert_eq!(number_from_str("3p-1"), Some(1.5));
+}
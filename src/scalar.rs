@@ -0,0 +1,199 @@
+//! The float types graphics can be computed with.
+//!
+//! Shapes, pens, views, drawing contexts and the rendering layer,
+//! from curves, strokes and clips to brushes, gradients and the backends,
+//! are generic over a `Scalar`, which is implemented for `f32` and `f64`.
+//! The float type defaults to `f64`.
+//! With `f32` scenes are kept in memory and rendered with `f32` throughout,
+//! without converting shapes when they are drawn.
+//!
+//! Constants such as tolerances are stored as `f64`
+//! and converted with `Scalar::from_f64` where they are used.
+//! The functions in this module convert explicitly between float types,
+//! for example to draw a shape kept with `f64` into an `f32` backend.
+
+use std::fmt::Show;
+use std::num::Float;
+
+use graphics::Shape;
+use graphics::{Pixel, PixelHorizontalLine, PixelVerticalLine, PixelLine};
+use graphics::{Line, Rect, RoundRect, Ellipse, Circle, Triangle, Polygon};
+use graphics::{Catmull, QuadraticBezierBound, QuadraticBezierFree};
+use graphics::dash::Dash;
+use graphics::simple_pen::Pen;
+use graphics::advanced_pen::{AdvancedPen, LineCap, LineJoin};
+use graphics::advanced_pen::{RoundLineCap, ArrowLineCap, SquareLineCap};
+use graphics::advanced_pen::{DiamonLineCap, CircleLineCap};
+use graphics::advanced_pen::{RoundLineJoin, SquareLineJoin, DiamonLineJoin, CircleLineJoin};
+
+/// Implemented by float types used for graphics.
+///
+/// Backends copy values, format them as text
+/// and send them to other threads.
+pub trait Scalar: Float + Copy + Send + Show {
+    /// Converts from `f64`, rounding to the nearest value.
+    fn from_f64(v: f64) -> Self;
+
+    /// Converts to `f64`.
+    ///
+    /// This is not named `to_f64`, which `ToPrimitive` already has.
+    fn as_f64(&self) -> f64;
+}
+
+impl Scalar for f32 {
+    #[inline(always)]
+    fn from_f64(v: f64) -> f32 {
+        v as f32
+    }
+
+    #[inline(always)]
+    fn as_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl Scalar for f64 {
+    #[inline(always)]
+    fn from_f64(v: f64) -> f64 {
+        v
+    }
+
+    #[inline(always)]
+    fn as_f64(&self) -> f64 {
+        *self
+    }
+}
+
+/// Converts a number to another float type.
+#[inline(always)]
+pub fn convert<A: Scalar, B: Scalar>(v: A) -> B {
+    Scalar::from_f64(v.as_f64())
+}
+
+/// Converts a matrix to another float type.
+#[inline(always)]
+pub fn convert_matrix<A: Scalar, B: Scalar>(m: &[A, ..6]) -> [B, ..6] {
+    [convert(m[0]), convert(m[1]), convert(m[2]),
+     convert(m[3]), convert(m[4]), convert(m[5])]
+}
+
+/// Converts a color to another float type.
+#[inline(always)]
+pub fn convert_color<A: Scalar, B: Scalar>(c: &[A, ..4]) -> [B, ..4] {
+    [convert(c[0]), convert(c[1]), convert(c[2]), convert(c[3])]
+}
+
+/// Converts a dash pattern to another float type.
+pub fn convert_dash<A: Scalar, B: Scalar>(dash: &Dash<A>) -> Dash<B> {
+    Dash {
+        array: dash.array.iter().map(|&x| convert(x)).collect(),
+        offset: convert(dash.offset),
+    }
+}
+
+/// Converts a pen to another float type.
+pub fn convert_pen<A: Scalar, B: Scalar>(pen: &Pen<A>) -> Pen<B> {
+    Pen {
+        width: convert(pen.width),
+        color: convert_color(&pen.color),
+        dash: pen.dash.as_ref().map(|dash| convert_dash(dash)),
+    }
+}
+
+/// Converts an advanced pen to another float type.
+pub fn convert_advanced_pen<A: Scalar, B: Scalar>(pen: &AdvancedPen<A>) -> AdvancedPen<B> {
+    AdvancedPen {
+        width: convert(pen.width),
+        color: convert_color(&pen.color),
+        line_cap_start: convert_line_cap(&pen.line_cap_start),
+        line_cap_end: convert_line_cap(&pen.line_cap_end),
+        line_join: convert_line_join(&pen.line_join),
+        dash: pen.dash.as_ref().map(|dash| convert_dash(dash)),
+    }
+}
+
+/// Converts a line cap to another float type.
+fn convert_line_cap<A: Scalar, B: Scalar>(cap: &LineCap<A>) -> LineCap<B> {
+    match *cap {
+        RoundLineCap => RoundLineCap,
+        ArrowLineCap => ArrowLineCap,
+        SquareLineCap => SquareLineCap,
+        DiamonLineCap(r) => DiamonLineCap(convert(r)),
+        CircleLineCap(r) => CircleLineCap(convert(r)),
+    }
+}
+
+/// Converts a line join to another float type.
+fn convert_line_join<A: Scalar, B: Scalar>(join: &LineJoin<A>) -> LineJoin<B> {
+    match *join {
+        RoundLineJoin => RoundLineJoin,
+        SquareLineJoin => SquareLineJoin,
+        DiamonLineJoin(r) => DiamonLineJoin(convert(r)),
+        CircleLineJoin(r) => CircleLineJoin(convert(r)),
+    }
+}
+
+/// Converts a shape to another float type and passes it to a closure.
+///
+/// Shapes borrow their data, so the converted shape only lives during the call.
+/// Shapes with a fixed number of values are converted on the stack,
+/// the others are copied into a new vector.
+pub fn convert_shape<A: Scalar, B: Scalar>(shape: &Shape<A>, f: |&Shape<B>|) {
+    let c = |x: A| -> B { convert(x) };
+    match *shape {
+        Pixel(p) => f(&Pixel(&[c(p[0]), c(p[1])])),
+        PixelHorizontalLine(l) => f(&PixelHorizontalLine(&[c(l[0]), c(l[1]), c(l[2])])),
+        PixelVerticalLine(l) => f(&PixelVerticalLine(&[c(l[0]), c(l[1]), c(l[2])])),
+        PixelLine(l) => f(&PixelLine(&[c(l[0]), c(l[1]), c(l[2]), c(l[3])])),
+        Line(l) => f(&Line(&[c(l[0]), c(l[1]), c(l[2]), c(l[3])])),
+        Rect(r) => f(&Rect(&[c(r[0]), c(r[1]), c(r[2]), c(r[3])])),
+        RoundRect(r) => f(&RoundRect(&[c(r[0]), c(r[1]), c(r[2]), c(r[3]), c(r[4])])),
+        Ellipse(e) => f(&Ellipse(&[c(e[0]), c(e[1]), c(e[2]), c(e[3])])),
+        Circle(r) => f(&Circle(&[c(r[0]), c(r[1]), c(r[2])])),
+        Triangle(t) => f(&Triangle(&[c(t[0]), c(t[1]), c(t[2]), c(t[3]), c(t[4]), c(t[5])])),
+        Polygon(p) | Catmull(p) | QuadraticBezierBound(p) | QuadraticBezierFree(p) => {
+            let v: Vec<B> = p.iter().map(|&x| convert(x)).collect();
+            let v = v.as_slice();
+            match *shape {
+                Polygon(_) => f(&Polygon(v)),
+                Catmull(_) => f(&Catmull(v)),
+                QuadraticBezierBound(_) => f(&QuadraticBezierBound(v)),
+                _ => f(&QuadraticBezierFree(v)),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_scalar() {
+    use graphics::View;
+    use graphics::raster::RasterBuffer;
+    use graphics_new::Context;
+
+    assert_eq!(convert::<f64, f32>(0.5), 0.5f32);
+    let mut view: View<f32> = View::new();
+    view.trans(10.0, 0.0);
+    view.scale(2.0, 2.0);
+    assert_eq!(view.convert::<f64>().matrix()[2], 20.0);
+    let pen: Pen<f32> = Pen { width: 2.0, color: [1.0, 0.0, 0.0, 1.0], dash: None };
+    let pen: AdvancedPen<f64> = convert_advanced_pen(&AdvancedPen::from_pen(&pen));
+    assert_eq!(pen.width, 2.0);
+
+    // The same drawing with both float types gives the same pixels.
+    let mut a: RasterBuffer<f32> = RasterBuffer::new(8, 8);
+    let c: Context<f32> = Context::new();
+    let c = c.trans(2.0, 2.0);
+    let c = c.rgba(1.0, 0.0, 0.0, 1.0);
+    c.rect(&mut a, 0.0, 0.0, 3.0, 2.0);
+    c.ellipse(&mut a, 1.0, 2.0, 3.0, 3.0);
+
+    let mut b: RasterBuffer = RasterBuffer::new(8, 8);
+    let c: Context<f64> = Context::new();
+    let c = c.trans(2.0, 2.0);
+    let c = c.rgba(1.0, 0.0, 0.0, 1.0);
+    c.rect(&mut b, 0.0, 0.0, 3.0, 2.0);
+    c.ellipse(&mut b, 1.0, 2.0, 3.0, 3.0);
+
+    assert_eq!(a.get_pixel(2, 2), [255, 0, 0, 255]);
+    assert!(a.pixels == b.pixels);
+}
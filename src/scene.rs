@@ -0,0 +1,400 @@
+
+//! A retained scene of shapes organized in groups.
+//!
+//! Every node has a transform from its own coordinates
+//! to the coordinates of its parent, a z-order and a visibility flag.
+//! Groups draw their children sorted by z-order,
+//! with children of equal z-order drawn in the order they were added.
+//! Hiding a group hides everything inside it.
+//!
+//! Nodes are named by ids that stay the same while the scene changes,
+//! so an editor can keep them and mutate single nodes.
+//! Ids of removed nodes are never given out again.
+//!
+//! Transforms and recorded shapes use the float type of the scene,
+//! which should match the pens, brushes and gradients of the effects.
+
+use std::num::zero;
+
+use graphics::{Shader, Shape, Matrix, Effect, Stroke, Fill, Gradient};
+use graphics::advanced_pen::AdvancedPen;
+use graphics::brush::SolidBrush;
+use graphics::gradient::ColorGradient;
+use graphics::recorder::RecordedShape;
+use graphics::bounds::transformed_bounding_box;
+use graphics::damage::intersects;
+use graphics::scalar::Scalar;
+use graphics_new::{identity, multiply};
+
+/// Implemented by shaders that can draw a shape with an effect.
+pub trait EffectShader<P, B, G, Fl=f64>: Shader<Fl> {
+    /// Draws a shape with an effect.
+    fn shade_effect(&mut self, shape: &Shape<Fl>, effect: &Effect<P, B, G>);
+}
+
+/// A stable name of a node in a scene.
+#[deriving(Clone, Eq, TotalEq, Hash, Show)]
+pub struct NodeId(pub uint);
+
+/// An owned effect.
+#[deriving(Clone)]
+pub enum SceneEffect<P, B, G> {
+    /// Draws edges with a pen.
+    StrokeEffect(P),
+    /// Fills with a brush.
+    FillEffect(B),
+    /// Fills with a gradient.
+    GradientEffect(G),
+}
+
+impl<P, B, G> SceneEffect<P, B, G> {
+    /// Returns an effect borrowing the owned data.
+    pub fn as_effect<'a>(&'a self) -> Effect<'a, P, B, G> {
+        match *self {
+            StrokeEffect(ref pen) => Stroke(pen),
+            FillEffect(ref brush) => Fill(brush),
+            GradientEffect(ref gradient) => Gradient(gradient),
+        }
+    }
+}
+
+/// What a node draws.
+#[deriving(Clone)]
+pub enum NodeKind<P, B, G, Fl=f64> {
+    /// A group drawing its children.
+    GroupNode,
+    /// A shape drawn with an effect.
+    ShapeNode(RecordedShape<Fl>, SceneEffect<P, B, G>),
+}
+
+/// A node in a scene.
+#[deriving(Clone)]
+pub struct Node<P, B, G, Fl=f64> {
+    /// What the node draws.
+    pub kind: NodeKind<P, B, G, Fl>,
+    /// Transform from node coordinates to parent coordinates.
+    pub transform: Matrix<Fl>,
+    /// The order among siblings, lower values are drawn first.
+    pub z: int,
+    /// Whether the node and its children are drawn.
+    pub visible: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl<P, B, G, Fl> Node<P, B, G, Fl> {
+    /// Returns the parent, or `None` for the root.
+    #[inline(always)]
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    /// Returns the children in the order they were added.
+    #[inline(always)]
+    pub fn children<'a>(&'a self) -> &'a [NodeId] {
+        self.children.as_slice()
+    }
+}
+
+/// A tree of nodes with a root group.
+#[deriving(Clone)]
+pub struct Scene<P=AdvancedPen, B=SolidBrush, G=ColorGradient, Fl=f64> {
+    nodes: Vec<Option<Node<P, B, G, Fl>>>,
+}
+
+impl<P, B, G, Fl: Scalar> Scene<P, B, G, Fl> {
+    /// Creates a scene with an empty root group.
+    pub fn new() -> Scene<P, B, G, Fl> {
+        Scene {
+            nodes: vec!(Some(Node {
+                kind: GroupNode,
+                transform: identity(),
+                z: 0,
+                visible: true,
+                parent: None,
+                children: Vec::new(),
+            })),
+        }
+    }
+
+    /// Returns the root group.
+    #[inline(always)]
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// Returns a node, or `None` if it was removed.
+    pub fn get<'a>(&'a self, id: NodeId) -> Option<&'a Node<P, B, G, Fl>> {
+        let NodeId(i) = id;
+        if i >= self.nodes.len() { return None; }
+        self.nodes.get(i).as_ref()
+    }
+
+    /// Returns a mutable node, or `None` if it was removed.
+    pub fn get_mut<'a>(&'a mut self, id: NodeId) -> Option<&'a mut Node<P, B, G, Fl>> {
+        let NodeId(i) = id;
+        if i >= self.nodes.len() { return None; }
+        self.nodes.get_mut(i).as_mut()
+    }
+
+    /// Adds an empty group with a transform to a group.
+    ///
+    /// Returns `None` if the parent is not a group in the scene.
+    pub fn add_group(&mut self, parent: NodeId, transform: Matrix<Fl>) -> Option<NodeId> {
+        self.add(parent, GroupNode, transform)
+    }
+
+    /// Adds a shape drawn with an effect to a group.
+    ///
+    /// Returns `None` if the parent is not a group in the scene.
+    pub fn add_shape(
+        &mut self,
+        parent: NodeId,
+        shape: &Shape<Fl>,
+        effect: SceneEffect<P, B, G>
+    ) -> Option<NodeId> {
+        self.add(parent, ShapeNode(RecordedShape::from_shape(shape), effect), identity())
+    }
+
+    /// Adds a node at the end of the children of a group.
+    fn add(
+        &mut self,
+        parent: NodeId,
+        kind: NodeKind<P, B, G, Fl>,
+        transform: Matrix<Fl>
+    ) -> Option<NodeId> {
+        if !self.is_group(parent) { return None; }
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Some(Node {
+            kind: kind,
+            transform: transform,
+            z: 0,
+            visible: true,
+            parent: Some(parent),
+            children: Vec::new(),
+        }));
+        self.get_mut(parent).unwrap().children.push(id);
+        Some(id)
+    }
+
+    /// Returns true if a node is a group in the scene.
+    pub fn is_group(&self, id: NodeId) -> bool {
+        match self.get(id) {
+            Some(&Node { kind: GroupNode, .. }) => true,
+            _ => false,
+        }
+    }
+
+    /// Removes a node and everything inside it.
+    ///
+    /// Returns false if the node is not in the scene or is the root.
+    pub fn remove(&mut self, id: NodeId) -> bool {
+        let parent = match self.get(id) {
+            Some(node) => node.parent,
+            None => return false,
+        };
+        let parent = match parent {
+            Some(parent) => parent,
+            None => return false,
+        };
+        self.get_mut(parent).unwrap().children.retain(|&child| child != id);
+        let mut stack = vec!(id);
+        while stack.len() > 0 {
+            let NodeId(i) = stack.pop().unwrap();
+            match self.nodes.get_mut(i).take() {
+                Some(node) => stack.push_all_move(node.children),
+                None => {}
+            }
+        }
+        true
+    }
+
+    /// Moves a node to the end of the children of another group.
+    ///
+    /// Returns false if either node is not in the scene,
+    /// if the node is the root or if the group is inside the node.
+    pub fn move_to(&mut self, id: NodeId, group: NodeId) -> bool {
+        if !self.is_group(group) { return false; }
+        let old_parent = match self.get(id) {
+            Some(&Node { parent: Some(parent), .. }) => parent,
+            _ => return false,
+        };
+        let mut ancestor = Some(group);
+        while ancestor.is_some() {
+            let a = ancestor.unwrap();
+            if a == id { return false; }
+            ancestor = self.get(a).unwrap().parent;
+        }
+        self.get_mut(old_parent).unwrap().children.retain(|&child| child != id);
+        self.get_mut(group).unwrap().children.push(id);
+        self.get_mut(id).unwrap().parent = Some(group);
+        true
+    }
+
+    /// Returns the transform from node coordinates to scene coordinates.
+    ///
+    /// Returns `None` if the node is not in the scene.
+    pub fn world_transform(&self, id: NodeId) -> Option<Matrix<Fl>> {
+        let mut node = match self.get(id) {
+            None => return None,
+            Some(node) => node,
+        };
+        let mut m = node.transform;
+        loop {
+            node = match node.parent {
+                None => return Some(m),
+                Some(parent) => self.get(parent).unwrap(),
+            };
+            m = multiply(&node.transform, &m);
+        }
+    }
+
+    /// Returns the visible shapes in drawing order,
+    /// with their transforms to scene coordinates.
+    pub fn draw_order(&self) -> Vec<(NodeId, Matrix<Fl>)> {
+        let mut res = Vec::new();
+        self.collect(self.root(), &identity(), &mut res);
+        res
+    }
+
+    /// Adds the visible shapes inside a node in drawing order.
+    fn collect(&self, id: NodeId, parent: &Matrix<Fl>, res: &mut Vec<(NodeId, Matrix<Fl>)>) {
+        let node = self.get(id).unwrap();
+        if !node.visible { return; }
+        let m = multiply(parent, &node.transform);
+        match node.kind {
+            ShapeNode(..) => res.push((id, m)),
+            GroupNode => {
+                let mut children = node.children.clone();
+                children.as_mut_slice().sort_by(|&a, &b|
+                    self.get(a).unwrap().z.cmp(&self.get(b).unwrap().z));
+                for &child in children.iter() {
+                    self.collect(child, &m, res);
+                }
+            }
+        }
+    }
+
+    /// Draws the visible shapes with their effects.
+    ///
+    /// Scene coordinates are mapped through the current view of the shader,
+    /// which is restored afterwards.
+    pub fn shade<S: EffectShader<P, B, G, Fl>>(&self, shader: &mut S) {
+        self.shade_inside(shader, None, zero());
+    }
+
+    /// Draws the visible shapes with their effects inside a box [x1, y1, x2, y2]
+    /// in device coordinates.
+    ///
+    /// Shapes whose bounding box, grown by the padding on every side,
+    /// misses the box are skipped.
+    /// The shader should be clipped to the box, see `DamageTracker::redraw`.
+    pub fn shade_region<S: EffectShader<P, B, G, Fl>>(
+        &self,
+        shader: &mut S,
+        region: [Fl, ..4],
+        padding: Fl
+    ) {
+        self.shade_inside(shader, Some(region), padding);
+    }
+
+    /// Draws the visible shapes with their effects,
+    /// skipping those outside the region if there is one.
+    fn shade_inside<S: EffectShader<P, B, G, Fl>>(
+        &self,
+        shader: &mut S,
+        region: Option<[Fl, ..4]>,
+        padding: Fl
+    ) {
+        let view = shader.get_view().matrix();
+        for &(id, m) in self.draw_order().iter() {
+            match self.get(id).unwrap().kind {
+                ShapeNode(ref shape, ref effect) => {
+                    let culled = match region {
+                        None => false,
+                        Some(r) => match transformed_bounding_box(&shape.as_shape(),
+                                                                  &multiply(&view, &m)) {
+                            None => true,
+                            Some(b) => !intersects(r, [b[0] - padding, b[1] - padding,
+                                                       b[2] + padding, b[3] + padding]),
+                        },
+                    };
+                    if culled { continue; }
+                    push_transform(shader, &m);
+                    shader.shade_effect(&shape.as_shape(), &effect.as_effect());
+                    shader.get_mut_view().pop();
+                }
+                GroupNode => {}
+            }
+        }
+    }
+
+    /// Draws the visible shapes without their effects.
+    ///
+    /// This works with any shader, for example to record or measure the scene.
+    pub fn shade_shapes<S: Shader<Fl>>(&self, shader: &mut S) {
+        for &(id, m) in self.draw_order().iter() {
+            match self.get(id).unwrap().kind {
+                ShapeNode(ref shape, _) => {
+                    push_transform(shader, &m);
+                    shader.shade(&shape.as_shape());
+                    shader.get_mut_view().pop();
+                }
+                GroupNode => {}
+            }
+        }
+    }
+}
+
+/// Saves the view of a shader and maps scene coordinates through it.
+fn push_transform<Fl: Scalar, S: Shader<Fl>>(shader: &mut S, m: &Matrix<Fl>) {
+    let view = shader.get_mut_view();
+    view.push();
+    view.trans = multiply(&view.trans, m);
+}
+
+#[test]
+fn test_scene() {
+    use graphics::Rect;
+    use graphics::recorder::{Recorder, RecordedRect};
+    use graphics::raster::RasterBuffer;
+
+    let red: SolidBrush = SolidBrush::new([1.0, 0.0, 0.0, 1.0]);
+    let blue: SolidBrush = SolidBrush::new([0.0, 0.0, 1.0, 1.0]);
+    let mut scene: Scene = Scene::new();
+    let root = scene.root();
+    let group = scene.add_group(root, [1.0, 0.0, 2.0,
+                                       0.0, 1.0, 0.0]).unwrap();
+    let a = scene.add_shape(group, &Rect(&[0.0, 0.0, 4.0, 4.0]), FillEffect(red)).unwrap();
+    let b = scene.add_shape(root, &Rect(&[2.0, 1.0, 1.0, 1.0]), FillEffect(blue)).unwrap();
+    assert!(scene.add_shape(a, &Rect(&[0.0, 0.0, 1.0, 1.0]), FillEffect(blue)).is_none());
+    assert_eq!(scene.world_transform(a).unwrap()[2], 2.0);
+
+    // The group is drawn after the blue rectangle when it has a higher z-order.
+    assert_eq!(scene.draw_order().iter().map(|&(id, _)| id).collect::<Vec<NodeId>>(),
+        vec!(a, b));
+    scene.get_mut(group).unwrap().z = 1;
+    assert_eq!(scene.draw_order().iter().map(|&(id, _)| id).collect::<Vec<NodeId>>(),
+        vec!(b, a));
+
+    let mut raster: RasterBuffer = RasterBuffer::new(8, 8);
+    scene.shade(&mut raster);
+    assert_eq!(raster.get_pixel(2, 1), [255, 0, 0, 255]);
+    assert_eq!(raster.get_pixel(1, 1), [0, 0, 0, 0]);
+    assert_eq!(raster.get_view().trans[2], 0.0);
+
+    scene.get_mut(group).unwrap().visible = false;
+    let mut recorder: Recorder = Recorder::new();
+    scene.shade_shapes(&mut recorder);
+    assert_eq!(recorder.list.records.len(), 1);
+    assert_eq!(recorder.list.records.get(0).shape, RecordedRect([2.0, 1.0, 1.0, 1.0]));
+
+    // Ids stay valid for the remaining nodes.
+    assert!(!scene.move_to(group, a));
+    assert!(scene.move_to(b, group));
+    assert!(scene.remove(group));
+    assert!(scene.get(a).is_none() && scene.get(b).is_none());
+    assert!(!scene.remove(root));
+    let c = scene.add_group(root, identity()).unwrap();
+    assert!(c != a && c != b && c != group);
+}
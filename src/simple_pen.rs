@@ -1,9 +1,15 @@
 
 //! A simple pen with round edges.
 
+use graphics::dash::Dash;
+
 /// A simple pen with round edges.
 pub struct Pen<Fl=f64> {
-    width: Fl,
-    color: [Fl, ..4]
+    /// Border width from edge to edge.
+    pub width: Fl,
+    /// Pen color.
+    pub color: [Fl, ..4],
+    /// Dash pattern, or `None` for a solid line.
+    pub dash: Option<Dash<Fl>>,
 }
 
@@ -0,0 +1,319 @@
+
+//! Converts stroked lines into polygons that can be filled.
+//!
+//! Each segment, join and cap becomes a separate polygon.
+//! All polygons have the same orientation,
+//! so filling them with the nonzero winding rule gives their union.
+//! This makes strokes look the same in every backend that can fill polygons.
+
+use std::cmp::{min, max};
+use std::num::{Float, zero, one};
+
+use graphics::Shape;
+use graphics::advanced_pen::{AdvancedPen, LineCap, LineJoin};
+use graphics::advanced_pen::{RoundLineCap, ArrowLineCap, SquareLineCap};
+use graphics::advanced_pen::{DiamonLineCap, CircleLineCap};
+use graphics::advanced_pen::{RoundLineJoin, SquareLineJoin};
+use graphics::advanced_pen::{DiamonLineJoin, CircleLineJoin};
+use graphics::curve::outline;
+use graphics::dash::dash_shape;
+use graphics::scalar::Scalar;
+use graphics_new::identity;
+
+/// The length of an arrow head relative to the pen width.
+pub static ARROW_LENGTH: f64 = 3.0;
+
+/// The maximum ratio between miter length and pen width.
+pub static MITER_LIMIT: f64 = 4.0;
+
+/// Strokes the outline of a shape.
+///
+/// Every shape is first converted to polylines by `curve::outline`,
+/// so round shapes are stroked along their flattened outline.
+/// The tolerance is the maximum error when approximating round parts.
+///
+/// With a dash pattern every shape is split into dashes,
+/// and each dash gets the start and end caps of the pen.
+pub fn stroke_shape<Fl: Scalar>(
+    shape: &Shape<Fl>,
+    pen: &AdvancedPen<Fl>,
+    tolerance: Fl
+) -> Vec<Vec<[Fl, ..2]>> {
+    match pen.dash {
+        Some(ref dash) if !dash.is_solid() => {
+            let mut res = Vec::new();
+            for piece in dash_shape(shape, dash, tolerance).iter() {
+                res.push_all_move(stroke_polyline(piece.as_slice(), false, pen, tolerance));
+            }
+            return res;
+        }
+        _ => {}
+    }
+    let mut res = Vec::new();
+    for &(ref points, closed) in outline(shape, &identity(), tolerance).iter() {
+        res.push_all_move(stroke_polyline(points.as_slice(), closed, pen, tolerance));
+    }
+    res
+}
+
+/// Strokes a polyline.
+///
+/// A closed polyline gets a join between the last and first point,
+/// while an open polyline gets the start and end caps of the pen.
+pub fn stroke_polyline<Fl: Scalar>(
+    points: &[[Fl, ..2]],
+    closed: bool,
+    pen: &AdvancedPen<Fl>,
+    tolerance: Fl
+) -> Vec<Vec<[Fl, ..2]>> {
+    let mut points: Vec<[Fl, ..2]> = points.iter().map(|&p| p).collect();
+    points.dedup();
+    if closed && points.len() > 1 && points.as_slice()[0] == *points.last().unwrap() {
+        points.pop();
+    }
+
+    let (o, half): (Fl, Fl) = (zero(), Scalar::from_f64(0.5));
+    let hw = half * pen.width;
+    let mut res = Vec::new();
+    let n = points.len();
+    if n == 0 || hw <= o { return res; }
+    if n == 1 {
+        let p = points.as_slice()[0];
+        res.push(circle(p, hw, tolerance));
+        return res;
+    }
+
+    let p = points.as_mut_slice();
+    let (start, end) = (p[0], p[n - 1]);
+    if !closed {
+        trim_for_arrow(p, 0, 1, &pen.line_cap_start, pen.width);
+        trim_for_arrow(p, n - 1, n - 2, &pen.line_cap_end, pen.width);
+    }
+
+    let segments = if closed { n } else { n - 1 };
+    for i in range(0u, segments) {
+        let (a, b) = (p[i], p[(i + 1) % n]);
+        let quad = segment(a, b, hw);
+        if quad.len() > 0 { res.push(quad); }
+    }
+
+    let (first, last) = if closed { (0u, n) } else { (1u, n - 1) };
+    for i in range(first, last) {
+        let prev = p[(i + n - 1) % n];
+        let next = p[(i + 1) % n];
+        join(&mut res, prev, p[i], next, hw, &pen.line_join, tolerance);
+    }
+
+    if !closed {
+        cap(&mut res, p[1], start, hw, &pen.line_cap_start, tolerance);
+        cap(&mut res, p[n - 2], end, hw, &pen.line_cap_end, tolerance);
+    }
+
+    for polygon in res.mut_iter() {
+        orient(polygon);
+    }
+    res
+}
+
+/// Moves an end point back so the line does not stick out of the arrow head.
+fn trim_for_arrow<Fl: Scalar>(
+    p: &mut [[Fl, ..2]],
+    end: uint,
+    inner: uint,
+    line_cap: &LineCap<Fl>,
+    width: Fl
+) {
+    match *line_cap {
+        ArrowLineCap => {}
+        _ => return,
+    }
+    let half: Fl = Scalar::from_f64(0.5);
+    let (dir, len) = direction(p[inner], p[end]);
+    let trim = width.min(half * len);
+    p[end] = [p[end][0] - dir[0] * trim, p[end][1] - dir[1] * trim];
+}
+
+/// Returns the rectangle covering a segment.
+fn segment<Fl: Scalar>(a: [Fl, ..2], b: [Fl, ..2], hw: Fl) -> Vec<[Fl, ..2]> {
+    let (dir, len) = direction(a, b);
+    if len == zero() { return vec!(); }
+    let n = [-dir[1] * hw, dir[0] * hw];
+    vec!([a[0] + n[0], a[1] + n[1]], [b[0] + n[0], b[1] + n[1]],
+         [b[0] - n[0], b[1] - n[1]], [a[0] - n[0], a[1] - n[1]])
+}
+
+/// Adds polygons that fill the gap between two segments.
+fn join<Fl: Scalar>(
+    res: &mut Vec<Vec<[Fl, ..2]>>,
+    prev: [Fl, ..2],
+    p: [Fl, ..2],
+    next: [Fl, ..2],
+    hw: Fl,
+    line_join: &LineJoin<Fl>,
+    tolerance: Fl
+) {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    let (d1, _) = direction(prev, p);
+    let (d2, _) = direction(p, next);
+    let n1 = [-d1[1] * hw, d1[0] * hw];
+    let n2 = [-d2[1] * hw, d2[0] * hw];
+    let bevel = |s: Fl| vec!(p, [p[0] + s * n1[0], p[1] + s * n1[1]],
+                             [p[0] + s * n2[0], p[1] + s * n2[1]]);
+    res.push(bevel(l));
+    res.push(bevel(-l));
+    match *line_join {
+        RoundLineJoin => res.push(circle(p, hw, tolerance)),
+        SquareLineJoin => {
+            let (half, miter_limit): (Fl, Fl) =
+                (Scalar::from_f64(0.5), Scalar::from_f64(MITER_LIMIT));
+            // The outer side is where the turn opens.
+            let s = if d1[0] * d2[1] - d1[1] * d2[0] > o { -l } else { l };
+            let cos = d1[0] * d2[0] + d1[1] * d2[1];
+            let half_cos = (half * (l + cos)).max(o).sqrt();
+            if half_cos * miter_limit <= l { return; }
+            let bisector = [n1[0] + n2[0], n1[1] + n2[1]];
+            let len = (bisector[0] * bisector[0] + bisector[1] * bisector[1]).sqrt();
+            if len == o { return; }
+            let miter = hw / half_cos;
+            res.push(vec!(p,
+                [p[0] + s * n1[0], p[1] + s * n1[1]],
+                [p[0] + s * bisector[0] / len * miter, p[1] + s * bisector[1] / len * miter],
+                [p[0] + s * n2[0], p[1] + s * n2[1]]));
+        }
+        DiamonLineJoin(r) => res.push(diamond(p, d1, r)),
+        CircleLineJoin(r) => res.push(circle(p, r, tolerance)),
+    }
+}
+
+/// Adds polygons for a cap at the end of a line going from `from` to `end`.
+///
+/// The end is the original end point, before trimming for arrows.
+fn cap<Fl: Scalar>(
+    res: &mut Vec<Vec<[Fl, ..2]>>,
+    from: [Fl, ..2],
+    end: [Fl, ..2],
+    hw: Fl,
+    line_cap: &LineCap<Fl>,
+    tolerance: Fl
+) {
+    let (d, _) = direction(from, end);
+    let n = [-d[1], d[0]];
+    match *line_cap {
+        RoundLineCap => res.push(circle(end, hw, tolerance)),
+        SquareLineCap => res.push(vec!(
+            [end[0] + n[0] * hw, end[1] + n[1] * hw],
+            [end[0] + (d[0] + n[0]) * hw, end[1] + (d[1] + n[1]) * hw],
+            [end[0] + (d[0] - n[0]) * hw, end[1] + (d[1] - n[1]) * hw],
+            [end[0] - n[0] * hw, end[1] - n[1] * hw])),
+        ArrowLineCap => {
+            let (arrow_length, two, half): (Fl, Fl, Fl) = (Scalar::from_f64(ARROW_LENGTH),
+                Scalar::from_f64(2.0), Scalar::from_f64(0.5));
+            let len = arrow_length * two * hw;
+            let base = [end[0] - d[0] * len, end[1] - d[1] * len];
+            let half = half * len;
+            res.push(vec!(end,
+                [base[0] + n[0] * half, base[1] + n[1] * half],
+                [base[0] - n[0] * half, base[1] - n[1] * half]));
+        }
+        DiamonLineCap(r) => res.push(diamond(end, d, r)),
+        CircleLineCap(r) => res.push(circle(end, r, tolerance)),
+    }
+}
+
+/// Returns a unit direction from one point to another, and the distance.
+#[inline(always)]
+fn direction<Fl: Scalar>(a: [Fl, ..2], b: [Fl, ..2]) -> ([Fl, ..2], Fl) {
+    let (o, l): (Fl, Fl) = (zero(), one());
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    let len = (dx * dx + dy * dy).sqrt();
+    if len == o { ([l, o], o) }
+    else { ([dx / len, dy / len], len) }
+}
+
+/// Returns a diamond with a radius, pointing along a direction.
+fn diamond<Fl: Scalar>(p: [Fl, ..2], d: [Fl, ..2], r: Fl) -> Vec<[Fl, ..2]> {
+    vec!([p[0] + d[0] * r, p[1] + d[1] * r],
+         [p[0] - d[1] * r, p[1] + d[0] * r],
+         [p[0] - d[0] * r, p[1] - d[1] * r],
+         [p[0] + d[1] * r, p[1] - d[0] * r])
+}
+
+/// Returns a polygon approximating a circle.
+fn circle<Fl: Scalar>(p: [Fl, ..2], r: Fl, tolerance: Fl) -> Vec<[Fl, ..2]> {
+    let pi: Fl = Float::pi();
+    let (l, two): (Fl, Fl) = (one(), Scalar::from_f64(2.0));
+    let n = if r <= tolerance { 8 } else {
+        let step = two * (l - tolerance / r).acos();
+        min(max((two * pi / step).ceil().as_f64() as uint, 8), 1024)
+    };
+    let count: Fl = Scalar::from_f64(n as f64);
+    range(0u, n).map(|i| {
+        let i: Fl = Scalar::from_f64(i as f64);
+        let angle = two * pi * i / count;
+        [p[0] + r * angle.cos(), p[1] + r * angle.sin()]
+    }).collect()
+}
+
+/// Makes the polygon have positive signed area.
+fn orient<Fl: Scalar>(polygon: &mut Vec<[Fl, ..2]>) {
+    let o: Fl = zero();
+    let p = polygon.as_slice();
+    let n = p.len();
+    let mut area = o;
+    for i in range(0u, n) {
+        let (a, b) = (p[i], p[(i + 1) % n]);
+        area = area + (a[0] * b[1] - b[0] * a[1]);
+    }
+    if area < o { polygon.as_mut_slice().reverse(); }
+}
+
+#[test]
+fn test_stroke_arrow() {
+    use graphics::Line;
+
+    let pen: AdvancedPen = AdvancedPen {
+        width: 2.0,
+        color: [0.0, 0.0, 0.0, 1.0],
+        line_cap_start: SquareLineCap,
+        line_cap_end: ArrowLineCap,
+        line_join: RoundLineJoin,
+        dash: None,
+    };
+    let res = stroke_shape(&Line(&[0.0, 0.0, 20.0, 0.0]), &pen, 0.1);
+    // One segment, one square cap and one arrow head.
+    assert_eq!(res.len(), 3);
+    let arrow = res.last().unwrap();
+    assert_eq!(arrow.len(), 3);
+    assert!(arrow.iter().any(|p| p[0] == 20.0 && p[1] == 0.0));
+    assert!(arrow.iter().all(|p| p[0] >= 14.0));
+}
+
+#[test]
+fn test_stroke_circle() {
+    use graphics::Circle;
+    use graphics::bounds::{winding, union};
+
+    let pen: AdvancedPen = AdvancedPen {
+        width: 2.0,
+        color: [0.0, 0.0, 0.0, 1.0],
+        line_cap_start: RoundLineCap,
+        line_cap_end: RoundLineCap,
+        line_join: SquareLineJoin,
+        dash: None,
+    };
+    let res = stroke_shape(&Circle(&[0.0, 0.0, 10.0]), &pen, 0.1);
+    assert!(res.len() > 0);
+    let mut b = None;
+    for polygon in res.iter() {
+        for &p in polygon.iter() {
+            b = Some(union(b, [p[0], p[1], p[0], p[1]]));
+        }
+    }
+    let b = b.unwrap();
+    assert!((b[0] + 11.0).abs() < 0.2 && (b[2] - 11.0).abs() < 0.2);
+    // The ring is covered, the center is not.
+    let covered = |p: [f64, ..2]| res.iter().any(|polygon| winding(polygon.as_slice(), p) != 0);
+    assert!(covered([10.0, 0.0]));
+    assert!(covered([0.0, -10.5]));
+    assert!(!covered([0.0, 0.0]));
+}
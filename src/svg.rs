@@ -0,0 +1,694 @@
+
+//! A backend writing shapes to an SVG document.
+//!
+//! Each shape becomes one SVG element.
+//! The effect set with `set_effect` decides how following shapes are painted.
+//! Pens, brushes and gradients are translated through the
+//! `SvgPen`, `SvgBrush` and `SvgGradient` traits,
+//! so other types can be used by implementing them.
+//! Strokes with caps or joins SVG does not have, or different caps at both ends,
+//! are written as filled paths by `EffectShader::shade_effect`.
+//!
+//! Blend modes are written as the CSS `mix-blend-mode` property.
+//! SVG can not express the other Porter-Duff operators on single elements,
+//! so they are drawn with source-over.
+//!
+//! Each region of the clip becomes a `clipPath` definition,
+//! and clipped elements are wrapped in one group per region.
+
+use std::io::{IoResult, Writer};
+use std::mem;
+use std::num::{zero, one};
+
+use graphics::{Shader, Shape, View, Matrix, Effect, Stroke, Fill, Gradient};
+use graphics::{Pixel, PixelHorizontalLine, PixelVerticalLine, PixelLine};
+use graphics::{Line, Rect, RoundRect, Ellipse, Circle, Triangle, Polygon};
+use graphics::{Catmull, QuadraticBezierBound, QuadraticBezierFree};
+use graphics::simple_pen::Pen;
+use graphics::advanced_pen::{AdvancedPen, RoundLineCap, SquareLineCap};
+use graphics::advanced_pen::{RoundLineJoin, SquareLineJoin};
+use graphics::stroke::stroke_shape;
+use graphics::gradient::{ColorGradient, PadSpread, RepeatSpread, ReflectSpread};
+use graphics::gradient::{LinearGradient, RadialGradient, ConicGradient};
+use graphics::brush::{FillRule, NonZero, EvenOdd};
+use graphics::brush::{SolidBrush, HatchBrush, CheckerBrush, ImageBrush};
+use graphics::dash::Dash;
+use graphics::composite::{CompositeOp, SourceOver, Plus, Multiply, Screen, Overlay};
+use graphics::composite::{Darken, Lighten, Difference};
+use graphics::clip::Clip;
+use graphics::scalar::Scalar;
+use graphics::scene::EffectShader;
+use graphics::damage::ClipShader;
+use graphics_new::{Backend, Matrix2d, Color, identity};
+
+/// Implemented by pens that can be written as SVG stroke attributes.
+pub trait SvgPen<Fl=f64> {
+    /// Returns the stroke color.
+    fn svg_color(&self) -> [Fl, ..4];
+    /// Returns attributes describing the stroke, except the color.
+    fn svg_stroke_attributes(&self) -> ~str;
+
+    /// Returns a pen to stroke with as a filled path,
+    /// when the stroke can not be written as attributes.
+    fn svg_outline<'a>(&'a self) -> Option<&'a AdvancedPen<Fl>> {
+        None
+    }
+}
+
+/// Implemented by brushes that can be written as SVG paint.
+pub trait SvgBrush<Fl=f64> {
+    /// Writes necessary definitions and returns the paint and opacity.
+    ///
+    /// The id can be used to name an element in the definitions.
+    fn svg_paint(&self, id: &str, defs: &mut Vec<~str>) -> (~str, Fl);
+
+    /// Returns the fill rule.
+    fn svg_fill_rule(&self) -> FillRule {
+        NonZero
+    }
+}
+
+/// Implemented by gradients that can be written as SVG definitions.
+pub trait SvgGradient {
+    /// Returns a gradient element with an id.
+    fn svg_gradient(&self, id: &str) -> ~str;
+}
+
+impl<Fl: Scalar> SvgPen<Fl> for Pen<Fl> {
+    fn svg_color(&self) -> [Fl, ..4] {
+        self.color
+    }
+
+    fn svg_stroke_attributes(&self) -> ~str {
+        format!("stroke-width=\"{}\" stroke-linecap=\"round\" stroke-linejoin=\"round\"{}",
+            self.width, dash_attributes(&self.dash))
+    }
+}
+
+impl<Fl: Scalar> SvgPen<Fl> for AdvancedPen<Fl> {
+    fn svg_color(&self) -> [Fl, ..4] {
+        self.color
+    }
+
+    fn svg_stroke_attributes(&self) -> ~str {
+        let cap = match self.line_cap_start {
+            RoundLineCap => "round",
+            SquareLineCap => "square",
+            _ => "butt",
+        };
+        let join = match self.line_join {
+            RoundLineJoin => "round",
+            SquareLineJoin => "miter",
+            _ => "bevel",
+        };
+        format!("stroke-width=\"{}\" stroke-linecap=\"{}\" stroke-linejoin=\"{}\"{}",
+            self.width, cap, join, dash_attributes(&self.dash))
+    }
+
+    fn svg_outline<'a>(&'a self) -> Option<&'a AdvancedPen<Fl>> {
+        if self.has_standard_ends() { None } else { Some(self) }
+    }
+}
+
+impl<Fl: Scalar> SvgBrush<Fl> for [Fl, ..4] {
+    fn svg_paint(&self, _id: &str, _defs: &mut Vec<~str>) -> (~str, Fl) {
+        (color_to_hex(self), self[3])
+    }
+}
+
+impl<Fl: Scalar> SvgBrush<Fl> for SolidBrush<Fl> {
+    fn svg_paint(&self, _id: &str, _defs: &mut Vec<~str>) -> (~str, Fl) {
+        (color_to_hex(&self.color), self.color[3])
+    }
+
+    fn svg_fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+impl<Fl: Scalar> SvgBrush<Fl> for HatchBrush<Fl> {
+    fn svg_paint(&self, id: &str, defs: &mut Vec<~str>) -> (~str, Fl) {
+        let (o, s): (Fl, Fl) = (zero(), self.spacing);
+        defs.push(format!("{}{}{}</pattern>",
+            pattern_head(id, s, s, self.get_transform()),
+            svg_rect(o, o, s, s, &self.background),
+            svg_rect(o, o, s, self.line_width, &self.color)));
+        (format!("url(\\#{})", id), one())
+    }
+
+    fn svg_fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+impl<Fl: Scalar> SvgBrush<Fl> for CheckerBrush<Fl> {
+    fn svg_paint(&self, id: &str, defs: &mut Vec<~str>) -> (~str, Fl) {
+        let (o, two, s): (Fl, Fl, Fl) = (zero(), Scalar::from_f64(2.0), self.size);
+        defs.push(format!("{}{}{}{}{}</pattern>",
+            pattern_head(id, two * s, two * s, self.get_transform()),
+            svg_rect(o, o, s, s, &self.colors[0]),
+            svg_rect(s, s, s, s, &self.colors[0]),
+            svg_rect(s, o, s, s, &self.colors[1]),
+            svg_rect(o, s, s, s, &self.colors[1])));
+        (format!("url(\\#{})", id), one())
+    }
+
+    fn svg_fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+impl<Fl: Scalar> SvgBrush<Fl> for ImageBrush<Fl> {
+    /// The image is written as one rectangle per visible pixel,
+    /// and always repeats.
+    fn svg_paint(&self, id: &str, defs: &mut Vec<~str>) -> (~str, Fl) {
+        let (o, l): (Fl, Fl) = (zero(), one());
+        let (w, h) = (self.width(), self.height());
+        let mut res = pattern_head(id, Scalar::from_f64(w as f64), Scalar::from_f64(h as f64),
+                                   self.get_transform());
+        for y in range(0u, h) {
+            for x in range(0u, w) {
+                let color = self.get_pixel(x, y);
+                if color[3] == o { continue; }
+                res = res + svg_rect(Scalar::from_f64(x as f64), Scalar::from_f64(y as f64),
+                                     l, l, &color);
+            }
+        }
+        defs.push(res + "</pattern>");
+        (format!("url(\\#{})", id), l)
+    }
+
+    fn svg_fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+}
+
+impl<Fl: Scalar> SvgGradient for ColorGradient<Fl> {
+    fn svg_gradient(&self, id: &str) -> ~str {
+        let spread = match self.spread {
+            PadSpread => "pad",
+            RepeatSpread => "repeat",
+            ReflectSpread => "reflect",
+        };
+        let m = *self.get_transform();
+        let head = |tag: &str, attrs: ~str| format!(
+            "<{} id=\"{}\" gradientUnits=\"userSpaceOnUse\" spreadMethod=\"{}\" \
+            gradientTransform=\"matrix({} {} {} {} {} {})\" {}>",
+            tag, id, spread, m[0], m[3], m[1], m[4], m[2], m[5], attrs);
+        let stop = |offset: Fl, color: &[Fl, ..4]| format!(
+            "<stop offset=\"{}\" stop-color=\"{}\" stop-opacity=\"{}\"/>",
+            offset, color_to_hex(color), color[3]);
+        match self.kind {
+            LinearGradient(l) => {
+                let mut res = head("linearGradient",
+                    format!("x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"", l[0], l[1], l[2], l[3]));
+                for &(offset, ref color) in self.stops.iter() {
+                    res = res + stop(offset, color);
+                }
+                res + "</linearGradient>"
+            }
+            RadialGradient(r) => {
+                let mut res = head("radialGradient",
+                    format!("cx=\"{}\" cy=\"{}\" r=\"{}\"", r[0], r[1], r[2]));
+                for &(offset, ref color) in self.stops.iter() {
+                    res = res + stop(offset, color);
+                }
+                res + "</radialGradient>"
+            }
+            ConicGradient(_) => {
+                // SVG has no conic gradients, so the average color is used.
+                let o: Fl = zero();
+                let n: Fl = Scalar::from_f64(self.stops.len() as f64);
+                let mut color = [o, ..4];
+                for &(_, c) in self.stops.iter() {
+                    for i in range(0u, 4) { color[i] = color[i] + c[i] / n; }
+                }
+                head("linearGradient", ~"") + stop(o, &color) + "</linearGradient>"
+            }
+        }
+    }
+}
+
+/// Writes shapes to an SVG document.
+pub struct SvgShader<Fl=f64> {
+    /// The width of the document.
+    pub width: Fl,
+    /// The height of the document.
+    pub height: Fl,
+    /// How following shapes combine with the shapes below.
+    pub composite: CompositeOp,
+    /// The clip in document coordinates.
+    pub clip: Clip<Fl>,
+    defs: Vec<~str>,
+    clip_ids: Vec<(Vec<Vec<[Fl, ..2]>>, ~str)>,
+    elements: Vec<~str>,
+    paint: ~str,
+    opacity: Fl,
+    fill_rule: FillRule,
+    stroke: Option<~str>,
+    next_id: uint,
+    view: View<Fl>,
+}
+
+impl<Fl: Scalar> SvgShader<Fl> {
+    /// Creates an empty document painting with black fill.
+    pub fn new(width: Fl, height: Fl) -> SvgShader<Fl> {
+        SvgShader {
+            width: width,
+            height: height,
+            composite: SourceOver,
+            clip: Clip::new(),
+            defs: Vec::new(),
+            clip_ids: Vec::new(),
+            elements: Vec::new(),
+            paint: ~"#000000",
+            opacity: one(),
+            fill_rule: NonZero,
+            stroke: None,
+            next_id: 0,
+            view: View::new(),
+        }
+    }
+
+    /// Sets how following shapes are painted.
+    pub fn set_effect<P: SvgPen<Fl>, B: SvgBrush<Fl>, G: SvgGradient>(
+        &mut self,
+        effect: &Effect<P, B, G>
+    ) {
+        match *effect {
+            Stroke(pen) => {
+                let color = pen.svg_color();
+                self.paint = color_to_hex(&color);
+                self.opacity = color[3];
+                self.stroke = Some(pen.svg_stroke_attributes());
+            }
+            Fill(brush) => {
+                let id = self.new_id();
+                let (paint, opacity) = brush.svg_paint(id.as_slice(), &mut self.defs);
+                self.paint = paint;
+                self.opacity = opacity;
+                self.fill_rule = brush.svg_fill_rule();
+                self.stroke = None;
+            }
+            Gradient(gradient) => {
+                let id = self.new_id();
+                self.defs.push(gradient.svg_gradient(id.as_slice()));
+                self.paint = format!("url(\\#{})", id);
+                self.opacity = one();
+                self.fill_rule = NonZero;
+                self.stroke = None;
+            }
+        }
+    }
+
+    /// Strokes the outline of a shape with an advanced pen.
+    ///
+    /// The stroke is written as a filled path,
+    /// such that caps and joins look the same as in other backends.
+    pub fn stroke(&mut self, shape: &Shape<Fl>, pen: &AdvancedPen<Fl>) {
+        let precision: Fl = Scalar::from_f64(0.01);
+        let polygons = stroke_shape(shape, pen, precision * pen.width);
+        let mut d = ~"";
+        for polygon in polygons.iter() {
+            for (i, p) in polygon.iter().enumerate() {
+                d = d + format!("{}{},{} ", if i == 0 { "M" } else { "L" }, p[0], p[1]);
+            }
+            d = d + "Z ";
+        }
+        let element = format!("<path d=\"{}\" fill=\"{}\" fill-opacity=\"{}\"{}/>",
+            d.trim(), color_to_hex(&pen.color), pen.color[3],
+            self.transform() + self.composite_style());
+        let element = self.clip_element(element);
+        self.elements.push(element);
+    }
+
+    /// Writes the document.
+    pub fn write_to<W: Writer>(&self, w: &mut W) -> IoResult<()> {
+        try!(w.write_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+        try!(write!(w, "<svg xmlns=\"http://www.w3.org/2000/svg\" \
+            width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+            self.width, self.height, self.width, self.height));
+        if self.defs.len() > 0 {
+            try!(w.write_str("<defs>\n"));
+            for def in self.defs.iter() {
+                try!(write!(w, "{}\n", *def));
+            }
+            try!(w.write_str("</defs>\n"));
+        }
+        for element in self.elements.iter() {
+            try!(write!(w, "{}\n", *element));
+        }
+        w.write_str("</svg>\n")
+    }
+
+    /// Returns a new unique id for definitions.
+    fn new_id(&mut self) -> ~str {
+        self.next_id += 1;
+        format!("p{}", self.next_id)
+    }
+
+    /// Returns the transform attribute of the view, if any.
+    fn transform(&self) -> ~str {
+        let id: Matrix<Fl> = identity();
+        let mut res = ~"";
+        for m in [self.view.base, self.view.trans].iter() {
+            if *m == id { continue; }
+            res = res + format!("matrix({} {} {} {} {} {})",
+                m[0], m[3], m[1], m[4], m[2], m[5]);
+            res = res + " ";
+        }
+        if res.len() == 0 { res }
+        else { format!(" transform=\"{}\"", res.trim()) }
+    }
+
+    /// Wraps an element in groups clipping it to each region of the clip.
+    fn clip_element(&mut self, element: ~str) -> ~str {
+        let mut res = element;
+        for i in range(0u, self.clip.regions.len()) {
+            let id = self.clip_id(i);
+            res = format!("<g clip-path=\"url(\\#{})\">{}</g>", id, res);
+        }
+        res
+    }
+
+    /// Returns the id of a clip region, writing its definition the first time.
+    fn clip_id(&mut self, i: uint) -> ~str {
+        let region = self.clip.regions.get(i).clone();
+        for &(ref r, ref id) in self.clip_ids.iter() {
+            if *r == region { return id.clone(); }
+        }
+        let id = self.new_id();
+        let mut d = ~"";
+        for contour in region.iter() {
+            for (j, p) in contour.iter().enumerate() {
+                d = d + format!("{}{},{} ", if j == 0 { "M" } else { "L" }, p[0], p[1]);
+            }
+            d = d + "Z ";
+        }
+        self.defs.push(format!("<clipPath id=\"{}\" clipPathUnits=\"userSpaceOnUse\">\
+            <path d=\"{}\" clip-rule=\"nonzero\"/></clipPath>", id, d.trim()));
+        self.clip_ids.push((region, id.clone()));
+        id
+    }
+
+    /// Returns the style attribute of the composite operator, if any.
+    fn composite_style(&self) -> ~str {
+        let mode = match self.composite {
+            Plus => "plus-lighter",
+            Multiply => "multiply",
+            Screen => "screen",
+            Overlay => "overlay",
+            Darken => "darken",
+            Lighten => "lighten",
+            Difference => "difference",
+            _ => return ~"",
+        };
+        format!(" style=\"mix-blend-mode:{}\"", mode)
+    }
+
+    /// Returns the paint attributes for areas.
+    fn area_paint(&self) -> ~str {
+        match self.stroke {
+            None => format!("fill=\"{}\" fill-opacity=\"{}\" fill-rule=\"{}\"",
+                self.paint, self.opacity, match self.fill_rule {
+                    NonZero => "nonzero",
+                    EvenOdd => "evenodd",
+                }),
+            Some(ref attrs) => format!("fill=\"none\" stroke=\"{}\" stroke-opacity=\"{}\" {}",
+                self.paint, self.opacity, *attrs),
+        }
+    }
+
+    /// Returns the paint attributes for lines.
+    fn line_paint(&self) -> ~str {
+        match self.stroke {
+            None => format!("fill=\"none\" stroke=\"{}\" stroke-opacity=\"{}\" stroke-width=\"1\"",
+                self.paint, self.opacity),
+            Some(_) => self.area_paint(),
+        }
+    }
+
+    /// Returns the paint attributes for one pixel shapes.
+    fn pixel_paint(&self) -> ~str {
+        format!("fill=\"{}\" fill-opacity=\"{}\"", self.paint, self.opacity)
+    }
+}
+
+impl<Fl: Scalar> Shader<Fl> for SvgShader<Fl> {
+    fn shade(&mut self, shape: &Shape<Fl>) {
+        let half: Fl = Scalar::from_f64(0.5);
+        let attrs = self.transform() + self.composite_style();
+        let element = match *shape {
+            Pixel(p) => format!("<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"1\" {}{}/>",
+                p[0], p[1], self.pixel_paint(), attrs),
+            PixelHorizontalLine(l) => format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"1\" {}{}/>",
+                l[0], l[1], l[2], self.pixel_paint(), attrs),
+            PixelVerticalLine(l) => format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"1\" height=\"{}\" {}{}/>",
+                l[0], l[1], l[2], self.pixel_paint(), attrs),
+            PixelLine(l) => format!(
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" \
+                stroke=\"{}\" stroke-opacity=\"{}\" stroke-width=\"1\" \
+                vector-effect=\"non-scaling-stroke\"{}/>",
+                l[0], l[1], l[2], l[3], self.paint, self.opacity, attrs),
+            Line(l) => format!("<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" {}{}/>",
+                l[0], l[1], l[2], l[3], self.line_paint(), attrs),
+            Rect(r) => format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}{}/>",
+                r[0], r[1], r[2], r[3], self.area_paint(), attrs),
+            RoundRect(r) => format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\" ry=\"{}\" {}{}/>",
+                r[0], r[1], r[2], r[3], r[4], r[4], self.area_paint(), attrs),
+            Ellipse(e) => format!(
+                "<ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" {}{}/>",
+                e[0] + half * e[2], e[1] + half * e[3], half * e[2], half * e[3],
+                self.area_paint(), attrs),
+            Circle(c) => format!("<circle cx=\"{}\" cy=\"{}\" r=\"{}\" {}{}/>",
+                c[0], c[1], c[2], self.area_paint(), attrs),
+            Triangle(t) => format!("<polygon points=\"{},{} {},{} {},{}\" {}{}/>",
+                t[0], t[1], t[2], t[3], t[4], t[5], self.area_paint(), attrs),
+            Polygon(p) => format!("<polygon points=\"{}\" {}{}/>",
+                points(p), self.area_paint(), attrs),
+            Catmull(p) => format!("<path d=\"{}\" {}{}/>",
+                catmull_path(p), self.area_paint(), attrs),
+            QuadraticBezierBound(p) => format!("<path d=\"{}\" {}{}/>",
+                bound_path(p), self.area_paint(), attrs),
+            QuadraticBezierFree(p) => format!("<path d=\"{}\" {}{}/>",
+                free_path(p), self.area_paint(), attrs),
+        };
+        let element = self.clip_element(element);
+        self.elements.push(element);
+    }
+
+    fn get_view<'a>(&'a self) -> &'a View<Fl> {
+        &self.view
+    }
+
+    fn get_mut_view<'a>(&'a mut self) -> &'a mut View<Fl> {
+        &mut self.view
+    }
+}
+
+impl<Fl: Scalar> ClipShader<Fl> for SvgShader<Fl> {
+    fn get_mut_clip<'a>(&'a mut self) -> &'a mut Clip<Fl> {
+        &mut self.clip
+    }
+}
+
+impl<Fl: Scalar> Backend<Fl> for SvgShader<Fl> {
+    fn draw(
+        &mut self,
+        transform: &Matrix2d<Fl>,
+        color: &Color<Fl>,
+        op: &CompositeOp,
+        clip: &Clip<Fl>,
+        shape: &Shape<Fl>
+    ) {
+        let (base, trans, old_op) = (self.view.base, self.view.trans, self.composite);
+        let (paint, opacity) = (color_to_hex(color), color[3]);
+        let old_paint = mem::replace(&mut self.paint, paint);
+        let old_opacity = mem::replace(&mut self.opacity, opacity);
+        let old_stroke = self.stroke.take();
+        self.view.base = identity();
+        self.view.trans = *transform;
+        self.composite = *op;
+        let old_clip = mem::replace(&mut self.clip, clip.clone());
+        self.shade(shape);
+        self.clip = old_clip;
+        self.view.base = base;
+        self.view.trans = trans;
+        self.composite = old_op;
+        self.paint = old_paint;
+        self.opacity = old_opacity;
+        self.stroke = old_stroke;
+    }
+}
+
+impl<Fl: Scalar, P: SvgPen<Fl>, B: SvgBrush<Fl>, G: SvgGradient>
+EffectShader<P, B, G, Fl> for SvgShader<Fl> {
+    fn shade_effect(&mut self, shape: &Shape<Fl>, effect: &Effect<P, B, G>) {
+        match *effect {
+            Stroke(pen) => match pen.svg_outline() {
+                Some(pen) => { self.stroke(shape, pen); return; }
+                None => {}
+            },
+            _ => {}
+        }
+        self.set_effect(effect);
+        self.shade(shape);
+    }
+}
+
+/// Converts a color into a hex string, ignoring alpha.
+fn color_to_hex<Fl: Scalar>(color: &[Fl, ..4]) -> ~str {
+    let byte = |v: Fl| (v.max(zero()).min(one()).as_f64() * 255.0 + 0.5).floor() as u8;
+    format!("\\#{:02x}{:02x}{:02x}", byte(color[0]), byte(color[1]), byte(color[2]))
+}
+
+/// Returns the dash attributes with a leading space,
+/// or nothing for a solid line.
+fn dash_attributes<Fl: Scalar>(dash: &Option<Dash<Fl>>) -> ~str {
+    match *dash {
+        Some(ref dash) if !dash.is_solid() => {
+            let array: Vec<~str> = dash.array.iter().map(|x| format!("{}", *x)).collect();
+            format!(" stroke-dasharray=\"{}\" stroke-dashoffset=\"{}\"",
+                array.as_slice().connect(" "), dash.offset)
+        }
+        _ => ~"",
+    }
+}
+
+/// Returns the start of a pattern element in user space.
+fn pattern_head<Fl: Scalar>(id: &str, w: Fl, h: Fl, m: &Matrix<Fl>) -> ~str {
+    format!("<pattern id=\"{}\" patternUnits=\"userSpaceOnUse\" width=\"{}\" height=\"{}\" \
+        patternTransform=\"matrix({} {} {} {} {} {})\">",
+        id, w, h, m[0], m[3], m[1], m[4], m[2], m[5])
+}
+
+/// Returns a filled rectangle, or nothing if the color is transparent.
+fn svg_rect<Fl: Scalar>(x: Fl, y: Fl, w: Fl, h: Fl, color: &[Fl, ..4]) -> ~str {
+    if color[3] == zero() { return ~""; }
+    format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" fill-opacity=\"{}\"/>",
+        x, y, w, h, color_to_hex(color), color[3])
+}
+
+/// Returns a list of points as used by the `points` attribute.
+fn points<Fl: Scalar>(p: &[Fl]) -> ~str {
+    let pairs: Vec<~str> = range(0u, p.len() / 2)
+        .map(|i| format!("{},{}", p[i * 2], p[i * 2 + 1])).collect();
+    pairs.as_slice().connect(" ")
+}
+
+/// Returns path data for a closed Catmull-Rom spline.
+///
+/// Each segment is converted into a cubic Bezier curve.
+fn catmull_path<Fl: Scalar>(p: &[Fl]) -> ~str {
+    let six: Fl = Scalar::from_f64(6.0);
+    let n = p.len() / 2;
+    if n == 0 { return ~""; }
+    let pt = |i: uint| [p[(i % n) * 2], p[(i % n) * 2 + 1]];
+    let mut res = format!("M{},{}", p[0], p[1]);
+    for i in range(0u, n) {
+        let (p0, p1, p2, p3) = (pt(i + n - 1), pt(i), pt(i + 1), pt(i + 2));
+        res = res + format!(" C{},{} {},{} {},{}",
+            p1[0] + (p2[0] - p0[0]) / six, p1[1] + (p2[1] - p0[1]) / six,
+            p2[0] - (p3[0] - p1[0]) / six, p2[1] - (p3[1] - p1[1]) / six,
+            p2[0], p2[1]);
+    }
+    res + " Z"
+}
+
+/// Returns path data for a closed quadratic curve
+/// passing through the middle of each line.
+fn bound_path<Fl: Scalar>(p: &[Fl]) -> ~str {
+    let half: Fl = Scalar::from_f64(0.5);
+    let n = p.len() / 2;
+    if n == 0 { return ~""; }
+    let mid = |i: uint| [half * (p[(i % n) * 2] + p[((i + 1) % n) * 2]),
+                         half * (p[(i % n) * 2 + 1] + p[((i + 1) % n) * 2 + 1])];
+    let start = mid(0);
+    let mut res = format!("M{},{}", start[0], start[1]);
+    for i in range(0u, n) {
+        let c = (i + 1) % n;
+        let end = mid(i + 1);
+        res = res + format!(" Q{},{} {},{}", p[c * 2], p[c * 2 + 1], end[0], end[1]);
+    }
+    res + " Z"
+}
+
+/// Returns path data for a chain of Bezier segments with free tangents.
+fn free_path<Fl: Scalar>(p: &[Fl]) -> ~str {
+    if p.len() < 2 { return ~""; }
+    let mut res = format!("M{},{}", p[0], p[1]);
+    let mut i = 2u;
+    while i + 6 <= p.len() {
+        res = res + format!(" C{},{} {},{} {},{}",
+            p[i], p[i + 1], p[i + 2], p[i + 3], p[i + 4], p[i + 5]);
+        i += 6;
+    }
+    res
+}
+
+
+#[test]
+fn test_svg() {
+    use std::io::MemWriter;
+    use std::str;
+    use graphics::advanced_pen::ArrowLineCap;
+
+    let mut svg: SvgShader = SvgShader::new(100.0, 50.0);
+    let clip: Clip = Clip::new();
+    svg.clip = clip.intersect_rect(&identity(), [0.0, 0.0, 50.0, 40.0]);
+    svg.get_mut_view().trans(5.0, 0.0);
+    let brush: SolidBrush = SolidBrush::new([1.0, 0.0, 0.0, 0.5]);
+    let fill: Effect<AdvancedPen, SolidBrush, ColorGradient> = Fill(&brush);
+    svg.shade_effect(&Rect(&[10.0, 5.0, 20.0, 10.0]), &fill);
+    let pen: AdvancedPen = AdvancedPen {
+        width: 2.0,
+        color: [0.0, 0.0, 1.0, 1.0],
+        line_cap_start: RoundLineCap,
+        line_cap_end: RoundLineCap,
+        line_join: RoundLineJoin,
+        dash: None,
+    };
+    let stroke: Effect<AdvancedPen, SolidBrush, ColorGradient> = Stroke(&pen);
+    svg.shade_effect(&Circle(&[50.0, 25.0, 10.0]), &stroke);
+    let mut gradient: ColorGradient = ColorGradient::linear(0.0, 0.0, 10.0, 0.0);
+    gradient.add_stop(0.25, [1.0, 1.0, 0.0, 1.0]);
+    let gradient: Effect<AdvancedPen, SolidBrush, ColorGradient> = Gradient(&gradient);
+    svg.shade_effect(&Polygon(&[1.0, 2.0, 3.0, 4.0, 5.0, 1.0]), &gradient);
+    let checker: CheckerBrush = CheckerBrush::new([1.0, 1.0, 1.0, 1.0], [0.0, 0.0, 0.0, 1.0], 2.0);
+    let checker: Effect<AdvancedPen, CheckerBrush, ColorGradient> = Fill(&checker);
+    svg.shade_effect(&Catmull(&[1.0, 2.0, 3.0, 4.0, 5.0, 1.0]), &checker);
+    let arrow = AdvancedPen { color: [0.0, 0.0, 0.0, 1.0], line_cap_end: ArrowLineCap,
+        dash: None, ..pen };
+    let stroke: Effect<AdvancedPen, SolidBrush, ColorGradient> = Stroke(&arrow);
+    svg.shade_effect(&Line(&[0.0, 0.0, 10.0, 10.0]), &stroke);
+
+    let mut w = MemWriter::new();
+    svg.write_to(&mut w).unwrap();
+    let text = str::from_utf8(w.get_ref()).unwrap();
+    assert!(text.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg "));
+    assert!(text.contains("<defs>\n<clipPath id=\"p2\" clipPathUnits=\"userSpaceOnUse\">\
+        <path d=\"M0,0 L50,0 L50,40 L0,40 Z\" clip-rule=\"nonzero\"/></clipPath>\n"));
+    assert!(text.contains("<g clip-path=\"url(#p2)\"><rect x=\"10\" y=\"5\" width=\"20\" \
+        height=\"10\" fill=\"#ff0000\" fill-opacity=\"0.5\" fill-rule=\"nonzero\" \
+        transform=\"matrix(1 0 0 1 5 0)\"/></g>\n"));
+    assert!(text.contains("<circle cx=\"50\" cy=\"25\" r=\"10\" fill=\"none\" \
+        stroke=\"#0000ff\" stroke-opacity=\"1\" stroke-width=\"2\" \
+        stroke-linecap=\"round\" stroke-linejoin=\"round\""));
+    assert!(text.contains("<linearGradient id=\"p3\" gradientUnits=\"userSpaceOnUse\" \
+        spreadMethod=\"pad\" gradientTransform=\"matrix(1 0 0 1 0 0)\" \
+        x1=\"0\" y1=\"0\" x2=\"10\" y2=\"0\">\
+        <stop offset=\"0.25\" stop-color=\"#ffff00\" stop-opacity=\"1\"/></linearGradient>\n"));
+    assert!(text.contains("<polygon points=\"1,2 3,4 5,1\" fill=\"url(#p3)\""));
+    assert!(text.contains("<pattern id=\"p4\" patternUnits=\"userSpaceOnUse\" \
+        width=\"4\" height=\"4\" patternTransform=\"matrix(1 0 0 1 0 0)\">"));
+    assert!(text.contains("<path d=\"M1,2 C"));
+    assert!(text.contains("fill=\"url(#p4)\""));
+    // The arrow is filled instead of stroked.
+    assert!(!text.contains("<line"));
+    assert!(text.contains("Z\" fill=\"#000000\" fill-opacity=\"1\" \
+        transform=\"matrix(1 0 0 1 5 0)\"/></g>\n</svg>\n"));
+    assert_eq!(text.split_str("<g clip-path=").count() - 1, 5);
+}
@@ -0,0 +1,237 @@
+
+//! Tile based rendering of display lists on multiple threads.
+//!
+//! The image is split into square tiles.
+//! The records of a display list are binned into the tiles
+//! touched by their bounding boxes, and each thread rasterizes
+//! whole tiles into their own buffers.
+//! A tile rasterizes shapes the same way as the whole image,
+//! so the result is identical pixel for pixel to rendering on one thread.
+
+use std::cmp::{min, max};
+use std::num::{zero, one};
+use sync::Arc;
+
+use graphics::Shader;
+use graphics::brush::{Brush, SolidBrush};
+use graphics::clip::Clip;
+use graphics::recorder::DisplayList;
+use graphics::raster::RasterBuffer;
+use graphics::bounds::transformed_bounding_box;
+use graphics::pixel::{PixelMode, Bresenham};
+use graphics::composite::{CompositeOp, SourceOver};
+use graphics::scalar::Scalar;
+use graphics_new::multiply;
+
+/// Renders display lists into raster buffers on multiple threads.
+///
+/// All records are drawn with the same paint and clip,
+/// like replaying the display list into a raster buffer.
+#[deriving(Clone, Show)]
+pub struct TileRenderer<B=SolidBrush, Fl=f64> {
+    /// The width and height of tiles in pixels.
+    pub tile_size: uint,
+    /// The number of threads.
+    pub threads: uint,
+    /// The color used when drawing shapes.
+    pub color: [Fl, ..4],
+    /// The width of lines in shape coordinates.
+    pub line_width: Fl,
+    /// A brush used instead of the color when filling shapes.
+    pub brush: Option<B>,
+    /// How the one pixel lines cover pixels.
+    pub pixel_mode: PixelMode,
+    /// How colors combine with existing pixels.
+    pub composite: CompositeOp,
+    /// Whether colors are combined in linear RGB instead of sRGB.
+    pub gamma_correct: bool,
+    /// The clip in pixel coordinates.
+    pub clip: Clip<Fl>,
+}
+
+impl<B: Brush<Fl> + Clone + Send, Fl: Scalar> TileRenderer<B, Fl> {
+    /// Creates a renderer drawing black with tiles of 64 pixels on 4 threads.
+    pub fn new() -> TileRenderer<B, Fl> {
+        let (o, l): (Fl, Fl) = (zero(), one());
+        TileRenderer {
+            tile_size: 64,
+            threads: 4,
+            color: [o, o, o, l],
+            line_width: l,
+            brush: None,
+            pixel_mode: Bresenham,
+            composite: SourceOver,
+            gamma_correct: false,
+            clip: Clip::new(),
+        }
+    }
+
+    /// Sets the paint and clip of a raster buffer.
+    pub fn setup(&self, buffer: &mut RasterBuffer<Fl>) {
+        buffer.color = self.color;
+        buffer.line_width = self.line_width;
+        buffer.brush = self.brush.clone().map(|brush| ~brush as ~Brush<Fl>);
+        buffer.pixel_mode = self.pixel_mode;
+        buffer.composite = self.composite;
+        buffer.gamma_correct = self.gamma_correct;
+        buffer.clip = self.clip.clone();
+    }
+
+    /// Renders a display list into a transparent image.
+    pub fn render(&self, list: &DisplayList<Fl>, width: uint, height: uint) -> RasterBuffer<Fl> {
+        let mut res = RasterBuffer::new(width, height);
+        let size = max(self.tile_size, 1);
+        let columns = (width + size - 1) / size;
+        let rows = (height + size - 1) / size;
+        let tiles = columns * rows;
+        if tiles == 0 { return res; }
+
+        let bins = Arc::new(bin(list, self.line_width, size, columns, rows));
+        let list = Arc::new(list.clone());
+        let threads = max(self.threads, 1);
+        let (tx, rx) = channel();
+        for first in range(0u, threads) {
+            let (tx, bins, list) = (tx.clone(), bins.clone(), list.clone());
+            let renderer = self.clone();
+            spawn(proc() {
+                let mut i = first;
+                while i < tiles {
+                    let (x, y) = (i % columns * size, i / columns * size);
+                    let mut tile = RasterBuffer::new_tile(width, height, x, y,
+                        min(size, width - x), min(size, height - y));
+                    renderer.setup(&mut tile);
+                    draw_records(&mut tile, list.get(), bins.get().get(i).as_slice());
+                    tx.send(tile);
+                    i += threads;
+                }
+            });
+        }
+
+        {
+            let pixels = res.pixels.as_mut_slice();
+            for _ in range(0u, tiles) {
+                let tile = rx.recv();
+                let origin = tile.origin();
+                let row_len = tile.width * 4;
+                for row in range(0u, tile.height) {
+                    let start = ((origin[1] + row) * width + origin[0]) * 4;
+                    let src = tile.pixels.as_slice().slice(row * row_len, (row + 1) * row_len);
+                    for (i, &v) in src.iter().enumerate() {
+                        pixels[start + i] = v;
+                    }
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Returns the indices of the records touching each tile,
+/// with tiles stored row by row.
+///
+/// Bounding boxes are grown by half the line width and two pixels,
+/// to include lines, anti-aliasing and pixels touched by rounding.
+pub fn bin<Fl: Scalar>(
+    list: &DisplayList<Fl>,
+    line_width: Fl,
+    tile_size: uint,
+    columns: uint,
+    rows: uint
+) -> Vec<Vec<uint>> {
+    let (o, half, two): (Fl, Fl, Fl) = (zero(), Scalar::from_f64(0.5), Scalar::from_f64(2.0));
+    let (columns_fl, rows_fl): (Fl, Fl) =
+        (Scalar::from_f64(columns as f64), Scalar::from_f64(rows as f64));
+    let mut bins = Vec::from_fn(columns * rows, |_| Vec::new());
+    let size: Fl = Scalar::from_f64(tile_size as f64);
+    for (i, record) in list.records.iter().enumerate() {
+        let m = multiply(&record.base, &record.trans);
+        let b = match transformed_bounding_box(&record.shape.as_shape(), &m) {
+            None => continue,
+            Some(b) => b,
+        };
+        let norm = (m[0].abs() + m[1].abs()).max(m[3].abs() + m[4].abs());
+        let pad = half * line_width * norm + two;
+        let (x1, y1) = ((b[0] - pad) / size, (b[1] - pad) / size);
+        let (x2, y2) = ((b[2] + pad) / size, (b[3] + pad) / size);
+        if !(x2 >= o && y2 >= o && x1 < columns_fl && y1 < rows_fl) { continue; }
+        let start_x = x1.floor().max(o).as_f64() as uint;
+        let start_y = y1.floor().max(o).as_f64() as uint;
+        let end_x = min(x2.floor().as_f64() as uint + 1, columns);
+        let end_y = min(y2.floor().as_f64() as uint + 1, rows);
+        for y in range(start_y, end_y) {
+            for x in range(start_x, end_x) {
+                bins.get_mut(y * columns + x).push(i);
+            }
+        }
+    }
+    bins
+}
+
+/// Draws records of a display list with their views.
+fn draw_records<Fl: Scalar>(
+    buffer: &mut RasterBuffer<Fl>,
+    list: &DisplayList<Fl>,
+    indices: &[uint]
+) {
+    for &i in indices.iter() {
+        let record = list.records.get(i);
+        {
+            let view = buffer.get_mut_view();
+            view.base = record.base;
+            view.trans = record.trans;
+        }
+        buffer.shade(&record.shape.as_shape());
+    }
+}
+
+#[test]
+fn test_tile() {
+    use graphics::{Rect, Ellipse, Line, PixelLine, Polygon};
+    use graphics::recorder::Recorder;
+    use graphics::pixel::XiaolinWu;
+    use graphics::composite::Multiply;
+    use graphics::brush::CheckerBrush;
+    use graphics_new::identity;
+
+    let mut recorder: Recorder = Recorder::new();
+    recorder.shade(&Rect(&[0.5, 1.25, 9.0, 3.0]));
+    recorder.get_mut_view().rot_deg(30.0);
+    recorder.shade(&Ellipse(&[4.0, 0.0, 11.0, 6.0]));
+    recorder.shade(&Line(&[0.0, 0.0, 17.0, 3.0]));
+    recorder.get_mut_view().reset();
+    recorder.shade(&PixelLine(&[1.0, 13.0, 18.0, 2.0]));
+    recorder.shade(&Polygon(&[3.0, 3.0, 15.0, 5.0, 6.0, 12.0, 9.0, 1.0]));
+
+    let mut renderer: TileRenderer = TileRenderer::new();
+    renderer.color = [0.2, 0.6, 1.0, 0.5];
+    renderer.line_width = 2.0;
+    renderer.pixel_mode = XiaolinWu;
+    renderer.composite = Multiply;
+    renderer.tile_size = 4;
+    renderer.threads = 3;
+    let tiled = renderer.render(&recorder.list, 19, 14);
+
+    let mut single: RasterBuffer = RasterBuffer::new(19, 14);
+    renderer.setup(&mut single);
+    recorder.list.replay(&mut single);
+    assert!(single.pixels.iter().any(|&v| v != 0));
+    assert!(tiled.pixels == single.pixels);
+
+    // Gamma correct blending, a clip and a brush give the same pixels too.
+    let mut renderer: TileRenderer<CheckerBrush> = TileRenderer::new();
+    renderer.color = [0.2, 0.6, 1.0, 0.5];
+    renderer.brush = Some(CheckerBrush::new([1.0, 0.0, 0.0, 0.5], [0.0, 0.0, 1.0, 0.75], 3.0));
+    renderer.gamma_correct = true;
+    let clip: Clip = Clip::new();
+    renderer.clip = clip.intersect_rect(&identity(), [2.0, 1.0, 13.0, 10.0]);
+    renderer.tile_size = 5;
+    renderer.threads = 2;
+    let tiled = renderer.render(&recorder.list, 19, 14);
+
+    let mut single: RasterBuffer = RasterBuffer::new(19, 14);
+    renderer.setup(&mut single);
+    recorder.list.replay(&mut single);
+    assert!(single.pixels.iter().any(|&v| v != 0));
+    assert_eq!(single.get_pixel(0, 0), [0, 0, 0, 0]);
+    assert!(tiled.pixels == single.pixels);
+}